@@ -24,6 +24,7 @@ pub struct MultiHeadAttentionConfig {
     pub d_in: usize,
     pub d_out: usize,
     pub context_length: usize,
+    /// 注意力权重的丢弃概率。
     pub dropout: f64,
     pub nheads: usize,
     #[config(default = false)]
@@ -34,6 +35,12 @@ impl<B: Backend> MultiHeadAttention<B> {
     /// 输入的维度为 (batch-size, num-tokens, embedding-dim)。
     /// 输出的维度为 (batch-size, num-tokens, d-out)。
     pub fn forward(&self, x: Tensor<B, 3>) -> Tensor<B, 3> {
+        self.forward_with_padding(x, None)
+    }
+
+    /// 和 [`MultiHeadAttention::forward`] 相同，但 `padding_mask`（维度为 (batch-size, num-tokens)）为 true 的
+    /// 填充 token 不会被其他 token 关注。填充 token 只关注自身，以免其注意力权重全为 -inf，softmax 后得到 NaN。
+    pub fn forward_with_padding(&self, x: Tensor<B, 3>, padding_mask: Option<Tensor<B, 2, Bool>>) -> Tensor<B, 3> {
         let (b, ntokens) = {
             let s = x.shape().dims;
             (s[0], s[1])
@@ -57,7 +64,18 @@ impl<B: Backend> MultiHeadAttention<B> {
         let dk = *keys.dims().last().expect("get k's last dim") as f32;
 
         let attn_scores = queries.matmul(keys.transpose());
-        let mask = self.mask.clone().bool().slice(s![.., .., ..ntokens, ..ntokens]);
+        let mut mask = self.mask.clone().bool().slice(s![.., .., ..ntokens, ..ntokens]);
+        if let Some(padding_mask) = padding_mask {
+            let shape = [b, 1, ntokens, ntokens];
+            let not_self = Tensor::<B, 2, Bool>::diag_mask([ntokens, ntokens], 0, &mask.device())
+                .unsqueeze::<4>()
+                .expand(shape);
+            let padding_mask = padding_mask
+                .reshape([b, 1, 1, ntokens])
+                .expand(shape)
+                .bool_and(not_self);
+            mask = mask.expand(shape).bool_or(padding_mask);
+        }
 
         let attn_scores = attn_scores.mask_fill(mask, f32::NEG_INFINITY);
 
@@ -0,0 +1,70 @@
+//! 对比开启/关闭激活值重计算时，GPT 模型单步前向 + 反向传播的内存峰值和耗时。
+//!
+//! 内存峰值取自进程的 VmHWM（仅支持 Linux），所以每种策略需要单独运行一次：
+//! ```bash
+//! cargo run --release --example checkpointing -- none
+//! cargo run --release --example checkpointing -- balanced
+//! ```
+use std::fs;
+use std::time::Instant;
+
+use burn::backend::{Autodiff, NdArray};
+use burn::prelude::*;
+use burn::tensor::Distribution;
+use chapter04::checkpoint::{BalancedCheckpointing, CheckpointStrategy, NoCheckpointing};
+use chapter04::{Config, GPT_124M};
+
+const BATCH_SIZE: usize = 2;
+
+fn main() {
+    let strategy = std::env::args().nth(1).unwrap_or_else(|| "none".to_owned());
+
+    let c = GPT_124M.with_context_length(256);
+
+    match strategy.as_str() {
+        "none" => run::<NoCheckpointing>(&c),
+        "balanced" => run::<BalancedCheckpointing>(&c),
+        unknown => panic!("unknown strategy '{unknown}', expect 'none' or 'balanced'"),
+    }
+}
+
+fn run<C: CheckpointStrategy>(c: &Config) {
+    type B<C> = Autodiff<NdArray<f32>, C>;
+    let device = &<B<C> as Backend>::Device::default();
+
+    B::<C>::seed(123);
+    let model = c.init::<B<C>>(device);
+
+    let baseline = peak_rss_kib();
+    let start = Instant::now();
+
+    let idx = Tensor::<B<C>, 2, Int>::random(
+        [BATCH_SIZE, c.context_length],
+        Distribution::Uniform(0.0, c.vocab_size as f64),
+        device,
+    );
+    let loss = model.forward(idx).powi_scalar(2).mean();
+    let grads = loss.backward();
+
+    let elapsed = start.elapsed();
+    let grad = model.tok_emb.weight.grad(&grads).expect("miss tok_emb grad");
+    assert_eq!([c.vocab_size, c.emb_dim], grad.dims());
+
+    let peak = peak_rss_kib();
+    println!(
+        "strategy: {}, peak RSS: {:.1} MiB (+{:.1} MiB for forward & backward), elapsed: {elapsed:?}",
+        std::any::type_name::<C>().rsplit("::").next().unwrap_or_default(),
+        peak as f64 / 1024.0,
+        (peak - baseline) as f64 / 1024.0,
+    );
+}
+
+/// 读取进程的内存峰值（单位为 KiB）。
+fn peak_rss_kib() -> u64 {
+    let status = fs::read_to_string("/proc/self/status").expect("read /proc/self/status");
+    status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmHWM:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse().ok())
+        .expect("parse VmHWM")
+}
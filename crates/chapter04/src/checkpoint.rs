@@ -0,0 +1,14 @@
+//! 激活值重计算（activation checkpointing）。
+//!
+//! burn 的 autodiff 后端通过 [`CheckpointStrategy`] 决定反向传播所需的激活值是保存下来，还是在反向传播时重新计算。
+//! - [`NoCheckpointing`]（默认）：保存所有激活值；
+//! - [`BalancedCheckpointing`]：只保存矩阵乘等计算密集型算子的输出，GELU、LayerNorm、softmax、dropout
+//!   等内存密集型算子的输出在反向传播时重新计算。
+//!
+//! [`TransformerBlock`](crate::TransformerBlock) 的激活值绝大部分来自后者，所以开启后每个块在训练时占用的内存会明显下降，
+//! 代价是反向传播变慢。策略是后端类型的一部分，训练程序需要在选择后端类型时决定是否开启，例如
+//! `Autodiff<LibTorch, BalancedCheckpointing>` 或者 [`Checkpointed<LibTorch>`](Checkpointed)。
+pub use burn::backend::autodiff::checkpoint::strategy::{BalancedCheckpointing, CheckpointStrategy, NoCheckpointing};
+
+/// 开启激活值重计算的 autodiff 后端。
+pub type Checkpointed<B> = burn::backend::Autodiff<B, BalancedCheckpointing>;
@@ -1,6 +1,13 @@
+use std::fmt;
+use std::str::FromStr;
 use std::sync::LazyLock;
 
+use crate::InitScheme;
+
 /// 默认值采用 GPT-124M 的配置。
+///
+/// burn 的 `Config` 反序列化时不会填充字段的默认值，只有 `Option` 字段允许缺失，所以后来新增的字段均为 `Option`，
+/// 以便加载旧的配置文件。
 #[derive(burn::prelude::Config, Copy, Debug)]
 pub struct Config {
     #[config(default = 50257)]
@@ -13,10 +20,139 @@ pub struct Config {
     pub nheads: usize,
     #[config(default = 12)]
     pub nlayers: usize,
+    /// 各 dropout 层的默认丢弃概率，未单独设置的 `drop_*_rate` 都使用这个值。
     #[config(default = 0.1)]
     pub drop_rate: f64,
+    /// 嵌入层输出（`drop_emb`）的丢弃概率。
+    pub drop_emb_rate: Option<f64>,
+    /// 注意力权重的丢弃概率。
+    pub drop_attn_rate: Option<f64>,
+    /// 残差分支输出（`drop_shortcut`）的丢弃概率。
+    pub drop_shortcut_rate: Option<f64>,
     #[config(default = false)]
     pub qkv_bias: bool,
+    /// 参数的初始化方案，`None` 表示 [`InitScheme::BurnDefault`]。
+    pub init: Option<InitScheme>,
+}
+
+/// 预设的模型规模。除 `gpt2-tiny` 外，其余配置和 OpenAI 发布的 GPT-2 模型一致，可直接加载预训练参数。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// 用于调试和测试的小模型，没有对应的预训练参数。
+    Gpt2Tiny,
+    /// GPT-2 124M
+    Gpt2Small,
+    /// GPT-2 355M
+    Gpt2Medium,
+    /// GPT-2 774M
+    Gpt2Large,
+    /// GPT-2 1558M
+    Gpt2Xl,
 }
 
 pub static GPT_124M: LazyLock<Config> = LazyLock::new(Config::new);
+
+impl Config {
+    pub fn init_scheme(&self) -> InitScheme {
+        self.init.unwrap_or(InitScheme::BurnDefault)
+    }
+
+    pub fn drop_emb_rate(&self) -> f64 {
+        self.drop_emb_rate.unwrap_or(self.drop_rate)
+    }
+
+    pub fn drop_attn_rate(&self) -> f64 {
+        self.drop_attn_rate.unwrap_or(self.drop_rate)
+    }
+
+    pub fn drop_shortcut_rate(&self) -> f64 {
+        self.drop_shortcut_rate.unwrap_or(self.drop_rate)
+    }
+
+    /// 按名称（例如 `gpt2-medium`）查找预设配置，名称列表参见 [`Preset::ALL`]。
+    pub fn preset(name: &str) -> anyhow::Result<Self> {
+        name.parse::<Preset>().map(|p| p.config())
+    }
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [
+        Preset::Gpt2Tiny,
+        Preset::Gpt2Small,
+        Preset::Gpt2Medium,
+        Preset::Gpt2Large,
+        Preset::Gpt2Xl,
+    ];
+
+    pub fn config(self) -> Config {
+        let (emb_dim, nheads, nlayers) = match self {
+            Self::Gpt2Tiny => (64, 4, 2),
+            Self::Gpt2Small => (768, 12, 12),
+            Self::Gpt2Medium => (1024, 16, 24),
+            Self::Gpt2Large => (1280, 20, 36),
+            Self::Gpt2Xl => (1600, 25, 48),
+        };
+
+        let context_length = match self {
+            Self::Gpt2Tiny => 64,
+            _ => 1024,
+        };
+
+        Config {
+            vocab_size: 50257,
+            context_length,
+            emb_dim,
+            nheads,
+            nlayers,
+            drop_rate: 0.1,
+            drop_emb_rate: None,
+            drop_attn_rate: None,
+            drop_shortcut_rate: None,
+            // GPT-2 的 QKV 线性层带有偏置
+            qkv_bias: true,
+            init: Some(InitScheme::Gpt2),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gpt2Tiny => "gpt2-tiny",
+            Self::Gpt2Small => "gpt2-small",
+            Self::Gpt2Medium => "gpt2-medium",
+            Self::Gpt2Large => "gpt2-large",
+            Self::Gpt2Xl => "gpt2-xl",
+        }
+    }
+
+    /// OpenAI 发布的模型目录名（例如 `355M`）。`gpt2-tiny` 没有预训练参数，返回 `None`。
+    pub fn size(self) -> Option<&'static str> {
+        match self {
+            Self::Gpt2Tiny => None,
+            Self::Gpt2Small => Some("124M"),
+            Self::Gpt2Medium => Some("355M"),
+            Self::Gpt2Large => Some("774M"),
+            Self::Gpt2Xl => Some("1558M"),
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// 除了名称，也接受模型目录名（例如 `355M`，不区分大小写）。
+impl FromStr for Preset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|p| p.name() == s || p.size().is_some_and(|v| v.eq_ignore_ascii_case(s)))
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|p| p.name()).collect();
+                anyhow::anyhow!("unknown preset '{s}', expect one of {names:?}")
+            })
+    }
+}
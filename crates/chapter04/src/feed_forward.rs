@@ -4,7 +4,7 @@ use burn::nn::{Linear, LinearConfig};
 use burn::prelude::Backend;
 use burn::tensor::Tensor;
 
-use crate::Gelu;
+use crate::{Gelu, InitScheme};
 
 #[derive(Debug, Module)]
 pub struct FeedForward<B: Backend> {
@@ -17,6 +17,12 @@ pub struct FeedForward<B: Backend> {
 pub struct FeedForwardConfig {
     /// 输入的维度。
     pub d_model: usize,
+    /// linear1 的初始化方案。
+    #[config(default = "InitScheme::BurnDefault")]
+    pub init: InitScheme,
+    /// linear2 的初始化方案。它是残差分支的输出投影，GPT-2 会缩小其初始化的标准差。
+    #[config(default = "InitScheme::BurnDefault")]
+    pub proj_init: InitScheme,
 }
 
 impl<B: Backend> FeedForward<B> {
@@ -31,9 +37,13 @@ impl<B: Backend> FeedForward<B> {
 
 impl FeedForwardConfig {
     pub fn init<B: Backend>(&self, device: &B::Device) -> FeedForward<B> {
-        let linear1 = LinearConfig::new(self.d_model, 4 * self.d_model).init(device);
+        let linear1 = self
+            .init
+            .init_linear(LinearConfig::new(self.d_model, 4 * self.d_model), device);
         let gelu = Gelu;
-        let linear2 = LinearConfig::new(4 * self.d_model, self.d_model).init(device);
+        let linear2 = self
+            .proj_init
+            .init_linear(LinearConfig::new(4 * self.d_model, self.d_model), device);
 
         FeedForward { linear1, gelu, linear2 }
     }
@@ -0,0 +1,58 @@
+use burn::nn::{Embedding, EmbeddingConfig, Initializer, Linear, LinearConfig};
+use burn::prelude::Backend;
+
+/// 模型参数的初始化方案，作用于词嵌入、位置嵌入、注意力层、前馈网络和输出头。
+/// LayerNorm 总是初始化为 scale=1、shift=0。
+#[derive(burn::config::Config, Copy, Debug, PartialEq)]
+pub enum InitScheme {
+    /// 使用 burn 各模块的默认初始化器。
+    BurnDefault,
+    /// GPT-2 的方案：权重服从 N(0, 0.02²)，偏置为 0。残差分支的输出投影（注意力层的 out_proj 和前馈网络的 linear2）
+    /// 的标准差额外乘以 1/√(2·nlayers)，参见 [`InitScheme::residual`]。
+    Gpt2,
+    /// 和 `Gpt2` 相同，但权重的标准差为 `std`。
+    Normal { std: f64 },
+}
+
+impl InitScheme {
+    /// 权重的标准差，`None` 表示使用 burn 的默认初始化器。
+    pub fn std(self) -> Option<f64> {
+        match self {
+            Self::BurnDefault => None,
+            Self::Gpt2 => Some(0.02),
+            Self::Normal { std } => Some(std),
+        }
+    }
+
+    /// 残差分支输出投影使用的方案。GPT-2 每个 transformer 块有 2 个残差分支，按 1/√(2·nlayers) 缩小标准差，
+    /// 避免残差流的方差随层数累积。
+    pub fn residual(self, nlayers: usize) -> Self {
+        match self.std() {
+            None => self,
+            Some(std) => Self::Normal {
+                std: std / ((2 * nlayers) as f64).sqrt(),
+            },
+        }
+    }
+
+    pub fn init_embedding<B: Backend>(self, c: EmbeddingConfig, device: &B::Device) -> Embedding<B> {
+        match self.std() {
+            None => c.init(device),
+            Some(std) => c.with_initializer(Initializer::Normal { mean: 0.0, std }).init(device),
+        }
+    }
+
+    pub fn init_linear<B: Backend>(self, c: LinearConfig, device: &B::Device) -> Linear<B> {
+        let Some(std) = self.std() else {
+            return c.init(device);
+        };
+
+        let d_output = c.d_output;
+        let mut out = c.with_initializer(Initializer::Normal { mean: 0.0, std }).init(device);
+        if out.bias.is_some() {
+            out.bias = Some(Initializer::Zeros.init([d_output], device));
+        }
+
+        out
+    }
+}
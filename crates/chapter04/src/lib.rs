@@ -1,15 +1,18 @@
 mod config;
 mod feed_forward;
 mod gelu;
+mod init;
 mod model;
 mod norm;
 mod transformer;
 
+pub mod checkpoint;
 pub mod utils;
 
 pub use config::*;
 pub use feed_forward::*;
 pub use gelu::*;
+pub use init::*;
 pub use model::*;
 pub use norm::*;
 pub use transformer::*;
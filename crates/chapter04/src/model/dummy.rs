@@ -39,7 +39,9 @@ impl<B: Backend> DummyGptModel<B> {
     pub fn new(c: &Config, d: &Device<B>) -> Self {
         let tok_emb = EmbeddingConfig::new(c.vocab_size, c.emb_dim).init(d);
         let pos_emb = EmbeddingConfig::new(c.context_length, c.emb_dim).init(d);
-        let drop_emb = Dropout { prob: c.drop_rate };
+        let drop_emb = Dropout {
+            prob: c.drop_emb_rate(),
+        };
 
         let trf_blocks: Vec<_> = (0..c.nlayers).map(|_| DummyTransformerBlock::new()).collect();
         let final_norm = DummyLayerNorm::new(c.emb_dim, None);
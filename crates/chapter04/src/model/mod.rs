@@ -20,44 +20,131 @@ pub struct GptModel<B: Backend> {
     pub out_head: Linear<B>,
 }
 
+/// 指定 [`GptModel::forward_with_outputs`] 除 logits 外需要额外返回的中间结果。
+#[derive(burn::config::Config, Copy, Debug)]
+pub struct ForwardOptions {
+    /// 是否返回嵌入层的输出。
+    #[config(default = false)]
+    pub embeddings: bool,
+    /// 是否返回每个 transformer 块的输出。
+    #[config(default = false)]
+    pub hidden_states: bool,
+    /// 是否返回 final_norm 的输出。
+    #[config(default = false)]
+    pub final_hidden: bool,
+}
+
+/// [`GptModel::forward_with_outputs`] 的输出，张量的维度均为 (batch-size, num-tokens, *)。
+#[derive(Debug)]
+pub struct ForwardOutputs<B: Backend> {
+    pub logits: Tensor<B, 3>,
+    /// 词嵌入和位置嵌入之和（经过 dropout），即第一个 transformer 块的输入。
+    pub embeddings: Option<Tensor<B, 3>>,
+    /// 第 i 个元素为第 i 个 transformer 块输出的残差流。
+    pub hidden_states: Option<Vec<Tensor<B, 3>>>,
+    /// final_norm 的输出，即 out_head 的输入。
+    pub final_hidden: Option<Tensor<B, 3>>,
+}
+
 impl<B: Backend> GptModel<B> {
     pub fn forward(&self, in_idx: Tensor<B, 2, Int>) -> Tensor<B, 3> {
+        self.forward_with_outputs(in_idx, ForwardOptions::new()).logits
+    }
+
+    /// 和 [`GptModel::forward`] 一样计算 logits，并按 `opts` 返回中间结果。
+    pub fn forward_with_outputs(&self, in_idx: Tensor<B, 2, Int>, opts: ForwardOptions) -> ForwardOutputs<B> {
         let device = in_idx.device();
         let seq_len = in_idx.shape().dims[1];
 
-        let tok_embeds = self.tok_emb.forward(in_idx);
+        let positions = Tensor::arange(0..(seq_len as i64), &device).unsqueeze::<2>();
+        self.forward_impl(in_idx, positions, None, opts)
+    }
+
+    /// 处理左填充的批量输入：第 i 行的前 `npads[i]` 个 token 是填充 token，它们不会被其他 token 关注，
+    /// 且每行第一个非填充 token 的位置编号为 0，所以非填充位置的输出和单独计算该行时一致。
+    pub fn forward_left_padded(
+        &self,
+        in_idx: Tensor<B, 2, Int>,
+        npads: &[usize],
+        opts: ForwardOptions,
+    ) -> ForwardOutputs<B> {
+        let device = in_idx.device();
+        let [batch_size, seq_len] = in_idx.dims();
+        assert_eq!(batch_size, npads.len(), "npads must have one element per row");
 
-        let pos_embeds = self
-            .pos_emb
-            .forward(Tensor::arange(0..(seq_len as i64), &device).unsqueeze::<2>());
+        if npads.iter().all(|&v| v == 0) {
+            return self.forward_with_outputs(in_idx, opts);
+        }
+
+        let positions: Vec<i64> = npads
+            .iter()
+            .flat_map(|&n| (0..seq_len).map(move |j| j.saturating_sub(n) as i64))
+            .collect();
+        let positions = Tensor::<B, 1, Int>::from_ints(positions.as_slice(), &device).reshape([batch_size, seq_len]);
+
+        let padding_mask: Vec<bool> = npads.iter().flat_map(|&n| (0..seq_len).map(move |j| j < n)).collect();
+        let padding_mask = Tensor::<B, 1, Bool>::from_bool(TensorData::from(padding_mask.as_slice()), &device)
+            .reshape([batch_size, seq_len]);
+
+        self.forward_impl(in_idx, positions, Some(padding_mask), opts)
+    }
+
+    fn forward_impl(
+        &self,
+        in_idx: Tensor<B, 2, Int>,
+        positions: Tensor<B, 2, Int>,
+        padding_mask: Option<Tensor<B, 2, Bool>>,
+        opts: ForwardOptions,
+    ) -> ForwardOutputs<B> {
+        let tok_embeds = self.tok_emb.forward(in_idx);
+        let pos_embeds = self.pos_emb.forward(positions);
 
         let x = tok_embeds + pos_embeds;
         let mut x = self.drop_emb.forward(x);
+        let embeddings = opts.embeddings.then(|| x.clone());
+
+        let mut hidden_states = opts.hidden_states.then(|| Vec::with_capacity(self.trf_blocks.len()));
         for b in &self.trf_blocks {
-            x = b.forward(x);
+            x = b.forward_with_padding(x, padding_mask.clone());
+            if let Some(v) = hidden_states.as_mut() {
+                v.push(x.clone());
+            }
         }
         let x = self.final_norm.forward(x);
+        let final_hidden = opts.final_hidden.then(|| x.clone());
         let logits = self.out_head.forward(x);
 
-        logits
+        ForwardOutputs {
+            logits,
+            embeddings,
+            hidden_states,
+            final_hidden,
+        }
     }
 }
 
 impl Config {
     pub fn init<B: Backend>(&self, device: &B::Device) -> GptModel<B> {
         let c = self;
+        let init = c.init_scheme();
 
-        let tok_emb = EmbeddingConfig::new(c.vocab_size, c.emb_dim).init(device);
-        let pos_emb = EmbeddingConfig::new(c.context_length, c.emb_dim).init(device);
-        let drop_emb = Dropout { prob: c.drop_rate };
+        let tok_emb = init.init_embedding(EmbeddingConfig::new(c.vocab_size, c.emb_dim), device);
+        let pos_emb = init.init_embedding(EmbeddingConfig::new(c.context_length, c.emb_dim), device);
+        let drop_emb = Dropout {
+            prob: c.drop_emb_rate(),
+        };
 
         let trf_blocks: Vec<_> = {
-            let cc = TransformerBlockConfig::new(c.context_length, c.emb_dim, c.nheads, c.drop_rate, c.qkv_bias);
+            let cc = TransformerBlockConfig::new(c.context_length, c.emb_dim, c.nheads, c.drop_rate, c.qkv_bias)
+                .with_drop_attn_rate(Some(c.drop_attn_rate()))
+                .with_drop_shortcut_rate(Some(c.drop_shortcut_rate()))
+                .with_init(init)
+                .with_proj_init(init.residual(c.nlayers));
             (0..c.nlayers).map(|_| cc.init(device)).collect()
         };
 
         let final_norm = LayerNormConfig::new(c.emb_dim).init(device);
-        let out_head = LinearConfig::new(c.emb_dim, c.vocab_size).with_bias(false).init(device);
+        let out_head = init.init_linear(LinearConfig::new(c.emb_dim, c.vocab_size).with_bias(false), device);
 
         GptModel {
             tok_emb,
@@ -1,13 +1,13 @@
 mod dummy;
 
 use burn::module::Module;
-use burn::nn::Dropout;
+use burn::nn::{Dropout, LinearConfig};
 use burn::prelude::*;
 use burn::tensor::Tensor;
 use chapter03::attention::{MultiHeadAttention, MultiHeadAttentionConfig};
 pub use dummy::*;
 
-use crate::{FeedForward, FeedForwardConfig, LayerNorm, LayerNormConfig};
+use crate::{FeedForward, FeedForwardConfig, InitScheme, LayerNorm, LayerNormConfig};
 
 #[derive(Debug, Module)]
 pub struct TransformerBlock<B: Backend> {
@@ -23,17 +23,33 @@ pub struct TransformerBlockConfig {
     pub context_length: usize,
     pub emb_dim: usize,
     pub nheads: usize,
+    /// 未单独设置的 `drop_*_rate` 使用的丢弃概率。
     pub drop_rate: f64,
     pub qkv_bias: bool,
+    /// 注意力权重的丢弃概率。
+    pub drop_attn_rate: Option<f64>,
+    /// 残差分支输出（`drop_shortcut`）的丢弃概率。
+    pub drop_shortcut_rate: Option<f64>,
+    /// 注意力层的 QKV 投影和前馈网络 linear1 的初始化方案。
+    #[config(default = "InitScheme::BurnDefault")]
+    pub init: InitScheme,
+    /// 残差分支输出投影（注意力层的 out_proj 和前馈网络的 linear2）的初始化方案。
+    #[config(default = "InitScheme::BurnDefault")]
+    pub proj_init: InitScheme,
 }
 
 impl<B: Backend> TransformerBlock<B> {
     /// 输入维度（batch-size，num-tokens，embedding-dim）
     pub fn forward(&self, x: Tensor<B, 3>) -> Tensor<B, 3> {
+        self.forward_with_padding(x, None)
+    }
+
+    /// `padding_mask` 的含义参见 [`MultiHeadAttention::forward_with_padding`]。
+    pub fn forward_with_padding(&self, x: Tensor<B, 3>, padding_mask: Option<Tensor<B, 2, Bool>>) -> Tensor<B, 3> {
         let shortcut = x.clone();
 
         let x = self.norm1.forward(x);
-        let x = self.attn.forward(x);
+        let x = self.attn.forward_with_padding(x, padding_mask);
         let x = self.drop_shortcut.forward(x);
         let x = x + shortcut;
 
@@ -49,20 +65,33 @@ impl<B: Backend> TransformerBlock<B> {
 
 impl TransformerBlockConfig {
     pub fn init<B: Backend>(&self, device: &B::Device) -> TransformerBlock<B> {
-        let attn = MultiHeadAttentionConfig::new(
+        let mut attn = MultiHeadAttentionConfig::new(
             self.emb_dim,
             self.emb_dim,
             self.context_length,
-            self.drop_rate,
+            self.drop_attn_rate.unwrap_or(self.drop_rate),
             self.nheads,
         )
         .with_qkv_bias(self.qkv_bias)
         .init(device);
+        if self.init != InitScheme::BurnDefault || self.proj_init != InitScheme::BurnDefault {
+            let c = LinearConfig::new(self.emb_dim, self.emb_dim);
+            let qkv = c.clone().with_bias(self.qkv_bias);
+            attn.wq = self.init.init_linear(qkv.clone(), device);
+            attn.wk = self.init.init_linear(qkv.clone(), device);
+            attn.wv = self.init.init_linear(qkv, device);
+            attn.out_proj = self.proj_init.init_linear(c, device);
+        }
 
-        let ff = FeedForwardConfig::new(self.emb_dim).init(device);
+        let ff = FeedForwardConfig::new(self.emb_dim)
+            .with_init(self.init)
+            .with_proj_init(self.proj_init)
+            .init(device);
         let norm1 = LayerNormConfig::new(self.emb_dim).init(device);
         let norm2 = LayerNormConfig::new(self.emb_dim).init(device);
-        let drop_shortcut = Dropout { prob: self.drop_rate };
+        let drop_shortcut = Dropout {
+            prob: self.drop_shortcut_rate.unwrap_or(self.drop_rate),
+        };
 
         TransformerBlock {
             attn,
@@ -0,0 +1,58 @@
+use burn::backend::NdArray;
+use burn::config::Config as _;
+use burn::prelude::*;
+use chapter04::{Config, InitScheme, Preset};
+
+type B = NdArray<f32>;
+
+#[test]
+fn load_legacy_json() {
+    // 只有一个 drop_rate 的旧版配置
+    const JSON: &str = r#"{
+        "vocab_size": 50257,
+        "context_length": 1024,
+        "emb_dim": 768,
+        "nheads": 12,
+        "nlayers": 12,
+        "drop_rate": 0.2,
+        "qkv_bias": true
+    }"#;
+
+    let c = Config::load_binary(JSON.as_bytes()).expect("load legacy config");
+
+    assert_eq!(0.2, c.drop_rate);
+    assert_eq!(None, c.drop_emb_rate);
+    assert_eq!(None, c.drop_attn_rate);
+    assert_eq!(None, c.drop_shortcut_rate);
+
+    assert_eq!(0.2, c.drop_emb_rate());
+    assert_eq!(0.2, c.drop_attn_rate());
+    assert_eq!(0.2, c.drop_shortcut_rate());
+
+    // 加入 init 字段之前保存的配置
+    assert_eq!(None, c.init);
+    assert_eq!(InitScheme::BurnDefault, c.init_scheme());
+}
+
+#[test]
+fn separate_drop_rates() {
+    let device = &<B as Backend>::Device::default();
+
+    let c = Preset::Gpt2Tiny
+        .config()
+        .with_drop_rate(0.0)
+        .with_drop_attn_rate(Some(0.3))
+        .with_drop_shortcut_rate(Some(0.4));
+
+    let c = Config::load_binary(c.to_string().as_bytes()).expect("round trip config");
+    assert_eq!(0.0, c.drop_emb_rate());
+    assert_eq!(0.3, c.drop_attn_rate());
+    assert_eq!(0.4, c.drop_shortcut_rate());
+
+    let model = c.init::<B>(device);
+    assert_eq!(0.0, model.drop_emb.prob);
+    for b in &model.trf_blocks {
+        assert_eq!(0.3, b.attn.dropout.prob);
+        assert_eq!(0.4, b.drop_shortcut.prob);
+    }
+}
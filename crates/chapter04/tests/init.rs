@@ -0,0 +1,102 @@
+use burn::backend::NdArray;
+use burn::prelude::*;
+use chapter04::{Config, GptModel, InitScheme, Preset};
+
+type B = NdArray<f32>;
+
+/// 返回（均值，标准差）
+fn mean_std<const D: usize>(t: Tensor<B, D>) -> (f32, f32) {
+    let t = t.flatten::<1>(0, D - 1);
+    let (var, mean) = t.var_mean(0);
+    (mean.into_scalar(), var.sqrt().into_scalar())
+}
+
+fn assert_normal<const D: usize>(name: &str, t: Tensor<B, D>, expect_std: f64) {
+    let n = t.shape().num_elements();
+    let (mean, std) = mean_std(t);
+
+    // 均值的标准误差为 std/√n，取 6 倍作为容忍度；标准差允许 5% 的相对误差。
+    let mean_tolerance = 6.0 * expect_std / (n as f64).sqrt();
+    assert!(
+        (mean as f64).abs() < mean_tolerance,
+        "{name}: mean {mean} exceeds tolerance {mean_tolerance}"
+    );
+    assert!(
+        ((std as f64) - expect_std).abs() < 0.05 * expect_std,
+        "{name}: expect std {expect_std}, got {std}"
+    );
+}
+
+fn assert_zeros(name: &str, t: Tensor<B, 1>) {
+    let max = t.abs().max().into_scalar();
+    assert_eq!(0.0, max, "{name}: expect zeros");
+}
+
+fn new_model(init: InitScheme) -> (Config, GptModel<B>) {
+    let device = &<B as Backend>::Device::default();
+
+    B::seed(123);
+    let c = Preset::Gpt2Tiny.config().with_nlayers(4).with_init(Some(init));
+    (c, c.init::<B>(device))
+}
+
+fn check_normal_scheme(init: InitScheme, std: f64) {
+    let (c, model) = new_model(init);
+    let residual_std = std / ((2 * c.nlayers) as f64).sqrt();
+
+    assert_normal("tok_emb", model.tok_emb.weight.val(), std);
+    assert_normal("pos_emb", model.pos_emb.weight.val(), std);
+    assert_normal("out_head", model.out_head.weight.val(), std);
+
+    for (i, b) in model.trf_blocks.iter().enumerate() {
+        for (name, l) in [("wq", &b.attn.wq), ("wk", &b.attn.wk), ("wv", &b.attn.wv)] {
+            assert_normal(&format!("#{i} attn.{name}"), l.weight.val(), std);
+            assert_zeros(
+                &format!("#{i} attn.{name} bias"),
+                l.bias.as_ref().expect("miss qkv bias").val(),
+            );
+        }
+        assert_normal(
+            &format!("#{i} attn.out_proj"),
+            b.attn.out_proj.weight.val(),
+            residual_std,
+        );
+        assert_zeros(
+            &format!("#{i} attn.out_proj bias"),
+            b.attn.out_proj.bias.as_ref().expect("miss out_proj bias").val(),
+        );
+
+        assert_normal(&format!("#{i} ff.linear1"), b.ff.linear1.weight.val(), std);
+        assert_normal(&format!("#{i} ff.linear2"), b.ff.linear2.weight.val(), residual_std);
+        assert_zeros(
+            &format!("#{i} ff.linear2 bias"),
+            b.ff.linear2.bias.as_ref().expect("miss linear2 bias").val(),
+        );
+
+        let (mean, std) = mean_std(b.norm1.scale.val());
+        assert_eq!((1.0, 0.0), (mean, std), "#{i} norm1.scale should be ones");
+    }
+}
+
+#[test]
+fn init_gpt2() {
+    check_normal_scheme(InitScheme::Gpt2, 0.02);
+}
+
+#[test]
+fn init_normal() {
+    check_normal_scheme(InitScheme::Normal { std: 0.1 }, 0.1);
+}
+
+#[test]
+fn init_burn_default() {
+    let (c, model) = new_model(InitScheme::BurnDefault);
+
+    // burn 的 Embedding 默认服从 N(0, 1)
+    assert_normal("tok_emb", model.tok_emb.weight.val(), 1.0);
+
+    // burn 的 Linear 默认使用 gain=1/√3 的 Kaiming 均匀分布，即 U(-1/√fan_in, 1/√fan_in)，标准差为 1/√(3·fan_in)
+    let expect_std = 1.0 / ((3 * c.emb_dim) as f64).sqrt();
+    assert_normal("out_head", model.out_head.weight.val(), expect_std);
+    assert_normal("#0 attn.wq", model.trf_blocks[0].attn.wq.weight.val(), expect_std);
+}
@@ -0,0 +1,87 @@
+use burn::backend::NdArray;
+use burn::prelude::*;
+use burn::tensor::Tolerance;
+use chapter04::{ForwardOptions, Preset};
+
+type B = NdArray<f32>;
+
+#[test]
+fn forward_with_outputs() {
+    let device = &<B as Backend>::Device::default();
+
+    B::seed(123);
+    let c = Preset::Gpt2Tiny.config().with_drop_rate(0.0);
+    let model = c.init::<B>(device);
+
+    let idx = Tensor::<B, 2, Int>::from_ints([[6109, 3626, 6100, 345], [6109, 1110, 6622, 257]], device);
+
+    let expect = model.forward(idx.clone());
+
+    let opts = ForwardOptions::new()
+        .with_embeddings(true)
+        .with_hidden_states(true)
+        .with_final_hidden(true);
+    let got = model.forward_with_outputs(idx, opts);
+
+    got.logits.clone().into_data().assert_eq(&expect.into_data(), true);
+
+    let embeddings = got.embeddings.expect("miss embeddings");
+    assert_eq!([2, 4, c.emb_dim], embeddings.dims());
+
+    let hidden_states = got.hidden_states.expect("miss hidden states");
+    assert_eq!(c.nlayers, hidden_states.len());
+    for (i, h) in hidden_states.iter().enumerate() {
+        assert_eq!([2, 4, c.emb_dim], h.dims(), "bad dims of #{i} hidden state");
+    }
+
+    let last = hidden_states.last().cloned().expect("miss last hidden state");
+    let final_hidden = got.final_hidden.expect("miss final hidden");
+    final_hidden
+        .clone()
+        .into_data()
+        .assert_eq(&model.final_norm.forward(last).into_data(), true);
+
+    model
+        .out_head
+        .forward(final_hidden)
+        .into_data()
+        .assert_eq(&got.logits.into_data(), true);
+}
+
+#[test]
+fn forward_with_outputs_default() {
+    let device = &<B as Backend>::Device::default();
+
+    let model = Preset::Gpt2Tiny.config().init::<B>(device);
+    let idx = Tensor::<B, 2, Int>::from_ints([[1, 2, 3]], device);
+
+    let got = model.forward_with_outputs(idx, ForwardOptions::new());
+
+    assert_eq!([1, 3, 50257], got.logits.dims());
+    assert!(got.embeddings.is_none());
+    assert!(got.hidden_states.is_none());
+    assert!(got.final_hidden.is_none());
+}
+
+#[test]
+fn forward_left_padded() {
+    let device = &<B as Backend>::Device::default();
+
+    B::seed(123);
+    let model = Preset::Gpt2Tiny.config().with_drop_rate(0.0).init::<B>(device);
+
+    let short = Tensor::<B, 2, Int>::from_ints([[3626, 6100]], device);
+    let long = Tensor::<B, 2, Int>::from_ints([[6109, 1110, 6622, 257]], device);
+    let padded = Tensor::<B, 2, Int>::from_ints([[0, 0, 3626, 6100], [6109, 1110, 6622, 257]], device);
+
+    let got = model.forward_left_padded(padded, &[2, 0], ForwardOptions::new()).logits;
+
+    let tolerance = Tolerance::<f32>::absolute(1e-5);
+    got.clone()
+        .slice(s![0..1, 2..])
+        .into_data()
+        .assert_approx_eq(&model.forward(short).into_data(), tolerance);
+    got.slice(s![1..2])
+        .into_data()
+        .assert_approx_eq(&model.forward(long).into_data(), tolerance);
+}
@@ -0,0 +1,83 @@
+//! 比较 [`TensorExt`] 和逐行复制到主机上计算的耗时：`cargo bench -p chapter05 --bench x [-- cuda]`。
+//!
+//! NdArray 的数据本来就在主机上，两者的耗时相近；在 GPU 上逐行复制的开销才明显。
+use std::time::{Duration, Instant};
+
+use burn::backend::{LibTorch, NdArray};
+use burn::prelude::*;
+use burn::tensor::{Distribution, activation};
+use chapter05::rand;
+use chapter05::x::{self, TensorExt};
+
+const VOCAB_SIZE: usize = 50257;
+const ITERS: u32 = 20;
+
+fn main() {
+    if std::env::args().any(|v| v == "cuda") {
+        run::<LibTorch>(&<LibTorch as Backend>::Device::Cuda(0));
+    } else {
+        run::<NdArray>(&Default::default());
+    }
+}
+
+fn run<B: Backend>(device: &B::Device) {
+    for batch_size in [1, 8, 32] {
+        let logits = Tensor::<B, 2>::random([batch_size, VOCAB_SIZE], Distribution::Normal(0.0, 1.0), device);
+        let probas = activation::softmax(logits, 1);
+        let values = Tensor::<B, 1>::random([batch_size], Distribution::Uniform(0.0, 1.0), device);
+        let c = probas.clone().cumsum();
+
+        println!("batch-size = {batch_size}");
+        bench("cumsum", || probas.clone().cumsum().into_data());
+        bench("cumsum (host)", || host_cumsum(probas.clone()).into_data());
+        bench("search_sorted", || c.clone().search_sorted(values.clone()).into_data());
+        bench("search_sorted (host)", || {
+            host_search_sorted(c.clone(), values.clone()).into_data()
+        });
+        bench("multinomial", || rand::multinomial(probas.clone()).into_data());
+    }
+}
+
+/// 调用 `f` 并取回结果，以等待设备上的计算完成。
+fn bench(name: &str, mut f: impl FnMut() -> TensorData) {
+    f();
+
+    let start = Instant::now();
+    for _ in 0..ITERS {
+        f();
+    }
+    let elapsed: Duration = start.elapsed() / ITERS;
+
+    println!("  {name:<24}{elapsed:>12.3?}/iter");
+}
+
+/// 原来的实现：逐行复制到主机上计算。
+fn host_cumsum<B: Backend>(t: Tensor<B, 2>) -> Tensor<B, 2> {
+    let device = &t.device();
+    let rows: Vec<_> = t
+        .iter_dim(0)
+        .map(|v| {
+            let mut data = v.to_data().to_vec::<f32>().expect("read row");
+            let mut s = 0.0;
+            for v in data.iter_mut() {
+                s += *v;
+                *v = s;
+            }
+            Tensor::<B, 1>::from_floats(data.as_slice(), device)
+        })
+        .collect();
+
+    Tensor::stack(rows, 0)
+}
+
+fn host_search_sorted<B: Backend>(t: Tensor<B, 2>, values: Tensor<B, 1>) -> Tensor<B, 1, Int> {
+    let device = &t.device();
+    let values = values.to_data().to_vec::<f32>().expect("read values");
+    let out: Vec<i64> = t
+        .iter_dim(0)
+        .zip(values)
+        .map(|(v, t)| x::search_sorted(&v.to_data().to_vec::<f32>().expect("read row"), t) as i64)
+        .collect();
+
+    Tensor::from_ints(out.as_slice(), device)
+}
@@ -10,7 +10,7 @@ fn main() {
 
     let p = Tensor::<B, 2>::from_floats([[0.1, 0.2, 0.3, 0.4], [0.1, 0.3, 0.3, 0.3]], device);
 
-    let got = rand::multinomial(p).squeeze::<1>(0);
+    let got = rand::multinomial(p).squeeze::<1>(1);
 
     println!("{got}");
 }
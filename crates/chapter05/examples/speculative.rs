@@ -0,0 +1,56 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::Context as _;
+use burn::backend::LibTorch;
+use burn::prelude::Backend;
+use chapter04::GptModel;
+use chapter05::gpt2;
+use chapter05::speculative;
+use chapter05::utils::GenerateOptions;
+use tiktoken::ext::Encoding;
+
+type B = LibTorch;
+
+type Device = <LibTorch as Backend>::Device;
+
+/// 以 GPT-2 124M 为草稿模型、355M 为目标模型进行投机解码：
+/// `cargo run --example speculative -- <124M 参数目录> <355M 参数目录>`。
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let draft_dir = args.next().unwrap_or_else(|| "gpt2/124M".to_owned());
+    let target_dir = args.next().unwrap_or_else(|| "gpt2/355M".to_owned());
+
+    let device = &Device::Cpu;
+
+    let (draft, _) = load_model(&draft_dir, device).context("load draft model")?;
+    let (target, context_length) = load_model(&target_dir, device).context("load target model")?;
+
+    let tokenizer = Encoding::gpt2();
+    let prompt = tokenizer.encode("Every effort moves you", &HashSet::new());
+
+    let opts = GenerateOptions::new(50, context_length);
+    let (token_ids, stats) = speculative::speculative_generate(&target, &draft, &prompt, &opts, 4, device)
+        .context("speculative generate")?;
+
+    let out = tokenizer.decode(&token_ids).context("decode output")?;
+    println!("Output text:\n{}", String::from_utf8_lossy(&out));
+    println!(
+        "rounds: {}, drafted: {}, accepted: {} ({:.1}%)",
+        stats.rounds,
+        stats.drafted,
+        stats.accepted,
+        stats.acceptance_rate() * 100.0
+    );
+
+    Ok(())
+}
+
+fn load_model(dir: &str, device: &Device) -> anyhow::Result<(GptModel<B>, usize)> {
+    let (c, params) = gpt2::load_settings_and_params(Path::new(dir)).context("load gpt2 config")?;
+
+    let mut model = c.init::<B>(device);
+    gpt2::load_weights_into_gpt2(params, &mut model).context("load weights into model")?;
+
+    Ok((model, c.context_length))
+}
@@ -1,9 +1,12 @@
-import json
+import sys
+
 import numpy as np
 
 from gpt_download import download_and_load_gpt2
 
-settings, params = download_and_load_gpt2(model_size="124M", models_dir=".")
+# 模型规模：124M（默认）、355M、774M 或 1558M
+model_size = sys.argv[1] if len(sys.argv) > 1 else "124M"
+settings, params = download_and_load_gpt2(model_size=model_size, models_dir=".")
 
 print("Settings:", settings)
 print("Parameter dictionary keys:", params.keys())
@@ -11,8 +14,24 @@ print("Parameter dictionary keys:", params.keys())
 print(params["wte"])
 print("Token embedding weight tensor dimensions:", params["wte"].shape)
 
-path = "124M/params-124m.json"
-with open(path, "w", encoding="utf-8") as f:
-    # json.dump(params, f, ensure_ascii=False)
-    json.dump(params, f, default=lambda x: x.tolist() if isinstance(x, np.ndarray) else x)
-print(f"Parameters saved to {path}")
\ No newline at end of file
+
+
+def flatten(prefix, value, out):
+    """把嵌套的参数字典展开为以点号分隔的名称，例如 blocks.0.attn.c_attn.w。"""
+    if isinstance(value, dict):
+        items = value.items()
+    elif isinstance(value, list):
+        items = enumerate(value)
+    else:
+        out[prefix] = np.asarray(value, dtype="<f4")
+        return
+    for k, v in items:
+        flatten(f"{prefix}.{k}" if prefix else str(k), v, out)
+
+
+arrays = {}
+flatten("", params, arrays)
+
+path = f"{model_size}/params-{model_size.lower()}.npz"
+np.savez(path, **arrays)
+print(f"Parameters saved to {path}")
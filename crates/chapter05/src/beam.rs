@@ -0,0 +1,155 @@
+//! 束搜索（beam search）解码。
+use burn::prelude::*;
+use burn::tensor::activation;
+use chapter04::GptModel;
+
+#[derive(Config, Debug)]
+pub struct BeamSearchOptions {
+    pub max_new_tokens: usize,
+    pub context_size: usize,
+    /// 束宽，即每一步保留的候选序列数。
+    #[config(default = 4)]
+    pub num_beams: usize,
+    /// 候选序列的得分为 `累计对数概率 / 生成长度^length_penalty`。大于 0 时倾向于更长的序列，小于 0 时倾向于更短的序列。
+    #[config(default = 1.0)]
+    pub length_penalty: f32,
+    /// 为 true 时，结束的候选序列达到 `num_beams` 个即停止；否则直到未结束的序列不可能得到更高的得分才停止。
+    #[config(default = false)]
+    pub early_stopping: bool,
+    /// 返回得分最高的序列数，不能超过 `num_beams`。
+    #[config(default = 1)]
+    pub num_return_sequences: usize,
+    pub eos_id: Option<u32>,
+}
+
+/// 束搜索得到的一个序列。
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hypothesis {
+    /// 生成的 token，不含输入和 EOS。
+    pub tokens: Vec<u32>,
+    /// 生成的各 token（包括 EOS）的对数概率之和。
+    pub logprob: f32,
+    /// 按长度惩罚归一化后的得分，返回的序列按该得分降序排列。
+    pub score: f32,
+    /// 是否以 EOS 结束。
+    pub finished: bool,
+}
+
+struct Beam {
+    tokens: Vec<u32>,
+    logprob: f32,
+}
+
+/// 以 `prompt` 为输入进行束搜索，返回得分最高的 `opts.num_return_sequences` 个序列。
+pub fn beam_search<B: Backend>(
+    model: &GptModel<B>,
+    prompt: &[u32],
+    opts: &BeamSearchOptions,
+    device: &B::Device,
+) -> Vec<Hypothesis> {
+    assert!(!prompt.is_empty(), "prompt must not be empty");
+    assert!(opts.num_beams > 0, "num_beams must be positive");
+    assert!(
+        (1..=opts.num_beams).contains(&opts.num_return_sequences),
+        "num_return_sequences must be in [1, num_beams]"
+    );
+
+    let score = |logprob: f32, len: usize| logprob / (len.max(1) as f32).powf(opts.length_penalty);
+
+    let mut beams = vec![Beam {
+        tokens: vec![],
+        logprob: 0.0,
+    }];
+    let mut hyps: Vec<Hypothesis> = vec![];
+    let mut done = false;
+
+    for step in 0..opts.max_new_tokens {
+        let nbeams = beams.len();
+        let seq_len = prompt.len() + step;
+
+        let ids: Vec<i64> = beams
+            .iter()
+            .flat_map(|b| prompt.iter().chain(&b.tokens).map(|&v| v as i64))
+            .collect();
+        let idx = Tensor::<B, 1, Int>::from_ints(ids.as_slice(), device).reshape([nbeams, seq_len]);
+        let idx = idx.slice(s![.., seq_len.saturating_sub(opts.context_size)..]);
+
+        let logits = model.forward(idx).slice(s![.., -1, ..]).squeeze::<2>(1);
+        let [_, vocab_size] = logits.dims();
+
+        // 所有候选的累计对数概率，维度为 (num-beams * vocab-size)
+        let beam_logprobs: Vec<f32> = beams.iter().map(|b| b.logprob).collect();
+        let beam_logprobs = Tensor::<B, 1>::from_floats(beam_logprobs.as_slice(), device).unsqueeze_dim::<2>(1);
+        let scores = (activation::log_softmax(logits, 1) + beam_logprobs).reshape([nbeams * vocab_size]);
+
+        // 最多有 num_beams 个候选以 EOS 结束，所以取 2 * num_beams 个候选足以保留 num_beams 个未结束的序列。
+        let k = (2 * opts.num_beams).min(nbeams * vocab_size);
+        let (values, indices) = scores.topk_with_indices(k, 0);
+        let values: Vec<f32> = values.into_data().to_vec().expect("read beam scores");
+        let indices: Vec<i64> = indices.into_data().iter::<i64>().collect();
+
+        let mut next = Vec::with_capacity(opts.num_beams);
+        for (rank, (logprob, i)) in values.into_iter().zip(indices).enumerate() {
+            let (b, token) = (i as usize / vocab_size, (i as usize % vocab_size) as u32);
+            let tokens = &beams[b].tokens;
+
+            if opts.eos_id == Some(token) {
+                // 和 Hugging Face 一样，只接受排名在前 num_beams 的结束序列
+                if rank < opts.num_beams {
+                    hyps.push(Hypothesis {
+                        tokens: tokens.clone(),
+                        logprob,
+                        score: score(logprob, tokens.len() + 1),
+                        finished: true,
+                    });
+                }
+                continue;
+            }
+
+            let mut tokens = tokens.clone();
+            tokens.push(token);
+            next.push(Beam { tokens, logprob });
+            if next.len() == opts.num_beams {
+                break;
+            }
+        }
+        beams = next;
+
+        sort_by_score(&mut hyps);
+        hyps.truncate(opts.num_beams);
+
+        if hyps.len() == opts.num_beams {
+            // 累计对数概率只会减小，所以未结束的序列的得分不超过以 best 和最有利的长度算得的得分：长度惩罚大于 0 时
+            // 为最长的 max_new_tokens，否则为当前长度 step + 1
+            let best = beams.iter().map(|b| b.logprob).fold(f32::NEG_INFINITY, f32::max);
+            let len = if opts.length_penalty > 0.0 {
+                opts.max_new_tokens
+            } else {
+                step + 1
+            };
+            let worst = hyps.last().map(|h| h.score).unwrap_or(f32::NEG_INFINITY);
+            if opts.early_stopping || worst >= score(best, len) {
+                done = true;
+                break;
+            }
+        }
+    }
+
+    // 达到生成长度上限时，未结束的序列也作为候选
+    if !done {
+        hyps.extend(beams.into_iter().map(|b| Hypothesis {
+            score: score(b.logprob, b.tokens.len()),
+            tokens: b.tokens,
+            logprob: b.logprob,
+            finished: false,
+        }));
+        sort_by_score(&mut hyps);
+    }
+
+    hyps.truncate(opts.num_return_sequences);
+    hyps
+}
+
+fn sort_by_score(hyps: &mut [Hypothesis]) {
+    hyps.sort_by(|a, b| b.score.total_cmp(&a.score));
+}
@@ -1,7 +1,7 @@
 use burn::backend::{Autodiff, NdArray};
 use burn::prelude::*;
 use burn::tensor::activation;
-use chapter05::config::GPT_124M;
+use chapter05::config;
 
 type B = Autodiff<NdArray<f32>>;
 
@@ -12,7 +12,7 @@ fn main() -> anyhow::Result<()> {
     // ["every effort moves", "I really like"]
     let inputs = Tensor::<B, 2, Int>::from_ints([[16833, 3626, 6100], [40, 1107, 588]], &device);
 
-    let model = GPT_124M.init::<B>(device).no_grad();
+    let model = config::gpt2_small_256().init::<B>(device).no_grad();
 
     let logits = model.forward(inputs);
     let dim = logits.dims().len() - 1;
@@ -2,7 +2,7 @@ use anyhow::Context as _;
 use burn::backend::{Autodiff, NdArray};
 use burn::prelude::*;
 use burn::tensor::activation;
-use chapter05::config::GPT_124M;
+use chapter05::config;
 use chapter05::utils::Tokenizer as _;
 use tiktoken::ext::Encoding;
 
@@ -17,7 +17,7 @@ fn main() -> anyhow::Result<()> {
     // [" effort moves you", " really like chocolate"]
     let targets = Tensor::<B, 2, Int>::from_ints([[3626, 6100, 345], [1107, 588, 11311]], &device);
 
-    let model = GPT_124M.init::<B>(device).no_grad();
+    let model = config::gpt2_small_256().init::<B>(device).no_grad();
 
     let logits = model.forward(inputs);
     let dim = logits.dims().len() - 1;
@@ -5,7 +5,7 @@ use burn::backend::{Autodiff, NdArray};
 use burn::prelude::*;
 use chapter02::dataset::{self, LoaderV1Options};
 use chapter02::verdict;
-use chapter05::config::GPT_124M;
+use chapter05::config;
 use tiktoken::ext::Encoding;
 
 type B = Autodiff<NdArray<f32>>;
@@ -13,6 +13,7 @@ type B = Autodiff<NdArray<f32>>;
 // type B = Autodiff<LibTorch>;
 
 fn main() -> anyhow::Result<()> {
+    let c = config::gpt2_small_256();
     let tokenizer = Encoding::gpt2();
 
     let text_data = verdict::load().context("load verdict")?;
@@ -28,8 +29,8 @@ fn main() -> anyhow::Result<()> {
     let train_loader = {
         let opts = LoaderV1Options {
             batch_size: 2,
-            max_length: GPT_124M.context_length,
-            stride: GPT_124M.context_length,
+            max_length: c.context_length,
+            stride: c.context_length,
             drop_last: true,
             shuffle_seed: Some(123),
             ..Default::default()
@@ -39,8 +40,8 @@ fn main() -> anyhow::Result<()> {
     let val_loader = {
         let opts = LoaderV1Options {
             batch_size: 2,
-            max_length: GPT_124M.context_length,
-            stride: GPT_124M.context_length,
+            max_length: c.context_length,
+            stride: c.context_length,
             drop_last: false,
             ..Default::default()
         };
@@ -5,7 +5,7 @@ use burn::backend::{Autodiff, NdArray};
 use burn::prelude::*;
 use chapter02::dataset::{self, LoaderV1Options};
 use chapter02::verdict;
-use chapter05::config::GPT_124M;
+use chapter05::config;
 use chapter05::loss;
 use tiktoken::ext::Encoding;
 
@@ -14,6 +14,7 @@ type B = Autodiff<NdArray<f32>>;
 // type B = Autodiff<LibTorch>;
 
 fn main() -> anyhow::Result<()> {
+    let c = config::gpt2_small_256();
     let tokenizer = Encoding::gpt2();
 
     let text_data = verdict::load().context("load verdict")?;
@@ -29,8 +30,8 @@ fn main() -> anyhow::Result<()> {
     let train_loader = {
         let opts = LoaderV1Options {
             batch_size: 2,
-            max_length: GPT_124M.context_length,
-            stride: GPT_124M.context_length,
+            max_length: c.context_length,
+            stride: c.context_length,
             drop_last: true,
             shuffle_seed: Some(123),
             ..Default::default()
@@ -40,8 +41,8 @@ fn main() -> anyhow::Result<()> {
     let val_loader = {
         let opts = LoaderV1Options {
             batch_size: 2,
-            max_length: GPT_124M.context_length,
-            stride: GPT_124M.context_length,
+            max_length: c.context_length,
+            stride: c.context_length,
             drop_last: false,
             ..Default::default()
         };
@@ -50,7 +51,7 @@ fn main() -> anyhow::Result<()> {
 
     let device = &<B as Backend>::Device::Cpu;
 
-    let model = GPT_124M.init::<B>(&device).no_grad();
+    let model = c.init::<B>(&device).no_grad();
 
     let train_loss = loss::calc_loss_loader(train_loader.as_ref(), &model, None, &device);
     let val_loss = loss::calc_loss_loader(val_loader.as_ref(), &model, None, &device);
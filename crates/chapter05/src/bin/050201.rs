@@ -14,7 +14,7 @@ use burn::tensor::backend::AutodiffBackend;
 use chapter02::dataset::{self, Batch, LoaderV1Options};
 use chapter02::verdict;
 use chapter04::GptModel;
-use chapter05::config::GPT_124M;
+use chapter05::config;
 use chapter05::loss;
 use chapter05::utils::Tokenizer;
 use serde::{Deserialize, Serialize};
@@ -24,6 +24,7 @@ type B = Autodiff<LibTorch>;
 // type B = Autodiff<Cuda>;
 
 fn main() -> anyhow::Result<()> {
+    let c = config::gpt2_small_256();
     let tokenizer = Encoding::gpt2();
 
     let text_data = verdict::load().context("load verdict")?;
@@ -45,8 +46,8 @@ fn main() -> anyhow::Result<()> {
     let train_loader = {
         let opts = LoaderV1Options {
             batch_size: 2,
-            max_length: GPT_124M.context_length,
-            stride: GPT_124M.context_length,
+            max_length: c.context_length,
+            stride: c.context_length,
             drop_last: true,
             shuffle_seed: Some(123),
             ..Default::default()
@@ -57,8 +58,8 @@ fn main() -> anyhow::Result<()> {
     let val_loader = {
         let opts = LoaderV1Options {
             batch_size: 2,
-            max_length: GPT_124M.context_length,
-            stride: GPT_124M.context_length,
+            max_length: c.context_length,
+            stride: c.context_length,
             drop_last: false,
             ..Default::default()
         };
@@ -67,7 +68,7 @@ fn main() -> anyhow::Result<()> {
 
     // 警告：Module::to_device 转移后的模型不再支持反向传播。
     // 详情参见 burn 的官方文档：https://docs.rs/burn/0.17.1/burn/module/trait.Module.html#tymethod.to_device
-    let model = GPT_124M.init(device);
+    let model = c.init(device);
 
     let optimizer = AdamWConfig::new().with_weight_decay(0.1).init::<B, GptModel<B>>();
 
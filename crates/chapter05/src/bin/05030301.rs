@@ -3,7 +3,7 @@ use std::fs;
 use anyhow::Context;
 use burn::backend::LibTorch;
 use burn::prelude::*;
-use chapter05::config::GPT_124M;
+use chapter05::config;
 use chapter05::utils::{self, GenerateOptions, Tokenizer};
 use tiktoken::ext::Encoding;
 
@@ -13,6 +13,7 @@ type B = LibTorch;
 type D = <B as Backend>::Device;
 
 fn main() -> anyhow::Result<()> {
+    let c = config::gpt2_small_256();
     if !fs::exists("gpt_124m_trained.mpk").context("check model path")? {
         anyhow::bail!("train GPT-124M model first by running `cargo run --bin 0502`");
     }
@@ -22,17 +23,17 @@ fn main() -> anyhow::Result<()> {
     B::seed(123);
     let device = &D::Cpu;
 
-    let model = GPT_124M
+    let model = c
         .load::<B>("gpt_124m_trained.mpk", device)
         .context("load model")?
         .no_grad();
 
     let idx = tokenizer.tokenize("Every effort moves you").to_device(device);
 
-    let opts = GenerateOptions::new(15, GPT_124M.context_length)
+    let opts = GenerateOptions::new(15, c.context_length)
         .with_topk(25.into())
         .with_temperature(1.4);
-    let token_ids = utils::generate(&model, idx, opts);
+    let token_ids = utils::generate(&model, idx, &opts).context("generate")?;
 
     let out = tokenizer.detokenize(token_ids).context("decode output")?;
     println!("Output text: {out}");
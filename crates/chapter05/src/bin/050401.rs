@@ -12,17 +12,27 @@ use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
 use burn::tensor::backend::AutodiffBackend;
 use chapter02::dataset::{self, Batch, LoaderV1Options};
 use chapter02::verdict;
-use chapter04::GptModel;
-use chapter05::config::GPT_124M;
+use chapter04::{GptModel, InitScheme};
+use chapter05::checkpoint::CheckpointMeta;
+use chapter05::config;
 use chapter05::loss;
+use chapter05::resume::{Checkpointer, ResumableLoader, TrainState};
 use chapter05::utils::Tokenizer;
+use clap::Parser;
 use serde::{Deserialize, Serialize};
 use tiktoken::ext::Encoding;
 
 type B = Autodiff<LibTorch>;
 // type B = Autodiff<Cuda>;
 
+/// 训练检查点目录，中断后使用 `--resume` 继续训练。
+const CHECKPOINT_DIR: &str = "gpt_124m_train-ckpt";
+
 fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    // 从头训练时按 GPT-2 的方案初始化参数
+    let c = config::gpt2_small_256().with_init(Some(InitScheme::Gpt2));
+
     let tokenizer = Encoding::gpt2();
 
     let text_data = verdict::load().context("load verdict")?;
@@ -39,20 +49,21 @@ fn main() -> anyhow::Result<()> {
     let train_loader = {
         let opts = LoaderV1Options {
             batch_size: 2,
-            max_length: GPT_124M.context_length,
-            stride: GPT_124M.context_length,
+            max_length: c.context_length,
+            stride: c.context_length,
             drop_last: true,
             shuffle_seed: Some(123),
             ..Default::default()
         };
-        dataset::create_dataloader_v1::<B, _>(train_data, &tokenizer, opts).context("load train data")?
+        let v = dataset::create_dataloader_v1::<B, _>(train_data, &tokenizer, opts).context("load train data")?;
+        ResumableLoader::new(v)
     };
 
     let val_loader = {
         let opts = LoaderV1Options {
             batch_size: 2,
-            max_length: GPT_124M.context_length,
-            stride: GPT_124M.context_length,
+            max_length: c.context_length,
+            stride: c.context_length,
             drop_last: false,
             ..Default::default()
         };
@@ -61,13 +72,22 @@ fn main() -> anyhow::Result<()> {
 
     // 警告：Module::to_device 转移后的模型不再支持反向传播。
     // 详情参见 burn 的官方文档：https://docs.rs/burn/0.17.1/burn/module/trait.Module.html#tymethod.to_device
-    let model = GPT_124M.init(device);
+    let model = c.init(device);
 
     let optimizer = AdamWConfig::new().with_weight_decay(0.1).init::<B, GptModel<B>>();
 
+    let checkpointer = Checkpointer::new(CHECKPOINT_DIR, CheckpointMeta::new(c), cli.save_every);
+    let (model, optimizer, state) = if cli.resume {
+        checkpointer
+            .resume::<B, _>(optimizer, device)
+            .with_context(|| format!("resume from {CHECKPOINT_DIR}"))?
+    } else {
+        (model, optimizer, TrainState::new(123))
+    };
+
     let opts = TrainOpts {
         model,
-        train_loader: train_loader.as_ref(),
+        train_loader: &train_loader,
         val_loader: val_loader.as_ref(),
         optimizer,
         device: &device,
@@ -77,9 +97,11 @@ fn main() -> anyhow::Result<()> {
         start_context: "Every effort moves you",
         tokenizer: &tokenizer,
         lr: 0.0004,
+        checkpointer: &checkpointer,
+        state,
     };
 
-    let (model, ..) = train_model_simple(opts);
+    let (model, ..) = train_model_simple(opts)?;
 
     const MODEL_PATH: &str = "gpt_124m_trained.burn";
     let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
@@ -88,6 +110,16 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[derive(Parser)]
+struct Cli {
+    /// 每训练多少步保存一次训练检查点，为 0 时不保存。
+    #[clap(long, default_value_t = 10)]
+    save_every: usize,
+    /// 从上次保存的训练检查点继续训练。
+    #[clap(long)]
+    resume: bool,
+}
+
 struct TrainOpts<'a, B, O, T>
 where
     B: AutodiffBackend,
@@ -95,7 +127,7 @@ where
     T: Tokenizer<B::InnerBackend>,
 {
     model: GptModel<B>,
-    train_loader: &'a dyn DataLoader<B, Batch<B>>,
+    train_loader: &'a ResumableLoader<B, Batch<B>>,
     val_loader: &'a dyn DataLoader<B, Batch<B>>,
     optimizer: O,
     device: &'a B::Device,
@@ -105,6 +137,8 @@ where
     start_context: &'a str,
     tokenizer: &'a T,
     lr: LearningRate,
+    checkpointer: &'a Checkpointer,
+    state: TrainState,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -116,7 +150,7 @@ struct TrainOveriew {
 
 struct EvaluateOpts<'a, B: AutodiffBackend> {
     model: GptModel<B>,
-    train_loader: &'a dyn DataLoader<B, Batch<B>>,
+    train_loader: &'a ResumableLoader<B, Batch<B>>,
     val_loader: &'a dyn DataLoader<B, Batch<B>>,
     device: &'a B::Device,
     eval_iter: usize,
@@ -128,7 +162,9 @@ where
 {
     // TODO: 评估没有显式调用 valid 函数排除 dropout 的影响有多大。
     let model = opts.model.no_grad();
-    let train_loss = loss::calc_loss_loader(opts.train_loader, &model, opts.eval_iter.into(), opts.device);
+    let train_loss = opts
+        .train_loader
+        .eval(|v| loss::calc_loss_loader(v, &model, opts.eval_iter.into(), opts.device));
     let val_loss = loss::calc_loss_loader(opts.val_loader, &model, opts.eval_iter.into(), opts.device);
 
     (train_loss, val_loss)
@@ -148,7 +184,7 @@ where
     println!("{}", decoded_text.replace('\n', " "));
 }
 
-fn train_model_simple<B, O, T>(opts: TrainOpts<'_, B, O, T>) -> (GptModel<B>, O, TrainOveriew)
+fn train_model_simple<B, O, T>(opts: TrainOpts<'_, B, O, T>) -> anyhow::Result<(GptModel<B>, O, TrainOveriew)>
 where
     B: AutodiffBackend<FloatElem = f32>,
     O: Optimizer<GptModel<B>, B>,
@@ -166,53 +202,52 @@ where
         start_context,
         tokenizer,
         lr,
+        checkpointer,
+        mut state,
     } = opts;
 
-    let mut train_losses = vec![];
-    let mut val_losses = vec![];
-    let mut track_tokens_seen = vec![];
-
-    let mut token_seen = 0;
-    let eval_freq = eval_freq as i32;
-    let mut global_step = -1i32;
-
-    for epoch in 1..=epoches {
-        for (input_batch, target_batch) in train_loader.iter() {
+    for epoch in state.epochs_done + 1..=epoches {
+        for (input_batch, target_batch) in train_loader.epoch(&mut state) {
             let loss = loss::calc_loss_batch(input_batch.clone(), target_batch, &model, device);
 
             let grads = GradientsParams::from_grads(loss.backward(), &model);
             model = optimizer.step(lr, model, grads);
 
-            token_seen += input_batch.shape().num_elements();
-            global_step += 1;
-
-            if global_step % eval_freq != 0 {
-                continue;
+            state.tokens_seen += input_batch.shape().num_elements();
+            state.batches_done += 1;
+            state.step += 1;
+
+            let global_step = state.step - 1;
+            if global_step % eval_freq == 0 {
+                // 评估模型
+                let opts = EvaluateOpts {
+                    model: model.clone(),
+                    train_loader,
+                    val_loader,
+                    device,
+                    eval_iter,
+                };
+                let (train_loss, val_loss) = evaluate_model(opts);
+                state.train_losses.push(train_loss);
+                state.val_losses.push(val_loss);
+                state.track_tokens_seen.push(state.tokens_seen);
+                println!("Ep {epoch} (Step: {global_step:06}): Train Loss: {train_loss:.3}, Val Loss: {val_loss:.3}");
             }
 
-            // 评估模型
-            let opts = EvaluateOpts {
-                model: model.clone(),
-                train_loader,
-                val_loader,
-                device,
-                eval_iter,
-            };
-            let (train_loss, val_loss) = evaluate_model(opts);
-            train_losses.push(train_loss);
-            val_losses.push(val_loss);
-            track_tokens_seen.push(token_seen);
-            println!("Ep {epoch} (Step: {global_step:06}): Train Loss: {train_loss:.3}, Val Loss: {val_loss:.3}");
+            checkpointer
+                .on_step(&model, &optimizer, &mut state)
+                .context("save training checkpoint")?;
         }
 
         generate_and_print_sample(model.clone(), tokenizer, device, start_context);
+        state.finish_epoch();
     }
 
     let overview = TrainOveriew {
-        train_losses,
-        val_losses,
-        track_tokens_seen,
+        train_losses: state.train_losses,
+        val_losses: state.val_losses,
+        track_tokens_seen: state.track_tokens_seen,
     };
 
-    (model, optimizer, overview)
+    Ok((model, optimizer, overview))
 }
@@ -3,7 +3,7 @@ use burn::backend::libtorch::LibTorchDevice;
 use burn::backend::{Autodiff, LibTorch};
 use burn::prelude::*;
 use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
-use chapter05::config::GPT_124M;
+use chapter05::config;
 
 type B = Autodiff<LibTorch>;
 // type B = Autodiff<Cuda>;
@@ -13,7 +13,7 @@ fn main() -> anyhow::Result<()> {
 
     let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
 
-    let _model = GPT_124M
+    let _model = config::gpt2_small_256()
         .init::<B>(device)
         .load_file("gpt_124m_trained.burn", &recorder, device)
         .context("load model")?;
@@ -1,9 +1,10 @@
+use std::collections::HashSet;
 use std::path::Path;
 
 use anyhow::Context as _;
 use burn::backend::LibTorch;
 use burn::prelude::Backend;
-use chapter05::config::GPT_124M;
+use chapter05::contrastive::{self, ContrastiveSearchOptions};
 use chapter05::gpt2;
 use chapter05::utils::{self, GenerateOptions, Tokenizer as _};
 use tiktoken::ext::Encoding;
@@ -17,11 +18,7 @@ fn main() -> anyhow::Result<()> {
     let device = &Device::Cpu;
 
     let data_dir = Path::new("gpt2/124M");
-    let (settings, params) = gpt2::load_settings_and_params(&data_dir).expect("load gpt2 config");
-
-    let c = GPT_124M
-        .with_context_length(settings.context_length)
-        .with_qkv_bias(true);
+    let (c, params) = gpt2::load_settings_and_params(&data_dir).expect("load gpt2 config");
 
     let mut model = c.init::<B>(device);
 
@@ -36,9 +33,20 @@ fn main() -> anyhow::Result<()> {
         .with_topk(50.into())
         .with_temperature(1.5);
 
-    let token_ids = utils::generate(&model, idx, opts);
+    let token_ids = utils::generate(&model, idx, &opts).context("generate")?;
     let out = tokenizer.detokenize(token_ids).context("decode output")?;
     println!("Output text:\n{out}");
 
+    // 对比搜索是确定性的解码，且不像贪心解码那样很快陷入重复
+    let prompt = tokenizer.encode("Every effort moves you", &HashSet::new());
+    let opts = ContrastiveSearchOptions::new(25, c.context_length)
+        .with_top_k(4)
+        .with_alpha(0.6);
+    let tokens = contrastive::contrastive_search(&model, &prompt, &opts, device);
+    let out = tokenizer
+        .decode(&[prompt, tokens].concat())
+        .context("decode contrastive output")?;
+    println!("Contrastive search output:\n{}", String::from_utf8_lossy(&out));
+
     Ok(())
 }
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use anyhow::Context as _;
+use burn::backend::LibTorch;
+use burn::module::Module;
+use burn::nn::LinearConfig;
+use burn::prelude::Backend;
+use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
+use chapter04::{Config, GptModel, Preset};
+use chapter05::checkpoint;
+use chapter05::gpt2::gguf::{self, GgmlType, Vocab};
+use clap::Parser;
+use tiktoken::ext::Encoding;
+
+type B = LibTorch;
+
+type Device = <B as Backend>::Device;
+
+/// 把检查点目录或 `save_file` 保存的模型转换为 llama.cpp 使用的 GGUF 文件，词表取自 GPT-2 的分词器。例如
+/// `cargo run --bin export_gguf -- --type q8_0 --out gpt-355m-sft.gguf gpt-355m-model-sft`。
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let device = &Device::Cpu;
+
+    let (model, config) = if checkpoint::is_checkpoint(&cli.checkpoint) {
+        let (model, meta) = checkpoint::load_checkpoint::<B>(&cli.checkpoint, device)
+            .with_context(|| format!("load {:?}", cli.checkpoint))?;
+        (model, meta.config)
+    } else {
+        load_mpk(&cli, device)?
+    };
+
+    let eos_id = (checkpoint::GPT2_EOS_ID < config.vocab_size as u32).then_some(checkpoint::GPT2_EOS_ID);
+    let vocab = Vocab::from_tokenizer(&Encoding::gpt2(), config.vocab_size, eos_id).context("build vocab")?;
+
+    gguf::save(&model, &config, Some(&vocab), cli.r#type, &cli.out)
+        .with_context(|| format!("save to {:?}", cli.out))?;
+    println!("saved to {}", cli.out.display());
+
+    Ok(())
+}
+
+/// 按命令行参数创建模型，加载 `save_file` 保存的参数。
+fn load_mpk(cli: &Cli, device: &Device) -> anyhow::Result<(GptModel<B>, Config)> {
+    let config = match &cli.config {
+        Some(p) => {
+            <Config as burn::config::Config>::load(p).with_context(|| format!("load model config from {p:?}"))?
+        }
+        None => cli.preset.config(),
+    };
+
+    let mut model = config.init::<B>(device);
+    if let Some(n) = cli.num_classes {
+        model.out_head = LinearConfig::new(config.emb_dim, n).with_bias(true).init(device);
+    }
+
+    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+    let model = model
+        .load_file(&cli.checkpoint, &recorder, device)
+        .with_context(|| format!("load {:?}", cli.checkpoint))?;
+
+    Ok((model, config))
+}
+
+#[derive(Parser)]
+struct Cli {
+    /// `save_checkpoint` 保存的检查点目录，或者以 `save_file` 保存的模型（.mpk）。后者的模型结构由 `--config`、
+    /// `--preset` 和 `--num-classes` 决定。
+    checkpoint: PathBuf,
+    /// 输出的 GGUF 文件。
+    #[clap(long)]
+    out: PathBuf,
+    /// 模型配置（JSON）。
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// 未指定 `--config` 时的预设模型规模。
+    #[clap(long, default_value_t = Preset::Gpt2Small)]
+    preset: Preset,
+    /// 分类模型的类别数。指定时输出层为带偏置的分类层，和第 6 章的分类模型相同。
+    #[clap(long)]
+    num_classes: Option<usize>,
+    /// 矩阵参数的数据类型：f32、f16、q8_0 或 q4_0。偏置和层归一化参数总是保存为 f32。
+    #[clap(long, default_value_t = GgmlType::F32)]
+    r#type: GgmlType,
+}
@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+
+use anyhow::Context as _;
+use burn::backend::LibTorch;
+use burn::module::Module;
+use burn::prelude::Backend;
+use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
+use chapter04::{Config, GptModel, Preset};
+use chapter05::checkpoint;
+use chapter05::gpt2::hf;
+use clap::Parser;
+
+type B = LibTorch;
+
+type Device = <B as Backend>::Device;
+
+/// 把检查点目录或 `save_file` 保存的模型转换为 Hugging Face 格式的检查点，例如第 7 章微调的模型
+/// `cargo run --bin export_hf -- --out gpt-355m-sft gpt-355m-model-sft`。第 6 章的分类模型没有对应的
+/// `GPT2LMHeadModel` 结构，不能转换。
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let device = &Device::Cpu;
+
+    let (model, config) = if checkpoint::is_checkpoint(&cli.checkpoint) {
+        let (model, meta) = checkpoint::load_checkpoint::<B>(&cli.checkpoint, device)
+            .with_context(|| format!("load {:?}", cli.checkpoint))?;
+        (model, meta.config)
+    } else {
+        load_mpk(&cli, device)?
+    };
+
+    hf::save(&model, &config, &cli.out).with_context(|| format!("save to {:?}", cli.out))?;
+    println!("saved to {}", cli.out.display());
+
+    Ok(())
+}
+
+/// 按命令行参数创建模型，加载 `save_file` 保存的参数。
+fn load_mpk(cli: &Cli, device: &Device) -> anyhow::Result<(GptModel<B>, Config)> {
+    let config = match &cli.config {
+        Some(p) => {
+            <Config as burn::config::Config>::load(p).with_context(|| format!("load model config from {p:?}"))?
+        }
+        None => cli.preset.config(),
+    };
+
+    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+    let model = config
+        .init::<B>(device)
+        .load_file(&cli.checkpoint, &recorder, device)
+        .with_context(|| format!("load {:?}", cli.checkpoint))?;
+
+    Ok((model, config))
+}
+
+#[derive(Parser)]
+struct Cli {
+    /// `save_checkpoint` 保存的检查点目录，或者以 `save_file` 保存的模型（.mpk）。后者的模型结构由 `--config` 或
+    /// `--preset` 决定。
+    checkpoint: PathBuf,
+    /// 输出目录，保存 config.json 和 model.safetensors。
+    #[clap(long)]
+    out: PathBuf,
+    /// 模型配置（JSON）。
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// 未指定 `--config` 时的预设模型规模。
+    #[clap(long, default_value_t = Preset::Gpt2Small)]
+    preset: Preset,
+}
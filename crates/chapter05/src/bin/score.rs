@@ -0,0 +1,178 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use burn::backend::LibTorch;
+use burn::prelude::Backend;
+use chapter04::{Config, GptModel, Preset};
+use chapter05::checkpoint::{self, Head};
+use chapter05::gpt2;
+use chapter05::score::{self, Score, ScoreOptions};
+use clap::Parser;
+use serde::Deserialize;
+use tiktoken::ext::Encoding;
+
+type B = LibTorch;
+
+type Device = <B as Backend>::Device;
+
+/// 计算文本的困惑度，比较多个检查点在验证数据上的表现。例如
+/// `cargo run --bin score -- --checkpoint a.mpk --checkpoint b.mpk the-verdict.txt`。
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let device = &Device::Cpu;
+    let tokenizer = Encoding::gpt2();
+
+    anyhow::ensure!(cli.max_length.is_none_or(|v| v >= 2), "--max-length must be at least 2");
+    let items = load_items(&cli.input).context("load input")?;
+
+    let config = match &cli.config {
+        Some(p) => {
+            <Config as burn::config::Config>::load(p).with_context(|| format!("load model config from {p:?}"))?
+        }
+        None => cli.preset.config(),
+    };
+    let sources: Vec<_> = cli
+        .gpt2_dir
+        .iter()
+        .map(|v| (v, true))
+        .chain(cli.checkpoint.iter().map(|v| (v, false)))
+        .collect();
+    anyhow::ensure!(
+        !sources.is_empty(),
+        "at least one --checkpoint or --gpt2-dir is required"
+    );
+
+    println!("{:<40}{:>10}{:>12}{:>12}", "model", "tokens", "nll", "ppl");
+    for (path, is_gpt2) in sources {
+        let (model, context_length) = if is_gpt2 {
+            load_gpt2(path, device).with_context(|| format!("load gpt2 params from {path:?}"))?
+        } else if checkpoint::is_checkpoint(path) {
+            let (model, meta) =
+                checkpoint::load_checkpoint::<B>(path, device).with_context(|| format!("load {path:?}"))?;
+            anyhow::ensure!(
+                meta.head == Head::LanguageModel,
+                "{path:?} is not a language model: {:?}",
+                meta.head
+            );
+            (model, meta.config.context_length)
+        } else {
+            let path_str = path.to_str().context("checkpoint path must be utf-8")?;
+            let model = config
+                .load::<B>(path_str, device)
+                .with_context(|| format!("load {path:?}"))?;
+            (model, config.context_length)
+        };
+
+        let max_length = cli.max_length.unwrap_or(context_length).min(context_length);
+        let stride = cli.stride.unwrap_or(max_length / 2);
+        anyhow::ensure!(
+            (1..max_length).contains(&stride),
+            "--stride must be in [1, {max_length}) for {path:?}, got {stride}"
+        );
+        let opts = ScoreOptions::new(max_length, stride);
+
+        let scores: Vec<Score> = items
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let s = match v {
+                    Item::Text { text } => score::score_text(&model, &tokenizer, text, &opts, device),
+                    Item::Continuation { context, continuation } => {
+                        score::score_continuation(&model, &tokenizer, context, continuation, &opts, device)
+                    }
+                };
+                if cli.verbose {
+                    println!(
+                        "  #{i}: tokens={}, nll={:.4}, ppl={:.4}",
+                        s.num_tokens(),
+                        s.nll(),
+                        s.perplexity()
+                    );
+                }
+                s
+            })
+            .collect();
+
+        let total = Score::merge(scores);
+        println!(
+            "{:<40}{:>10}{:>12.4}{:>12.4}",
+            path.display(),
+            total.num_tokens(),
+            total.mean_nll(),
+            total.perplexity()
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Parser)]
+struct Cli {
+    /// 待计分的文本文件。扩展名为 .jsonl 时每行为 `{"text": ...}` 或 `{"context": ..., "continuation": ...}`，
+    /// 后者只对 continuation 计分。
+    input: PathBuf,
+    /// 以 `save_file` 保存的模型（.mpk）或者 `save_checkpoint` 保存的检查点目录，可以指定多个。.mpk 的模型结构由
+    /// `--config` 或 `--preset` 决定，检查点目录自带配置。
+    #[clap(long)]
+    checkpoint: Vec<PathBuf>,
+    /// GPT-2 预训练参数目录（例如 gpt2/124M）、Hugging Face 格式的检查点目录（包含 model.safetensors）或者
+    /// GGUF 文件（.gguf），可以指定多个。
+    #[clap(long)]
+    gpt2_dir: Vec<PathBuf>,
+    /// 检查点的模型配置（JSON）。
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// 未指定 `--config` 时检查点的预设模型规模。
+    #[clap(long, default_value_t = Preset::Gpt2Small)]
+    preset: Preset,
+    /// 滑动窗口的长度，默认为模型的上下文长度。
+    #[clap(long)]
+    max_length: Option<usize>,
+    /// 滑动窗口的步长，默认为窗口长度的一半。
+    #[clap(long)]
+    stride: Option<usize>,
+    /// 输出每个样本的得分。
+    #[clap(long)]
+    verbose: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Item {
+    Text { text: String },
+    Continuation { context: String, continuation: String },
+}
+
+fn load_items(path: &Path) -> anyhow::Result<Vec<Item>> {
+    let text = fs::read_to_string(path).context("read file")?;
+    if path.extension().is_none_or(|v| v != "jsonl") {
+        return Ok(vec![Item::Text { text }]);
+    }
+
+    text.lines()
+        .enumerate()
+        .filter(|(_, v)| !v.trim().is_empty())
+        .map(|(i, v)| serde_json::from_str(v).with_context(|| format!("decode line {}", i + 1)))
+        .collect()
+}
+
+fn load_gpt2(dir: &Path, device: &Device) -> anyhow::Result<(GptModel<B>, usize)> {
+    if dir.extension().is_some_and(|v| v == "gguf") {
+        let (c, model, _) = gpt2::gguf::load::<B>(dir, device).context("load gguf checkpoint")?;
+        return Ok((model, c.context_length));
+    }
+
+    if dir.join(gpt2::hf::WEIGHTS_FILE).exists() {
+        let (c, model) = gpt2::hf::load::<B>(dir, device).context("load hugging face checkpoint")?;
+        return Ok((model, c.context_length));
+    }
+
+    let (c, params) = gpt2::load_settings_and_params(dir).context("load gpt2 config")?;
+
+    let mut model = c.init::<B>(device);
+    gpt2::load_weights_into_gpt2(params, &mut model).context("load weights into model")?;
+
+    Ok((model, c.context_length))
+}
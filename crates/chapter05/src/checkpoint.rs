@@ -0,0 +1,168 @@
+//! 自描述的模型检查点：一个目录，包含参数文件 [`WEIGHTS_FILE`] 和描述文件 [`META_FILE`]。描述文件记录模型配置、
+//! 输出层类型、分词器以及训练概况，[`load_checkpoint`] 据此创建结构相同的模型并加载参数，不需要调用方重新构造配置。
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use burn::config::Config as _;
+use burn::module::Module;
+use burn::nn::LinearConfig;
+use burn::prelude::*;
+use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
+use chapter04::GptModel;
+use serde::{Deserialize, Serialize};
+use tiktoken::ext::Encoding;
+
+pub const META_FILE: &str = "checkpoint.json";
+pub const WEIGHTS_FILE: &str = "model.mpk";
+
+/// GPT-2 分词器中 `<|endoftext|>` 的 ID。
+pub const GPT2_EOS_ID: u32 = 50256;
+
+/// 检查点的描述信息。
+#[derive(Config, Debug)]
+pub struct CheckpointMeta {
+    /// 创建模型的配置。
+    pub config: chapter04::Config,
+    #[config(default = "Head::LanguageModel")]
+    pub head: Head,
+    #[config(default = "TokenizerInfo::gpt2()")]
+    pub tokenizer: TokenizerInfo,
+    pub training: Option<TrainingSummary>,
+}
+
+/// 模型的输出层。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Head {
+    /// 输出词表上的 logits，没有偏置。
+    LanguageModel,
+    /// 第 6 章的分类层，带有偏置。
+    Classifier { num_classes: usize },
+}
+
+/// 分词器的名称以及特殊 token 的 ID。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenizerInfo {
+    /// 目前只支持 `gpt2`。
+    pub name: String,
+    pub eos_id: Option<u32>,
+    pub pad_id: Option<u32>,
+}
+
+/// 训练概况，各项指标为训练结束时的值。
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrainingSummary {
+    pub epochs: usize,
+    pub steps: usize,
+    pub examples_seen: Option<usize>,
+    pub tokens_seen: Option<usize>,
+    pub learning_rate: Option<f64>,
+    pub train_loss: Option<f32>,
+    pub val_loss: Option<f32>,
+    pub train_accuracy: Option<f32>,
+    pub val_accuracy: Option<f32>,
+    pub elapsed_secs: Option<f64>,
+}
+
+impl TokenizerInfo {
+    /// GPT-2 的分词器，EOS 和填充 token 都是 `<|endoftext|>`。
+    pub fn gpt2() -> Self {
+        Self {
+            name: "gpt2".to_owned(),
+            eos_id: Some(GPT2_EOS_ID),
+            pad_id: Some(GPT2_EOS_ID),
+        }
+    }
+
+    pub fn load(&self) -> anyhow::Result<Encoding> {
+        match self.name.as_str() {
+            "gpt2" => Ok(Encoding::gpt2()),
+            v => anyhow::bail!("unsupported tokenizer '{v}'"),
+        }
+    }
+}
+
+impl CheckpointMeta {
+    /// 按配置和输出层创建模型，参数为随机初始化的值。
+    pub fn init<B: Backend>(&self, device: &B::Device) -> GptModel<B> {
+        let mut model = self.config.init::<B>(device);
+        if let Head::Classifier { num_classes } = self.head {
+            model.out_head = LinearConfig::new(self.config.emb_dim, num_classes)
+                .with_bias(true)
+                .init(device);
+        }
+        model
+    }
+}
+
+/// 把模型和描述信息保存到目录 `dir`。
+pub fn save_checkpoint<B: Backend>(model: &GptModel<B>, meta: &CheckpointMeta, dir: &Path) -> anyhow::Result<()> {
+    let head = model.out_head.weight.dims()[1];
+    let expected = match meta.head {
+        Head::LanguageModel => meta.config.vocab_size,
+        Head::Classifier { num_classes } => num_classes,
+    };
+    anyhow::ensure!(
+        head == expected,
+        "out_head has {head} outputs, but {:?} expects {expected}",
+        meta.head
+    );
+
+    fs::create_dir_all(dir).context("create dir")?;
+    meta.save(dir.join(META_FILE)).context("save meta")?;
+
+    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+    model
+        .clone()
+        .save_file(dir.join(WEIGHTS_FILE), &recorder)
+        .context("save weights")
+}
+
+/// 读取 `dir` 中的描述信息，创建模型并加载参数。
+pub fn load_checkpoint<B: Backend>(dir: &Path, device: &B::Device) -> anyhow::Result<(GptModel<B>, CheckpointMeta)> {
+    let meta = load_meta(dir)?;
+
+    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+    let model = meta
+        .init::<B>(device)
+        .load_file(dir.join(WEIGHTS_FILE), &recorder, device)
+        .context("load weights")?;
+
+    Ok((model, meta))
+}
+
+/// 和 [`load_checkpoint`] 相同，但 `dir` 不是检查点目录而存在旧版本以 `save_file` 保存的 `<dir>.mpk` 时，按 `legacy`
+/// 返回的描述信息创建模型并加载该文件。
+pub fn load_checkpoint_or_mpk<B: Backend>(
+    dir: &Path,
+    legacy: impl FnOnce() -> CheckpointMeta,
+    device: &B::Device,
+) -> anyhow::Result<(GptModel<B>, CheckpointMeta)> {
+    let mut mpk = dir.as_os_str().to_owned();
+    mpk.push(".mpk");
+    let mpk = PathBuf::from(mpk);
+    if is_checkpoint(dir) || !mpk.is_file() {
+        return load_checkpoint(dir, device);
+    }
+
+    let meta = legacy();
+    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+    let model = meta
+        .init::<B>(device)
+        .load_file(&mpk, &recorder, device)
+        .with_context(|| format!("load legacy weights {mpk:?}"))?;
+
+    Ok((model, meta))
+}
+
+/// 只读取 `dir` 中的描述信息。
+pub fn load_meta(dir: &Path) -> anyhow::Result<CheckpointMeta> {
+    let p = dir.join(META_FILE);
+    CheckpointMeta::load(&p).with_context(|| format!("load checkpoint meta from {p:?}"))
+}
+
+/// `p` 是否为 [`save_checkpoint`] 保存的目录。
+pub fn is_checkpoint(p: &Path) -> bool {
+    p.join(META_FILE).is_file()
+}
@@ -1,11 +1,11 @@
-use chapter04::Config;
+use chapter04::{Config, Preset};
 
-pub static GPT_124M: &Config = &Config {
-    vocab_size: 50257,
-    context_length: 256,
-    emb_dim: 768,
-    nheads: 12,
-    nlayers: 12,
-    drop_rate: 0.1,
-    qkv_bias: false,
-};
+/// 本章从头训练使用的配置：[`Preset::Gpt2Small`] 的结构，为了减少计算量上下文长度缩短为 256，QKV 不带偏置，
+/// 参数使用 burn 默认的初始化方法。
+pub fn gpt2_small_256() -> Config {
+    Preset::Gpt2Small
+        .config()
+        .with_context_length(256)
+        .with_qkv_bias(false)
+        .with_init(None)
+}
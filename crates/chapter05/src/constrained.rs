@@ -0,0 +1,269 @@
+//! 受约束的解码：每一步只允许生成使已生成的文本仍可能完整匹配正则表达式的 token，用于生成 JSON 或固定的标签。
+//!
+//! 正则表达式编译为从头开始匹配的 DFA，按分词器的词表预先计算每个 DFA 状态下允许的 token。JSON 输出通过
+//! [`json_schema_to_regex`] 把 JSON schema 的一个子集转换为正则表达式。
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+use anyhow::Context as _;
+use burn::prelude::*;
+use regex_automata::dfa::dense::{self, DFA};
+use regex_automata::dfa::{Automaton, StartKind};
+use regex_automata::util::primitives::StateID;
+use regex_automata::util::start;
+use regex_automata::{Anchored, MatchKind};
+use serde_json::Value;
+use tiktoken::ext::Encoding;
+
+use crate::sampling::{GenerateState, LogitsProcessor, LogitsProcessors};
+use crate::utils::GenerateOptions;
+
+/// 生成的文本需要完整匹配的约束。只有已生成的文本完整匹配时才允许生成 EOS，所以以 EOS 结束的输出都满足约束；
+/// 达到生成长度上限而结束的输出可能只是匹配的前缀。
+///
+/// 作为 [`LogitsProcessor`] 使用时应当最先应用，参见 [`Constraint::processors`]。
+pub struct Constraint {
+    dfa: DFA<Vec<u32>>,
+    start: StateID,
+    /// 可以到达完整匹配的状态。
+    live: HashSet<StateID>,
+    /// 第 i 个元素为 token i 对应的字节，无法解码的 token 为空。
+    vocab: Vec<Vec<u8>>,
+    eos_id: u32,
+    /// 各状态下允许的 token。
+    allowed: RefCell<HashMap<StateID, Rc<Vec<u32>>>>,
+}
+
+impl Constraint {
+    /// 以 `tokenizer` 的前 `vocab_size` 个 token 为词表编译正则表达式 `pattern`。生成时应当把 `eos_id` 设为 EOS。
+    pub fn regex(pattern: &str, tokenizer: &Encoding, vocab_size: usize, eos_id: u32) -> anyhow::Result<Self> {
+        let dfa = dense::Builder::new()
+            .configure(
+                dense::Config::new()
+                    .match_kind(MatchKind::All)
+                    .start_kind(StartKind::Anchored),
+            )
+            .build(&format!(r"(?:{pattern})\z"))
+            .with_context(|| format!("compile regex '{pattern}'"))?;
+        let start = dfa
+            .start_state(&start::Config::new().anchored(Anchored::Yes))
+            .context("get start state")?;
+
+        let vocab = (0..vocab_size as u32)
+            .map(|id| {
+                if id == eos_id {
+                    vec![]
+                } else {
+                    tokenizer.decode(&[id]).unwrap_or_default()
+                }
+            })
+            .collect();
+
+        let live = live_states(&dfa, start);
+
+        Ok(Self {
+            dfa,
+            start,
+            live,
+            vocab,
+            eos_id,
+            allowed: RefCell::default(),
+        })
+    }
+
+    /// 按 [`json_schema_to_regex`] 把 `schema` 转换为正则表达式后编译。
+    pub fn json_schema(schema: &Value, tokenizer: &Encoding, vocab_size: usize, eos_id: u32) -> anyhow::Result<Self> {
+        let pattern = json_schema_to_regex(schema).context("convert json schema to regex")?;
+        Self::regex(&pattern, tokenizer, vocab_size, eos_id)
+    }
+
+    /// `text` 是否完整匹配。
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        self.walk(self.start, text).is_some_and(|s| self.is_accepting(s))
+    }
+
+    /// 已生成 `generated` 后允许的下一个 token，按 id 升序排列。`generated` 不满足约束时只允许 EOS。
+    pub fn allowed_tokens(&self, generated: &[u32]) -> Rc<Vec<u32>> {
+        let state = generated.iter().try_fold(self.start, |s, &id| {
+            let bytes = self.vocab.get(id as usize)?;
+            self.walk(s, bytes)
+        });
+        let Some(state) = state.filter(|s| self.live.contains(s)) else {
+            return Rc::new(vec![self.eos_id]);
+        };
+
+        let mut cache = self.allowed.borrow_mut();
+        let allowed = cache.entry(state).or_insert_with(|| {
+            let mut out: Vec<u32> = (0..self.vocab.len() as u32)
+                .filter(|&id| {
+                    let bytes = &self.vocab[id as usize];
+                    !bytes.is_empty() && self.walk(state, bytes).is_some_and(|s| self.live.contains(&s))
+                })
+                .collect();
+            if self.is_accepting(state) {
+                out.push(self.eos_id);
+                out.sort_unstable();
+            }
+            Rc::new(out)
+        });
+
+        allowed.clone()
+    }
+
+    /// 约束之后依次应用 [`LogitsProcessors::from_options`] 的处理。约束必须最先应用，否则 top-k 等过滤后可能
+    /// 没有允许的 token。
+    pub fn processors<B: Backend>(
+        self,
+        opts: &GenerateOptions,
+        device: &B::Device,
+    ) -> anyhow::Result<LogitsProcessors<B>> {
+        let rest = LogitsProcessors::from_options(opts, self.vocab.len(), device)?;
+        Ok(LogitsProcessors::new().with(self).with(rest))
+    }
+
+    /// 从状态 `s` 开始读入 `bytes`，不可能再匹配时返回 `None`。
+    fn walk(&self, mut s: StateID, bytes: &[u8]) -> Option<StateID> {
+        for &b in bytes {
+            s = self.dfa.next_state(s, b);
+            if self.dfa.is_dead_state(s) {
+                return None;
+            }
+        }
+        Some(s)
+    }
+
+    fn is_accepting(&self, s: StateID) -> bool {
+        self.dfa.is_match_state(self.dfa.next_eoi_state(s))
+    }
+}
+
+impl<B: Backend> LogitsProcessor<B> for Constraint {
+    fn process(&self, logits: Tensor<B, 2>, state: &GenerateState) -> Tensor<B, 2> {
+        let [batch_size, vocab_size] = logits.dims();
+
+        let mut v = vec![f32::NEG_INFINITY; batch_size * vocab_size];
+        for (i, row) in v.chunks_mut(vocab_size).enumerate() {
+            for &id in self.allowed_tokens(state.generated_row(i)).iter() {
+                row[id as usize] = 0.0;
+            }
+        }
+
+        let v = Tensor::<B, 1>::from_floats(v.as_slice(), &logits.device()).reshape([batch_size, vocab_size]);
+        logits + v
+    }
+}
+
+/// 从 `start` 可以到达的状态中，能够到达完整匹配的状态。
+fn live_states(dfa: &DFA<Vec<u32>>, start: StateID) -> HashSet<StateID> {
+    let mut reverse: HashMap<StateID, Vec<StateID>> = HashMap::new();
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    let mut live = HashSet::new();
+
+    while let Some(s) = queue.pop_front() {
+        if dfa.is_match_state(dfa.next_eoi_state(s)) {
+            live.insert(s);
+        }
+        for b in 0..=255 {
+            let next = dfa.next_state(s, b);
+            if dfa.is_dead_state(next) {
+                continue;
+            }
+            reverse.entry(next).or_default().push(s);
+            if seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let mut queue: VecDeque<_> = live.iter().copied().collect();
+    while let Some(s) = queue.pop_front() {
+        for &prev in reverse.get(&s).into_iter().flatten() {
+            if live.insert(prev) {
+                queue.push_back(prev);
+            }
+        }
+    }
+
+    live
+}
+
+/// 把 JSON schema 的一个子集转换为正则表达式，匹配的文本为紧凑的 JSON，`:` 和 `,` 之后可以有一个空格。
+///
+/// 支持：
+/// - `enum`、`const`；
+/// - `type` 为 `string`（`minLength`、`maxLength`）、`integer`、`number`、`boolean`、`null`；
+/// - `type` 为 `array`（`items`、`minItems`、`maxItems`）；
+/// - `type` 为 `object`（`properties`）。所有属性都必须出现，且按属性名排序。
+pub fn json_schema_to_regex(schema: &Value) -> anyhow::Result<String> {
+    const WS: &str = "[ ]?";
+
+    if let Some(values) = schema.get("enum") {
+        let values = values.as_array().context("enum must be an array")?;
+        let alts: Vec<_> = values.iter().map(|v| regex::escape(&v.to_string())).collect();
+        return Ok(format!("(?:{})", alts.join("|")));
+    }
+    if let Some(v) = schema.get("const") {
+        return Ok(regex::escape(&v.to_string()));
+    }
+
+    let ty = schema.get("type").and_then(Value::as_str).context("missing type")?;
+    let usize_of = |key: &str| schema.get(key).and_then(Value::as_u64).map(|v| v as usize);
+
+    let out = match ty {
+        "string" => {
+            const CHAR: &str = r#"(?:[^"\\\x00-\x1F]|\\["\\/bfnrt]|\\u[0-9a-fA-F]{4})"#;
+            let min = usize_of("minLength").unwrap_or_default();
+            match usize_of("maxLength") {
+                Some(max) => format!(r#""{CHAR}{{{min},{max}}}""#),
+                None => format!(r#""{CHAR}{{{min},}}""#),
+            }
+        }
+        "integer" => "-?(?:0|[1-9][0-9]*)".to_owned(),
+        "number" => "-?(?:0|[1-9][0-9]*)(?:\\.[0-9]+)?(?:[eE][+-]?[0-9]+)?".to_owned(),
+        "boolean" => "(?:true|false)".to_owned(),
+        "null" => "null".to_owned(),
+        "array" => {
+            let item = match schema.get("items") {
+                Some(v) => json_schema_to_regex(v).context("convert items")?,
+                None => anyhow::bail!("array without items is not supported"),
+            };
+            let min = usize_of("minItems").unwrap_or_default();
+            let max = usize_of("maxItems");
+            anyhow::ensure!(max.is_none_or(|max| min <= max), "minItems must not exceed maxItems");
+
+            // 第一个元素之后的元素重复 [min-1, max-1] 次
+            let rest = match max {
+                Some(max) => format!("{{{},{}}}", min.saturating_sub(1), max.saturating_sub(1)),
+                None => format!("{{{},}}", min.saturating_sub(1)),
+            };
+            let items = format!("{item}(?:,{WS}{item}){rest}");
+            match (min, max) {
+                (_, Some(0)) => r"\[\]".to_owned(),
+                (0, _) => format!(r"\[(?:{items})?\]"),
+                _ => format!(r"\[{items}\]"),
+            }
+        }
+        "object" => {
+            let props = match schema.get("properties") {
+                Some(v) => v.as_object().context("properties must be an object")?,
+                None => return Ok(r"\{\}".to_owned()),
+            };
+            let fields = props
+                .iter()
+                .map(|(k, v)| {
+                    let v = json_schema_to_regex(v).with_context(|| format!("convert property '{k}'"))?;
+                    Ok(format!(
+                        "{}:{WS}{v}",
+                        regex::escape(&Value::from(k.as_str()).to_string())
+                    ))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            format!(r"\{{{}\}}", fields.join(&format!(",{WS}")))
+        }
+        _ => anyhow::bail!("unsupported type '{ty}'"),
+    };
+
+    Ok(out)
+}
@@ -0,0 +1,99 @@
+//! 对比搜索（contrastive search）解码。
+//!
+//! 每一步从概率最高的 `top_k` 个候选中选择得分最高的 token，得分为
+//! `(1 - alpha) * 模型给出的概率 - alpha * 退化惩罚`，其中退化惩罚为候选 token 的最终隐藏状态（final_norm 的输出）
+//! 和上下文中各 token 的隐藏状态的最大余弦相似度。和上下文过于相似的候选会被惩罚，从而避免贪心解码的重复。
+use burn::prelude::*;
+use burn::tensor::activation;
+use chapter04::{ForwardOptions, GptModel};
+
+#[derive(Config, Debug)]
+pub struct ContrastiveSearchOptions {
+    pub max_new_tokens: usize,
+    /// 包括候选 token 在内的上下文长度，至少为 2。
+    pub context_size: usize,
+    /// 每一步的候选 token 数。
+    #[config(default = 4)]
+    pub top_k: usize,
+    /// 退化惩罚的权重，取值范围 [0, 1]。为 0 时等价于贪心解码。
+    #[config(default = 0.6)]
+    pub alpha: f32,
+    pub eos_id: Option<u32>,
+}
+
+/// 以 `prompt` 为输入进行对比搜索，返回生成的 token，不含输入和 EOS。
+///
+/// 每一步把 `top_k` 个候选分别接在上下文之后作为一个批量做一次前向计算，同时得到候选的隐藏状态和下一步的 logits。
+pub fn contrastive_search<B: Backend>(
+    model: &GptModel<B>,
+    prompt: &[u32],
+    opts: &ContrastiveSearchOptions,
+    device: &B::Device,
+) -> Vec<u32> {
+    assert!(!prompt.is_empty(), "prompt must not be empty");
+    assert!(opts.top_k > 0, "top_k must be positive");
+    assert!(opts.context_size >= 2, "context_size must be at least 2");
+    assert!((0.0..=1.0).contains(&opts.alpha), "alpha must be in [0, 1]");
+
+    let forward_opts = ForwardOptions::new().with_final_hidden(true);
+
+    // 第一个 token 的 logits 只需要对输入做一次前向计算
+    let ids = window(prompt, opts.context_size);
+    let idx = Tensor::<B, 1, Int>::from_ints(ids.as_slice(), device).unsqueeze::<2>();
+    let mut logits = model.forward(idx).slice(s![.., -1, ..]).squeeze::<2>(1);
+
+    let mut seq = prompt.to_vec();
+    for _ in 0..opts.max_new_tokens {
+        let [_, vocab_size] = logits.dims();
+        let k = opts.top_k.min(vocab_size);
+
+        let (probas, candidates) = activation::softmax(logits.clone(), 1).topk_with_indices(k, 1);
+        let candidates: Vec<i64> = candidates.into_data().iter::<i64>().collect();
+
+        // 每行为上下文接上一个候选，维度为 (k, seq-len)
+        let ctx = window(&seq, opts.context_size - 1);
+        let ids: Vec<i64> = candidates
+            .iter()
+            .flat_map(|&c| ctx.iter().copied().chain([c]))
+            .collect();
+        let seq_len = ctx.len() + 1;
+        let idx = Tensor::<B, 1, Int>::from_ints(ids.as_slice(), device).reshape([k, seq_len]);
+
+        let out = model.forward_with_outputs(idx, forward_opts);
+        let hidden = out.final_hidden.expect("final hidden states");
+        let [_, _, emb_dim] = hidden.dims();
+
+        // 候选和上下文各 token 的隐藏状态的余弦相似度，维度为 (k, seq-len - 1)
+        let hidden = hidden.clone() / hidden.powf_scalar(2.0).sum_dim(2).sqrt().clamp_min(1e-12);
+        let cand = hidden.clone().slice(s![.., -1, ..]).reshape([k, emb_dim, 1]);
+        let ctx_hidden = hidden.slice(s![.., ..seq_len - 1, ..]);
+        let degeneration = ctx_hidden
+            .matmul(cand)
+            .reshape([k, seq_len - 1])
+            .max_dim(1)
+            .reshape([1, k]);
+
+        let scores = probas.mul_scalar(1.0 - opts.alpha) - degeneration.mul_scalar(opts.alpha);
+        let scores: Vec<f32> = scores.into_data().convert::<f32>().to_vec().expect("read scores");
+        // 得分相同时选择概率较高的候选
+        let best = scores
+            .iter()
+            .enumerate()
+            .fold(0, |best, (i, v)| if *v > scores[best] { i } else { best });
+
+        let token = candidates[best] as u32;
+        if opts.eos_id == Some(token) {
+            break;
+        }
+        seq.push(token);
+
+        logits = out.logits.slice(s![best..best + 1, -1, ..]).squeeze::<2>(1);
+    }
+
+    seq.split_off(prompt.len())
+}
+
+/// `seq` 末尾最多 `n` 个 token。
+fn window(seq: &[u32], n: usize) -> Vec<i64> {
+    seq[seq.len().saturating_sub(n)..].iter().map(|&v| v as i64).collect()
+}
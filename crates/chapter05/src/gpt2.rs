@@ -1,14 +1,17 @@
 use std::fs::{self, File};
 use std::io::BufReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context as _;
 use burn::module::{Module, Param};
 use burn::prelude::Backend;
-use burn::tensor::Tensor;
-use chapter04::{Config, GptModel};
+use burn::tensor::{Tensor, TensorData};
+use chapter04::{Config, GptModel, Preset};
 
-use crate::config::GPT_124M;
+pub mod gguf;
+pub mod hf;
+pub mod npy;
+pub mod tf;
 
 // Settings: {'n_vocab': 50257, 'n_ctx': 1024, 'n_embd': 768, 'n_head': 12, 'n_layer': 12}
 // Parameter dictionary keys: dict_keys(['blocks', 'b', 'g', 'wpe', 'wte'])
@@ -26,16 +29,16 @@ use crate::config::GPT_124M;
 //  [ 0.05135201 -0.02768905  0.0499369  ...  0.00704835  0.15519823
 //    0.12067825]]
 // Token embedding weight tensor dimensions: (50257, 768)
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, PartialEq, serde::Deserialize)]
 pub struct Params {
     pub blocks: Vec<Block>,
     pub g: Vec<f32>,
     pub b: Vec<f32>,
-    pub wte: Vec<Vec<f32>>,
-    pub wpe: Vec<Vec<f32>>,
+    pub wte: Matrix,
+    pub wpe: Matrix,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, PartialEq, serde::Deserialize)]
 pub struct Block {
     pub attn: Attn,       // Attention weights
     pub mlp: FeedForward, // Feed-forward network weights
@@ -43,70 +46,217 @@ pub struct Block {
     pub ln_2: LayerNorm,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, PartialEq, serde::Deserialize)]
 pub struct Attn {
     pub c_attn: AttnQkv,
     pub c_proj: AttnOutProj, // Projection weights for attention
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, PartialEq, serde::Deserialize)]
 pub struct AttnQkv {
-    pub w: Vec<Vec<f32>>, // Weights for the attention layer
-    pub b: Vec<f32>,      // Bias for the attention layer
+    pub w: Matrix,   // Weights for the attention layer
+    pub b: Vec<f32>, // Bias for the attention layer
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, PartialEq, serde::Deserialize)]
 pub struct AttnOutProj {
-    pub w: Vec<Vec<f32>>, // Weights for the output projection
-    pub b: Vec<f32>,      // Bias for the output projection
+    pub w: Matrix,   // Weights for the output projection
+    pub b: Vec<f32>, // Bias for the output projection
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, PartialEq, serde::Deserialize)]
 pub struct FeedForward {
     pub c_fc: FeedForwardWb,
     pub c_proj: FeedForwardWb,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, PartialEq, serde::Deserialize)]
 pub struct FeedForwardWb {
-    pub w: Vec<Vec<f32>>, // Weights for the feed-forward layer
-    pub b: Vec<f32>,      // Bias for the feed-forward layer
+    pub w: Matrix,   // Weights for the feed-forward layer
+    pub b: Vec<f32>, // Bias for the feed-forward layer
+}
+
+/// 按行优先顺序存放在连续内存中的矩阵。从 JSON 反序列化时逐行追加，不会为每一行分别分配内存。
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Matrix {
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Vec<f32>,
+}
+
+impl Matrix {
+    pub fn new(rows: usize, cols: usize, data: Vec<f32>) -> Self {
+        assert_eq!(rows * cols, data.len(), "data length must be rows * cols");
+        Self { rows, cols, data }
+    }
+
+    pub fn row(&self, i: usize) -> &[f32] {
+        &self.data[i * self.cols..(i + 1) * self.cols]
+    }
 }
 
-#[derive(Debug, serde::Deserialize)]
+impl<'de> serde::Deserialize<'de> for Matrix {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct Rows;
+
+        impl<'de> serde::de::Visitor<'de> for Rows {
+            type Value = Matrix;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a list of rows")
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Matrix, A::Error> {
+                let mut out = Matrix::default();
+                while let Some(row) = seq.next_element::<Vec<f32>>()? {
+                    if out.rows == 0 {
+                        out.cols = row.len();
+                    } else if row.len() != out.cols {
+                        return Err(serde::de::Error::custom(format!(
+                            "bad #(cols) of {}-th row: expect {}, got {}",
+                            out.rows,
+                            out.cols,
+                            row.len()
+                        )));
+                    }
+                    out.data.extend(row);
+                    out.rows += 1;
+                }
+                Ok(out)
+            }
+        }
+
+        d.deserialize_seq(Rows)
+    }
+}
+
+#[derive(Debug, PartialEq, serde::Deserialize)]
 pub struct LayerNorm {
     pub g: Vec<f32>, // Weights for the feed-forward layer
     pub b: Vec<f32>, // Bias for the feed-forward layer
 }
 
-pub fn load_settings_and_params(data_dir: &Path) -> anyhow::Result<(Config, Params)> {
+/// 只读取 `data_dir` 中的 hparams.json，不读取参数。
+pub fn load_hparams(data_dir: &Path) -> anyhow::Result<Config> {
     let p = data_dir.join("hparams.json");
     if !p.exists() {
         return Err(anyhow::anyhow!("GPT-2 config file not found at {p:?}"));
     }
-    let c = load_settings(&p).context("load config")?;
+    let json = fs::read_to_string(&p).with_context(|| format!("read {p:?}"))?;
+
+    #[derive(serde::Deserialize)]
+    struct Gpt2Config {
+        n_vocab: usize,
+        n_ctx: usize,
+        n_embd: usize,
+        n_head: usize,
+        n_layer: usize,
+    }
+
+    let gpt2: Gpt2Config = serde_json::from_str(&json).context("json decode")?;
+
+    config_from_hparams(gpt2.n_vocab, gpt2.n_ctx, gpt2.n_embd, gpt2.n_head, gpt2.n_layer)
+}
+
+/// 读取 `data_dir` 中的 hparams.json 和参数。参数依次尝试 OpenAI 发布的 TensorFlow 检查点（model.ckpt.*）、
+/// `gpt2/main.py` 导出的 params-{size}.npz 和 params-{size}.json，其中模型规模 size（124m、355m、774m 或 1558m）
+/// 由 hparams.json 中的 n_embd、n_head 和 n_layer 推断，和目录名无关。不属于这四种规模的超参数（例如测试用的小模型）
+/// 要求目录中只有一个参数文件。
+pub fn load_settings_and_params(data_dir: &Path) -> anyhow::Result<(Config, Params)> {
+    let c = load_hparams(data_dir).context("load config")?;
+    let size = preset_from_hparams(c.emb_dim, c.nheads, c.nlayers).and_then(Preset::size);
 
-    // 从 data_dir 推断出模型规模
-    let size = match data_dir.file_name() {
-        Some(name) if name.eq_ignore_ascii_case("355m") => "355m",
-        _ => "124m", // 默认使用 124M 模型
+    let ckpt = data_dir.join(tf::CHECKPOINT_PREFIX);
+    let params = if tf::exists(&ckpt) {
+        tf::load_params(&ckpt).context("load tensorflow checkpoint")?
+    } else {
+        let p = find_params_file(data_dir, size).context("find params file")?;
+        if p.extension().is_some_and(|v| v == "npz") {
+            npy::load_params(&p).with_context(|| format!("load npz params from {p:?}"))?
+        } else {
+            load_params(&p).with_context(|| format!("load params from {p:?}"))?
+        }
     };
+    check_params(&params, &c).context("params do not match hparams.json")?;
 
-    let p = data_dir.join(format!("params-{size}.json"));
-    if !p.exists() {
-        return Err(anyhow::anyhow!("GPT-2 params file not found at {p:?}"));
+    Ok((c, params))
+}
+
+/// 查找 `data_dir` 中规模为 `size`（例如 `355M`）的参数文件，优先使用 .npz。
+fn find_params_file(data_dir: &Path, size: Option<&str>) -> anyhow::Result<PathBuf> {
+    let mut found = vec![];
+    for entry in fs::read_dir(data_dir).with_context(|| format!("read dir {data_dir:?}"))? {
+        let p = entry.context("read dir entry")?.path();
+        let is_params = p
+            .file_stem()
+            .and_then(|v| v.to_str())
+            .is_some_and(|v| v.starts_with("params-"))
+            && p.extension().is_some_and(|v| v == "npz" || v == "json");
+        if is_params {
+            found.push(p);
+        }
+    }
+    found.sort();
+    let names: Vec<_> = found.iter().filter_map(|p| p.file_name()?.to_str()).collect();
+
+    let Some(size) = size else {
+        return match found.as_slice() {
+            [p] => Ok(p.clone()),
+            [] => Err(anyhow::anyhow!("GPT-2 params file not found in {data_dir:?}")),
+            _ => Err(anyhow::anyhow!(
+                "hparams.json matches no GPT-2 size, can't tell which of {names:?} to load"
+            )),
+        };
+    };
+
+    let size = size.to_lowercase();
+    for ext in ["npz", "json"] {
+        let p = data_dir.join(format!("params-{size}.{ext}"));
+        if p.exists() {
+            return Ok(p);
+        }
     }
-    let p = load_params(&p).context("load params")?;
 
-    Ok((c, p))
+    if found.is_empty() {
+        anyhow::bail!("GPT-2 params file params-{size}.npz or params-{size}.json not found in {data_dir:?}");
+    }
+    anyhow::bail!("hparams.json describes GPT-2 {size} but {data_dir:?} only has {names:?}")
+}
+
+/// 检查参数的层数和嵌入矩阵的形状是否和配置一致。
+fn check_params(params: &Params, c: &Config) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        params.blocks.len() == c.nlayers,
+        "expect {} blocks (n_layer), got {}",
+        c.nlayers,
+        params.blocks.len()
+    );
+    anyhow::ensure!(
+        (params.wte.rows, params.wte.cols) == (c.vocab_size, c.emb_dim),
+        "expect wte of shape [{}, {}] (n_vocab, n_embd), got [{}, {}]",
+        c.vocab_size,
+        c.emb_dim,
+        params.wte.rows,
+        params.wte.cols
+    );
+    anyhow::ensure!(
+        (params.wpe.rows, params.wpe.cols) == (c.context_length, c.emb_dim),
+        "expect wpe of shape [{}, {}] (n_ctx, n_embd), got [{}, {}]",
+        c.context_length,
+        c.emb_dim,
+        params.wpe.rows,
+        params.wpe.cols
+    );
+
+    Ok(())
 }
 
 pub fn load_weights_into_gpt2<B: Backend>(params: Params, model: &mut GptModel<B>) -> anyhow::Result<()> {
     let device = &model.devices()[0].clone();
 
-    checked_assign_2d_param(&mut model.pos_emb.weight, &params.wpe, false).context("load positional embeddings")?;
+    checked_assign_2d_param(&mut model.pos_emb.weight, params.wpe).context("load positional embeddings")?;
 
-    checked_assign_2d_param(&mut model.tok_emb.weight, &params.wte, false).context("load token embeddings")?;
+    checked_assign_2d_param(&mut model.tok_emb.weight, params.wte).context("load token embeddings")?;
 
     anyhow::ensure!(
         model.trf_blocks.len() == params.blocks.len(),
@@ -114,8 +264,8 @@ pub fn load_weights_into_gpt2<B: Backend>(params: Params, model: &mut GptModel<B
         model.trf_blocks.len(),
         params.blocks.len()
     );
-    for (dst, src) in model.trf_blocks.iter_mut().zip(params.blocks.iter()) {
-        let (q_w, k_w, v_w) = tripple_split_2d(&src.attn.c_attn.w, device)?;
+    for (dst, src) in model.trf_blocks.iter_mut().zip(params.blocks) {
+        let (q_w, k_w, v_w) = tripple_split_2d(src.attn.c_attn.w, device)?;
         checked_assign_param(&mut dst.attn.wq.weight, q_w).context("load attention query weights")?;
         checked_assign_param(&mut dst.attn.wk.weight, k_w).context("load attention key weights")?;
         checked_assign_param(&mut dst.attn.wv.weight, v_w).context("load attention value weights")?;
@@ -129,16 +279,16 @@ pub fn load_weights_into_gpt2<B: Backend>(params: Params, model: &mut GptModel<B
             .context("load attention value bias")?;
 
         // pytorch 的线性层存的是转置，burn 存的是原始值。
-        checked_assign_2d_param(&mut dst.attn.out_proj.weight, &src.attn.c_proj.w, false)
+        checked_assign_2d_param(&mut dst.attn.out_proj.weight, src.attn.c_proj.w)
             .context("load attention out-proj weights")?;
         let b = dst.attn.out_proj.bias.as_mut().expect("miss out-proj bias");
         checked_assign_1d_param(b, &src.attn.c_proj.b).context("load out-proj bias")?;
 
-        checked_assign_2d_param(&mut dst.ff.linear1.weight, &src.mlp.c_fc.w, false)
+        checked_assign_2d_param(&mut dst.ff.linear1.weight, src.mlp.c_fc.w)
             .context("load feed-forward linear1 weights")?;
         let b = dst.ff.linear1.bias.as_mut().expect("miss ff.linear1 bias");
         checked_assign_1d_param(b, &src.mlp.c_fc.b).context("load ff.linear1 bias")?;
-        checked_assign_2d_param(&mut dst.ff.linear2.weight, &src.mlp.c_proj.w, false)
+        checked_assign_2d_param(&mut dst.ff.linear2.weight, src.mlp.c_proj.w)
             .context("load feed-forward linear2 weights")?;
         let b = dst.ff.linear2.bias.as_mut().expect("miss ff.linear2 bias");
         checked_assign_1d_param(b, &src.mlp.c_proj.b).context("load ff.linear2 bias")?;
@@ -151,7 +301,9 @@ pub fn load_weights_into_gpt2<B: Backend>(params: Params, model: &mut GptModel<B
 
     checked_assign_1d_param(&mut model.final_norm.scale, &params.g).context("load final_norm.scale")?;
     checked_assign_1d_param(&mut model.final_norm.shift, &params.b).context("load final_norm.shift")?;
-    checked_assign_2d_param(&mut model.out_head.weight, &params.wte, true).context("load out_head weights")?;
+    // 输出层和词嵌入共享参数，直接使用已加载的词嵌入
+    let wte = model.tok_emb.weight.val();
+    checked_assign_param(&mut model.out_head.weight, wte.transpose()).context("load out_head weights")?;
 
     Ok(())
 }
@@ -161,18 +313,9 @@ fn checked_assign_1d_param<B: Backend>(param: &mut Param<Tensor<B, 1>>, value: &
     checked_assign_param(param, value).context("assign")
 }
 
-fn checked_assign_2d_param<B: Backend>(
-    param: &mut Param<Tensor<B, 2>>,
-    value: &[Vec<f32>],
-    transposed: bool,
-) -> anyhow::Result<()> {
-    if transposed {
-        renew_param(param, |v| {
-            checked_new_2d_like(&value, &v.clone().transpose()).map(|v| v.transpose())
-        })
-    } else {
-        renew_param(param, |v| checked_new_2d_like(&value, &v.clone()))
-    }
+/// 参数的数据直接移动到张量中，不额外复制。
+fn checked_assign_2d_param<B: Backend>(param: &mut Param<Tensor<B, 2>>, value: Matrix) -> anyhow::Result<()> {
+    renew_param(param, |v| checked_new_2d_like(value, v))
 }
 
 fn checked_assign_param<B: Backend, const D: usize>(
@@ -203,31 +346,14 @@ fn checked_new_1d_like<B: Backend>(data: &[f32], like: &Tensor<B, 1>) -> anyhow:
     Ok(out)
 }
 
-fn checked_new_2d_like<B: Backend>(data: &[Vec<f32>], like: &Tensor<B, 2>) -> anyhow::Result<Tensor<B, 2>> {
+fn checked_new_2d_like<B: Backend>(data: Matrix, like: &Tensor<B, 2>) -> anyhow::Result<Tensor<B, 2>> {
     let device = &like.device();
 
-    let (row, col) = {
-        let dims = like.shape().dims;
-        (dims[0], dims[1])
-    };
-
-    anyhow::ensure!(row == data.len(), "bad #(rows): expect {}, got {}", row, data.len());
+    let [row, col] = like.dims();
+    anyhow::ensure!(row == data.rows, "bad #(rows): expect {}, got {}", row, data.rows);
+    anyhow::ensure!(col == data.cols, "bad #(cols): expect {}, got {}", col, data.cols);
 
-    let mut stacked = Vec::with_capacity(data.len());
-    for (i, v) in data.iter().enumerate() {
-        anyhow::ensure!(
-            v.len() == col,
-            "bad #(cols) of {}-th row: expect {}, got {}",
-            i,
-            col,
-            data[i].len()
-        );
-
-        let v = Tensor::<B, 1>::from_floats(v.as_slice(), device);
-        stacked.push(v);
-    }
-
-    let out = Tensor::stack::<2>(stacked, 0);
+    let out = Tensor::from_data(TensorData::new(data.data, [row, col]), device);
     Ok(out)
 }
 
@@ -237,32 +363,39 @@ fn load_params(p: &Path) -> anyhow::Result<Params> {
     serde_json::from_reader(BufReader::new(f)).context("json decode")
 }
 
-fn load_settings(p: &Path) -> anyhow::Result<Config> {
-    let json = fs::read_to_string(p).context("read file")?;
-
-    #[derive(serde::Deserialize)]
-    struct Gpt2Config {
-        n_vocab: usize,
-        n_ctx: usize,
-        n_embd: usize,
-        n_head: usize,
-        n_layer: usize,
-    }
-
-    let gpt2: Gpt2Config = serde_json::from_str(&json).context("json decode")?;
-
-    let mut out = GPT_124M.clone();
-    out.vocab_size = gpt2.n_vocab;
-    out.context_length = gpt2.n_ctx;
-    out.emb_dim = gpt2.n_embd;
-    out.nheads = gpt2.n_head;
-    out.nlayers = gpt2.n_layer;
+/// 按 GPT-2 的超参数构造配置，其余设置（QKV 偏置、初始化方案等）和预设的 GPT-2 模型相同。超参数不必属于某个
+/// 预设规模。
+fn config_from_hparams(
+    n_vocab: usize,
+    n_ctx: usize,
+    n_embd: usize,
+    n_head: usize,
+    n_layer: usize,
+) -> anyhow::Result<Config> {
+    anyhow::ensure!(
+        n_head > 0 && n_embd % n_head == 0,
+        "n_embd={n_embd} is not divisible by n_head={n_head}"
+    );
 
-    out.qkv_bias = true; // GPT-2 uses bias in QKV layers
+    let out = Preset::Gpt2Small
+        .config()
+        .with_vocab_size(n_vocab)
+        .with_context_length(n_ctx)
+        .with_emb_dim(n_embd)
+        .with_nheads(n_head)
+        .with_nlayers(n_layer);
 
     Ok(out)
 }
 
+/// 按 GPT-2 的超参数查找模型结构相同的预设规模。
+fn preset_from_hparams(n_embd: usize, n_head: usize, n_layer: usize) -> Option<Preset> {
+    Preset::ALL.into_iter().find(|p| {
+        let c = p.config();
+        c.emb_dim == n_embd && c.nheads == n_head && c.nlayers == n_layer
+    })
+}
+
 fn renew_param<B: Backend, const D: usize, F>(param: &mut Param<Tensor<B, D>>, f: F) -> anyhow::Result<()>
 where
     F: FnOnce(&Tensor<B, D>) -> anyhow::Result<Tensor<B, D>>,
@@ -286,34 +419,14 @@ fn tripple_split_1d<B: Backend>(
 }
 
 fn tripple_split_2d<B: Backend>(
-    data: &[Vec<f32>],
+    data: Matrix,
     device: &B::Device,
 ) -> anyhow::Result<(Tensor<B, 2>, Tensor<B, 2>, Tensor<B, 2>)> {
-    anyhow::ensure!(
-        data[0].len() % 3 == 0,
-        "bad #(cols)={} of 1st row, not multiple of 3",
-        data[0].len(),
-    );
-
-    let mut stacked = Vec::with_capacity(data.len());
-
-    let expected_cols = data[0].len();
-    for (i, v) in data.iter().enumerate() {
-        anyhow::ensure!(
-            v.len() == expected_cols,
-            "bad #(cols) of {}-th row, expect {}, got {}",
-            i,
-            expected_cols,
-            v.len(),
-        );
-
-        let v = Tensor::<B, 1>::from_floats(v.as_slice(), device);
-        stacked.push(v);
-    }
+    anyhow::ensure!(data.cols % 3 == 0, "bad #(cols)={}, not multiple of 3", data.cols,);
 
-    let t = Tensor::stack::<2>(stacked, 0);
+    let t = Tensor::<B, 2>::from_data(TensorData::new(data.data, [data.rows, data.cols]), device);
 
-    let splits = t.split(expected_cols / 3, 1);
+    let splits = t.split(data.cols / 3, 1);
 
     Ok((splits[0].clone(), splits[1].clone(), splits[2].clone()))
 }
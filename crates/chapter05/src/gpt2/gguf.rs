@@ -0,0 +1,916 @@
+//! 读写 llama.cpp 使用的 GGUF 格式的 GPT-2 检查点，参见
+//! <https://github.com/ggml-org/ggml/blob/master/docs/gguf.md>。
+//!
+//! 超参数保存为 `gpt2.*` 元数据，词表保存为 `tokenizer.ggml.*` 元数据，参数名和 llama.cpp 的 GPT-2 模型一致（例如
+//! `blk.0.attn_qkv.weight`）。ggml 的矩阵以 (out, in) 的顺序存储，和 burn 线性层的 (in, out) 互为转置。读取时支持
+//! F32、F16、Q8_0 和 Q4_0 格式的参数，加载后均转换为 F32。
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Context as _;
+use burn::module::Module;
+use burn::prelude::*;
+use chapter04::{Config, GptModel};
+use tiktoken::ext::Encoding;
+
+use super::checked_assign_param;
+use super::hf::{is_tied, split_qkv};
+
+const MAGIC: &[u8] = b"GGUF";
+const VERSION: u32 = 3;
+const DEFAULT_ALIGNMENT: usize = 32;
+/// Q8_0 和 Q4_0 每块的元素个数。
+const QK: usize = 32;
+
+const ARCH: &str = "gpt2";
+/// llama.cpp 的 token 类型：普通 token 和控制 token（例如 `<|endoftext|>`）。
+const TOKEN_TYPE_NORMAL: i32 = 1;
+const TOKEN_TYPE_CONTROL: i32 = 3;
+
+/// 参数的数据类型，取值和 ggml 的 `ggml_type` 相同。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GgmlType {
+    F32 = 0,
+    F16 = 1,
+    Q4_0 = 2,
+    Q8_0 = 8,
+}
+
+impl GgmlType {
+    pub const ALL: [GgmlType; 4] = [GgmlType::F32, GgmlType::F16, GgmlType::Q4_0, GgmlType::Q8_0];
+
+    pub fn from_u32(v: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| *t as u32 == v)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::F32 => "f32",
+            Self::F16 => "f16",
+            Self::Q4_0 => "q4_0",
+            Self::Q8_0 => "q8_0",
+        }
+    }
+
+    /// 每块的元素个数和字节数。
+    fn block(self) -> (usize, usize) {
+        match self {
+            Self::F32 => (1, 4),
+            Self::F16 => (1, 2),
+            Self::Q4_0 => (QK, 2 + QK / 2),
+            Self::Q8_0 => (QK, 2 + QK),
+        }
+    }
+
+    /// 以本类型为主的文件的 `general.file_type`。
+    fn file_type(self) -> u32 {
+        match self {
+            Self::F32 => 0,
+            Self::F16 => 1,
+            Self::Q4_0 => 2,
+            Self::Q8_0 => 7,
+        }
+    }
+}
+
+impl fmt::Display for GgmlType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for GgmlType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|t| t.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|t| t.name()).collect();
+                anyhow::anyhow!("unknown ggml type '{s}', expect one of {names:?}")
+            })
+    }
+}
+
+/// 元数据的值。
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+    Bool(bool),
+    String(String),
+    Array(Vec<Value>),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+}
+
+impl Value {
+    fn type_id(&self) -> u32 {
+        match self {
+            Self::U8(_) => 0,
+            Self::I8(_) => 1,
+            Self::U16(_) => 2,
+            Self::I16(_) => 3,
+            Self::U32(_) => 4,
+            Self::I32(_) => 5,
+            Self::F32(_) => 6,
+            Self::Bool(_) => 7,
+            Self::String(_) => 8,
+            Self::Array(_) => 9,
+            Self::U64(_) => 10,
+            Self::I64(_) => 11,
+            Self::F64(_) => 12,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Self::U8(v) => Some(v as u64),
+            Self::U16(v) => Some(v as u64),
+            Self::U32(v) => Some(v as u64),
+            Self::U64(v) => Some(v),
+            Self::I8(v) => u64::try_from(v).ok(),
+            Self::I16(v) => u64::try_from(v).ok(),
+            Self::I32(v) => u64::try_from(v).ok(),
+            Self::I64(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Self::F32(v) => Some(v as f64),
+            Self::F64(v) => Some(v),
+            _ => self.as_u64().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// 参数的描述信息。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TensorInfo {
+    /// 按 ggml 的顺序排列的维度，第一维是连续存储的维度，和行优先的形状顺序相反。
+    pub dims: Vec<usize>,
+    pub ggml_type: GgmlType,
+    /// 相对数据区起点的偏移量。
+    pub offset: usize,
+}
+
+impl TensorInfo {
+    /// 行优先顺序的形状。
+    pub fn shape(&self) -> Vec<usize> {
+        self.dims.iter().rev().copied().collect()
+    }
+
+    fn num_bytes(&self) -> anyhow::Result<usize> {
+        let (block_size, block_bytes) = self.ggml_type.block();
+        anyhow::ensure!(
+            self.dims.first().is_none_or(|v| v % block_size == 0),
+            "dims {:?} of {} tensor not multiple of block size {block_size}",
+            self.dims,
+            self.ggml_type
+        );
+        // 维度来自文件，需要检查溢出
+        self.dims
+            .iter()
+            .try_fold(1usize, |n, &v| n.checked_mul(v))
+            .and_then(|n| (n / block_size).checked_mul(block_bytes))
+            .with_context(|| format!("size of dims {:?} overflows", self.dims))
+    }
+}
+
+/// 读入内存的 GGUF 文件。
+pub struct Gguf {
+    metadata: BTreeMap<String, Value>,
+    tensors: BTreeMap<String, TensorInfo>,
+    bytes: Vec<u8>,
+    data_offset: usize,
+}
+
+impl Gguf {
+    pub fn open(p: &Path) -> anyhow::Result<Self> {
+        let bytes = fs::read(p).context("read file")?;
+        Self::parse(bytes)
+    }
+
+    pub fn parse(bytes: Vec<u8>) -> anyhow::Result<Self> {
+        let mut r = Reader { buf: &bytes, pos: 0 };
+
+        anyhow::ensure!(r.take(4).context("read magic")? == MAGIC, "bad magic");
+        let version = r.u32().context("read version")?;
+        anyhow::ensure!(matches!(version, 2 | 3), "unsupported version {version}");
+        let ntensors = r.u64().context("read tensor count")?;
+        let nkvs = r.u64().context("read metadata count")?;
+
+        let mut metadata = BTreeMap::new();
+        for i in 0..nkvs {
+            let key = r.string().with_context(|| format!("read {i}-th metadata key"))?;
+            let ty = r.u32().with_context(|| format!("read type of '{key}'"))?;
+            let v = r.value(ty).with_context(|| format!("read value of '{key}'"))?;
+            metadata.insert(key, v);
+        }
+
+        let mut tensors = BTreeMap::new();
+        for i in 0..ntensors {
+            let name = r.string().with_context(|| format!("read {i}-th tensor name"))?;
+            let info = r
+                .tensor_info()
+                .with_context(|| format!("read info of tensor '{name}'"))?;
+            tensors.insert(name, info);
+        }
+
+        let alignment = match metadata.get("general.alignment") {
+            Some(v) => v.as_u64().context("general.alignment must be an integer")? as usize,
+            None => DEFAULT_ALIGNMENT,
+        };
+        anyhow::ensure!(alignment > 0, "zero alignment");
+        let data_offset = r
+            .pos
+            .checked_next_multiple_of(alignment)
+            .context("data offset overflows")?;
+
+        let data_len = bytes.len().saturating_sub(data_offset);
+        for (name, t) in &tensors {
+            let num_bytes = t.num_bytes().with_context(|| format!("check tensor '{name}'"))?;
+            let end = t.offset.checked_add(num_bytes);
+            anyhow::ensure!(
+                end.is_some_and(|v| v <= data_len),
+                "tensor '{name}' at offset {} with {num_bytes} bytes is beyond data of {data_len} bytes",
+                t.offset
+            );
+        }
+
+        Ok(Self {
+            metadata,
+            tensors,
+            bytes,
+            data_offset,
+        })
+    }
+
+    pub fn metadata(&self) -> &BTreeMap<String, Value> {
+        &self.metadata
+    }
+
+    pub fn tensors(&self) -> &BTreeMap<String, TensorInfo> {
+        &self.tensors
+    }
+
+    /// 读取名为 `name` 的参数，返回行优先顺序的形状和数据。
+    pub fn read_f32(&self, name: &str) -> anyhow::Result<(Vec<usize>, Vec<f32>)> {
+        let t = self
+            .tensors
+            .get(name)
+            .with_context(|| format!("tensor '{name}' not found"))?;
+        // 打开文件时已检查参数不超出数据区
+        let begin = self
+            .data_offset
+            .checked_add(t.offset)
+            .context("tensor offset overflows")?;
+        let end = begin.checked_add(t.num_bytes()?).context("tensor end overflows")?;
+        let data = self.bytes.get(begin..end).context("tensor beyond file")?;
+        Ok((t.shape(), dequantize(t.ggml_type, data)))
+    }
+
+    fn get_u64(&self, key: &str) -> anyhow::Result<u64> {
+        let v = self
+            .metadata
+            .get(key)
+            .with_context(|| format!("metadata '{key}' not found"))?;
+        v.as_u64()
+            .with_context(|| format!("metadata '{key}' must be an integer"))
+    }
+
+    fn tensor<B: Backend, const D: usize>(&self, name: &str, device: &B::Device) -> anyhow::Result<Tensor<B, D>> {
+        let (shape, data) = self.read_f32(name)?;
+        anyhow::ensure!(
+            shape.len() == D,
+            "tensor '{name}' has {} dimensions, expect {D}",
+            shape.len()
+        );
+        Ok(Tensor::from_data(TensorData::new(data, shape), device))
+    }
+}
+
+/// GPT-2 的词表，每个 token 为其字节序列。
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Vocab {
+    pub tokens: Vec<Vec<u8>>,
+    pub eos_id: Option<u32>,
+    /// BPE 的合并规则，按优先级从高到低排列，每条规则为相邻的两段字节。
+    pub merges: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Vocab {
+    /// 以 token 编号为 BPE 的优先级创建词表，并据此推导合并规则，GPT-2 的词表满足这一点。
+    ///
+    /// 对每个长度大于 1 的普通 token，只用优先级比它高的 token 做 BPE 切分，恰好切成两段已有的 token 时，这两段即为
+    /// 生成它的合并规则。
+    pub fn new(tokens: Vec<Vec<u8>>, eos_id: Option<u32>) -> Self {
+        let ranks: HashMap<&[u8], usize> = tokens.iter().enumerate().map(|(i, t)| (t.as_slice(), i)).collect();
+
+        let mut merges = vec![];
+        for (rank, t) in tokens.iter().enumerate() {
+            if t.len() < 2 || eos_id == Some(rank as u32) {
+                continue;
+            }
+            if let [a, b] = bpe(t, &ranks, rank).as_slice()
+                && ranks.contains_key(a)
+                && ranks.contains_key(b)
+            {
+                merges.push((a.to_vec(), b.to_vec()));
+            }
+        }
+
+        Self { tokens, eos_id, merges }
+    }
+
+    /// 以 `tokenizer` 的前 `vocab_size` 个 token 为词表，任一 token 无法解码时返回错误。
+    pub fn from_tokenizer(tokenizer: &Encoding, vocab_size: usize, eos_id: Option<u32>) -> anyhow::Result<Self> {
+        let tokens = (0..vocab_size as u32)
+            .map(|id| tokenizer.decode(&[id]).with_context(|| format!("decode token {id}")))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self::new(tokens, eos_id))
+    }
+}
+
+/// 只用优先级高于 `max_rank` 的 token 对 `piece` 做 BPE 切分：每次合并拼接后优先级最高的相邻两段，直到无法合并。
+fn bpe<'a>(piece: &'a [u8], ranks: &HashMap<&[u8], usize>, max_rank: usize) -> Vec<&'a [u8]> {
+    // 每段的起始位置，最后一个元素为 piece 的长度
+    let mut bounds: Vec<usize> = (0..=piece.len()).collect();
+    loop {
+        let best = (0..bounds.len() - 2)
+            .filter_map(|i| {
+                let rank = *ranks.get(&piece[bounds[i]..bounds[i + 2]])?;
+                (rank < max_rank).then_some((rank, i))
+            })
+            .min();
+        let Some((_, i)) = best else {
+            break;
+        };
+        bounds.remove(i + 1);
+    }
+    bounds.windows(2).map(|w| &piece[w[0]..w[1]]).collect()
+}
+
+/// 从 GGUF 文件创建模型，同时返回文件中的词表（如果有）。
+pub fn load<B: Backend>(p: &Path, device: &B::Device) -> anyhow::Result<(Config, GptModel<B>, Option<Vocab>)> {
+    let f = Gguf::open(p).context("open gguf")?;
+
+    let c = load_config(&f).context("load config")?;
+    let mut model = c.init::<B>(device);
+    load_weights(&f, &mut model).context("load weights")?;
+    let vocab = load_vocab(&f).context("load vocab")?;
+
+    Ok((c, model, vocab))
+}
+
+/// 按 `gpt2.*` 元数据构造配置，词表大小取自 `token_embd.weight` 的形状。
+pub fn load_config(f: &Gguf) -> anyhow::Result<Config> {
+    let arch = f.metadata.get("general.architecture").and_then(Value::as_str);
+    anyhow::ensure!(arch == Some(ARCH), "unsupported architecture {arch:?}, expect '{ARCH}'");
+
+    let get = |key: &str| f.get_u64(&format!("{ARCH}.{key}")).map(|v| v as usize);
+    let n_embd = get("embedding_length")?;
+    let n_ctx = get("context_length")?;
+    let n_head = get("attention.head_count")?;
+    let n_layer = get("block_count")?;
+    let n_vocab = match f.tensors.get("token_embd.weight") {
+        Some(t) => match t.shape().as_slice() {
+            &[n, _] => n,
+            s => anyhow::bail!("token_embd.weight must be 2-D, got shape {s:?}"),
+        },
+        None => get("vocab_size")?,
+    };
+
+    super::config_from_hparams(n_vocab, n_ctx, n_embd, n_head, n_layer)
+}
+
+/// 把 GGUF 文件中的参数加载到 `model`，参数的形状必须和模型一致。没有 `output.weight` 时输出层和词嵌入共享参数；有
+/// `output.bias` 时（例如 [`save`] 保存的分类模型）`model` 的输出层必须有偏置。
+pub fn load_weights<B: Backend>(f: &Gguf, model: &mut GptModel<B>) -> anyhow::Result<()> {
+    let device = &model.devices()[0].clone();
+    let get1 = |name: &str| f.tensor::<B, 1>(name, device);
+    // ggml 的矩阵为 (out, in)，转置为线性层的 (in, out)
+    let get_linear = |name: &str| f.tensor::<B, 2>(name, device).map(|v| v.transpose());
+
+    let v = f.tensor::<B, 2>("position_embd.weight", device)?;
+    checked_assign_param(&mut model.pos_emb.weight, v).context("load positional embeddings")?;
+    let v = f.tensor::<B, 2>("token_embd.weight", device)?;
+    checked_assign_param(&mut model.tok_emb.weight, v).context("load token embeddings")?;
+
+    let nlayers = f.get_u64("gpt2.block_count")? as usize;
+    anyhow::ensure!(
+        model.trf_blocks.len() == nlayers,
+        "model has {} transformer blocks, but params has {nlayers}",
+        model.trf_blocks.len(),
+    );
+    for (i, dst) in model.trf_blocks.iter_mut().enumerate() {
+        let name = |v: &str| format!("blk.{i}.{v}");
+
+        let w = get_linear(&name("attn_qkv.weight"))?;
+        let [q, k, v] = split_qkv(w, 1).context("split attn_qkv.weight")?;
+        checked_assign_param(&mut dst.attn.wq.weight, q).context("load attention query weights")?;
+        checked_assign_param(&mut dst.attn.wk.weight, k).context("load attention key weights")?;
+        checked_assign_param(&mut dst.attn.wv.weight, v).context("load attention value weights")?;
+
+        let [q, k, v] = split_qkv(get1(&name("attn_qkv.bias"))?, 0).context("split attn_qkv.bias")?;
+        checked_assign_param(dst.attn.wq.bias.as_mut().context("miss q-bias")?, q)
+            .context("load attention query bias")?;
+        checked_assign_param(dst.attn.wk.bias.as_mut().context("miss k-bias")?, k)
+            .context("load attention key bias")?;
+        checked_assign_param(dst.attn.wv.bias.as_mut().context("miss v-bias")?, v)
+            .context("load attention value bias")?;
+
+        let w = get_linear(&name("attn_output.weight"))?;
+        checked_assign_param(&mut dst.attn.out_proj.weight, w).context("load attention out-proj weights")?;
+        let b = dst.attn.out_proj.bias.as_mut().context("miss out-proj bias")?;
+        checked_assign_param(b, get1(&name("attn_output.bias"))?).context("load out-proj bias")?;
+
+        let w = get_linear(&name("ffn_up.weight"))?;
+        checked_assign_param(&mut dst.ff.linear1.weight, w).context("load feed-forward linear1 weights")?;
+        let b = dst.ff.linear1.bias.as_mut().context("miss ff.linear1 bias")?;
+        checked_assign_param(b, get1(&name("ffn_up.bias"))?).context("load ff.linear1 bias")?;
+        let w = get_linear(&name("ffn_down.weight"))?;
+        checked_assign_param(&mut dst.ff.linear2.weight, w).context("load feed-forward linear2 weights")?;
+        let b = dst.ff.linear2.bias.as_mut().context("miss ff.linear2 bias")?;
+        checked_assign_param(b, get1(&name("ffn_down.bias"))?).context("load ff.linear2 bias")?;
+
+        checked_assign_param(&mut dst.norm1.scale, get1(&name("attn_norm.weight"))?).context("load norm1.scale")?;
+        checked_assign_param(&mut dst.norm1.shift, get1(&name("attn_norm.bias"))?).context("load norm1.shift")?;
+        checked_assign_param(&mut dst.norm2.scale, get1(&name("ffn_norm.weight"))?).context("load norm2.scale")?;
+        checked_assign_param(&mut dst.norm2.shift, get1(&name("ffn_norm.bias"))?).context("load norm2.shift")?;
+    }
+
+    let v = get1("output_norm.weight")?;
+    checked_assign_param(&mut model.final_norm.scale, v).context("load final_norm.scale")?;
+    let v = get1("output_norm.bias")?;
+    checked_assign_param(&mut model.final_norm.shift, v).context("load final_norm.shift")?;
+
+    let head = match f.tensors.contains_key("output.weight") {
+        true => get_linear("output.weight")?,
+        false => get_linear("token_embd.weight")?,
+    };
+    checked_assign_param(&mut model.out_head.weight, head).context("load out_head weights")?;
+    if f.tensors.contains_key("output.bias") {
+        let b = model.out_head.bias.as_mut().context("miss out_head bias")?;
+        checked_assign_param(b, get1("output.bias")?).context("load out_head bias")?;
+    }
+
+    if let Some(eps) = f.metadata.get("gpt2.attention.layer_norm_epsilon") {
+        let eps = eps.as_f64().context("layer_norm_epsilon must be a number")?;
+        for b in model.trf_blocks.iter_mut() {
+            b.norm1.eps = eps;
+            b.norm2.eps = eps;
+        }
+        model.final_norm.eps = eps;
+    }
+
+    Ok(())
+}
+
+/// 读取 `tokenizer.ggml.*` 中的词表，文件中没有词表时返回 `None`。
+pub fn load_vocab(f: &Gguf) -> anyhow::Result<Option<Vocab>> {
+    let Some(tokens) = f.metadata.get("tokenizer.ggml.tokens") else {
+        return Ok(None);
+    };
+
+    let model = f.metadata.get("tokenizer.ggml.model").and_then(Value::as_str);
+    anyhow::ensure!(model == Some(ARCH), "unsupported tokenizer {model:?}, expect '{ARCH}'");
+
+    let decoder: HashMap<char, u8> = byte_chars().into_iter().zip(0..=u8::MAX).collect();
+    // 字节级 BPE 把每个字节映射为一个可见字符，不在映射表中的字符按 UTF-8 编码
+    let decode = |s: &str| {
+        let mut out = vec![];
+        for c in s.chars() {
+            match decoder.get(&c) {
+                Some(b) => out.push(*b),
+                None => out.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+        out
+    };
+
+    let tokens = tokens
+        .as_array()
+        .context("tokenizer.ggml.tokens must be an array")?
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let s = v.as_str().with_context(|| format!("{i}-th token must be a string"))?;
+            Ok(decode(s))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // 合并规则的两段以空格分隔，空格本身已映射为其他字符
+    let merges = match f.metadata.get("tokenizer.ggml.merges") {
+        Some(v) => v
+            .as_array()
+            .context("tokenizer.ggml.merges must be an array")?
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let (a, b) = v
+                    .as_str()
+                    .and_then(|s| s.split_once(' '))
+                    .with_context(|| format!("{i}-th merge must be a string of two parts"))?;
+                Ok((decode(a), decode(b)))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        None => vec![],
+    };
+
+    let eos_id = match f.metadata.get("tokenizer.ggml.eos_token_id") {
+        Some(v) => Some(v.as_u64().context("eos_token_id must be an integer")? as u32),
+        None => None,
+    };
+
+    Ok(Some(Vocab { tokens, eos_id, merges }))
+}
+
+/// 把模型保存为 GGUF 文件，可以用 llama.cpp 加载。`c` 为创建模型的配置，`vocab` 为写入文件的词表，llama.cpp 分词时需要
+/// 其中的合并规则（参见 [`Vocab::new`]）。
+///
+/// 二维的参数按 `ggml_type` 保存，偏置、层归一化参数以及首维不是块大小整数倍的矩阵保存为 F32。输出层和词嵌入的参数相同时
+/// 不保存 `output.weight`；否则保存 `output.weight` 以及偏置 `output.bias`（如果有）。没有 QKV 偏置时
+/// `attn_qkv.bias` 为 0。
+pub fn save<B: Backend>(
+    model: &GptModel<B>,
+    c: &Config,
+    vocab: Option<&Vocab>,
+    ggml_type: GgmlType,
+    p: &Path,
+) -> anyhow::Result<()> {
+    let mut w = Writer::default();
+
+    w.meta("general.architecture", Value::String(ARCH.to_owned()));
+    w.meta("general.file_type", Value::U32(ggml_type.file_type()));
+    w.meta("gpt2.vocab_size", Value::U32(c.vocab_size as u32));
+    w.meta("gpt2.context_length", Value::U32(c.context_length as u32));
+    w.meta("gpt2.embedding_length", Value::U32(c.emb_dim as u32));
+    w.meta("gpt2.feed_forward_length", Value::U32(4 * c.emb_dim as u32));
+    w.meta("gpt2.block_count", Value::U32(c.nlayers as u32));
+    w.meta("gpt2.attention.head_count", Value::U32(c.nheads as u32));
+    w.meta(
+        "gpt2.attention.layer_norm_epsilon",
+        Value::F32(model.final_norm.eps as f32),
+    );
+
+    if let Some(vocab) = vocab {
+        let chars = byte_chars();
+        let encode = |t: &[u8]| -> String { t.iter().map(|b| chars[*b as usize]).collect() };
+        let tokens = vocab.tokens.iter().map(|t| Value::String(encode(t))).collect();
+        let merges = vocab
+            .merges
+            .iter()
+            .map(|(a, b)| Value::String(format!("{} {}", encode(a), encode(b))))
+            .collect();
+        let types = (0..vocab.tokens.len() as u32)
+            .map(|i| match vocab.eos_id == Some(i) {
+                true => Value::I32(TOKEN_TYPE_CONTROL),
+                false => Value::I32(TOKEN_TYPE_NORMAL),
+            })
+            .collect();
+
+        w.meta("tokenizer.ggml.model", Value::String(ARCH.to_owned()));
+        w.meta("tokenizer.ggml.pre", Value::String(ARCH.to_owned()));
+        w.meta("tokenizer.ggml.tokens", Value::Array(tokens));
+        w.meta("tokenizer.ggml.token_type", Value::Array(types));
+        if !vocab.merges.is_empty() {
+            w.meta("tokenizer.ggml.merges", Value::Array(merges));
+        }
+        if let Some(id) = vocab.eos_id {
+            w.meta("tokenizer.ggml.bos_token_id", Value::U32(id));
+            w.meta("tokenizer.ggml.eos_token_id", Value::U32(id));
+        }
+    }
+
+    w.add("token_embd.weight", model.tok_emb.weight.val(), ggml_type);
+    w.add("position_embd.weight", model.pos_emb.weight.val(), ggml_type);
+
+    for (i, b) in model.trf_blocks.iter().enumerate() {
+        let name = |v: &str| format!("blk.{i}.{v}");
+
+        w.add(name("attn_norm.weight"), b.norm1.scale.val(), ggml_type);
+        w.add(name("attn_norm.bias"), b.norm1.shift.val(), ggml_type);
+
+        let attn = &b.attn;
+        let weight = Tensor::cat(
+            vec![attn.wq.weight.val(), attn.wk.weight.val(), attn.wv.weight.val()],
+            1,
+        );
+        w.add(name("attn_qkv.weight"), weight.transpose(), ggml_type);
+        let bias = [&attn.wq, &attn.wk, &attn.wv]
+            .map(|v| match &v.bias {
+                Some(b) => b.val(),
+                None => Tensor::zeros([v.weight.dims()[1]], &v.weight.device()),
+            })
+            .to_vec();
+        w.add(name("attn_qkv.bias"), Tensor::cat(bias, 0), ggml_type);
+
+        w.add(
+            name("attn_output.weight"),
+            attn.out_proj.weight.val().transpose(),
+            ggml_type,
+        );
+        let bias = attn.out_proj.bias.as_ref().context("miss out-proj bias")?;
+        w.add(name("attn_output.bias"), bias.val(), ggml_type);
+
+        w.add(name("ffn_norm.weight"), b.norm2.scale.val(), ggml_type);
+        w.add(name("ffn_norm.bias"), b.norm2.shift.val(), ggml_type);
+
+        w.add(name("ffn_up.weight"), b.ff.linear1.weight.val().transpose(), ggml_type);
+        let bias = b.ff.linear1.bias.as_ref().context("miss ff.linear1 bias")?;
+        w.add(name("ffn_up.bias"), bias.val(), ggml_type);
+        w.add(
+            name("ffn_down.weight"),
+            b.ff.linear2.weight.val().transpose(),
+            ggml_type,
+        );
+        let bias = b.ff.linear2.bias.as_ref().context("miss ff.linear2 bias")?;
+        w.add(name("ffn_down.bias"), bias.val(), ggml_type);
+    }
+
+    w.add("output_norm.weight", model.final_norm.scale.val(), ggml_type);
+    w.add("output_norm.bias", model.final_norm.shift.val(), ggml_type);
+
+    if !is_tied(model) {
+        w.add("output.weight", model.out_head.weight.val().transpose(), ggml_type);
+        if let Some(b) = &model.out_head.bias {
+            w.add("output.bias", b.val(), ggml_type);
+        }
+    }
+
+    fs::write(p, w.finish()).context("write file")
+}
+
+/// 待保存的元数据和参数，参数为名称、ggml 顺序的维度、数据类型和编码后的数据。
+#[derive(Default)]
+struct Writer {
+    metadata: Vec<(String, Value)>,
+    tensors: Vec<(String, Vec<usize>, GgmlType, Vec<u8>)>,
+}
+
+impl Writer {
+    fn meta(&mut self, key: &str, v: Value) {
+        self.metadata.push((key.to_owned(), v));
+    }
+
+    /// 一维参数以及首维不是块大小整数倍的矩阵保存为 F32，其余按 `ggml_type` 保存。
+    fn add<B: Backend, const D: usize>(&mut self, name: impl Into<String>, t: Tensor<B, D>, ggml_type: GgmlType) {
+        let dims: Vec<usize> = t.dims().into_iter().rev().collect();
+        let data: Vec<f32> = t.into_data().convert::<f32>().to_vec().expect("read tensor");
+
+        let ty = match ggml_type {
+            _ if D == 1 => GgmlType::F32,
+            GgmlType::Q4_0 | GgmlType::Q8_0 if dims[0] % QK != 0 => GgmlType::F32,
+            v => v,
+        };
+        self.tensors.push((name.into(), dims, ty, quantize(ty, &data)));
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut out = vec![];
+        out.extend(MAGIC);
+        out.extend(VERSION.to_le_bytes());
+        out.extend((self.tensors.len() as u64).to_le_bytes());
+        out.extend((self.metadata.len() as u64).to_le_bytes());
+
+        for (k, v) in &self.metadata {
+            put_string(&mut out, k);
+            out.extend(v.type_id().to_le_bytes());
+            put_value(&mut out, v);
+        }
+
+        let mut offset = 0;
+        for (name, dims, ty, data) in &self.tensors {
+            put_string(&mut out, name);
+            out.extend((dims.len() as u32).to_le_bytes());
+            for d in dims {
+                out.extend((*d as u64).to_le_bytes());
+            }
+            out.extend((*ty as u32).to_le_bytes());
+            out.extend((offset as u64).to_le_bytes());
+            offset = (offset + data.len()).next_multiple_of(DEFAULT_ALIGNMENT);
+        }
+
+        for (_, _, _, data) in &self.tensors {
+            out.resize(out.len().next_multiple_of(DEFAULT_ALIGNMENT), 0);
+            out.extend(data);
+        }
+
+        out
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, s: &str) {
+    out.extend((s.len() as u64).to_le_bytes());
+    out.extend(s.as_bytes());
+}
+
+fn put_value(out: &mut Vec<u8>, v: &Value) {
+    match v {
+        Value::U8(v) => out.push(*v),
+        Value::I8(v) => out.extend(v.to_le_bytes()),
+        Value::U16(v) => out.extend(v.to_le_bytes()),
+        Value::I16(v) => out.extend(v.to_le_bytes()),
+        Value::U32(v) => out.extend(v.to_le_bytes()),
+        Value::I32(v) => out.extend(v.to_le_bytes()),
+        Value::F32(v) => out.extend(v.to_le_bytes()),
+        Value::Bool(v) => out.push(*v as u8),
+        Value::String(v) => put_string(out, v),
+        Value::Array(vs) => {
+            let ty = vs.first().map_or(Value::U8(0).type_id(), Value::type_id);
+            out.extend(ty.to_le_bytes());
+            out.extend((vs.len() as u64).to_le_bytes());
+            for v in vs {
+                put_value(out, v);
+            }
+        }
+        Value::U64(v) => out.extend(v.to_le_bytes()),
+        Value::I64(v) => out.extend(v.to_le_bytes()),
+        Value::F64(v) => out.extend(v.to_le_bytes()),
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).filter(|v| *v <= self.buf.len());
+        let end = end.with_context(|| format!("unexpected eof: want {n} bytes at {}", self.pos))?;
+        let out = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("N bytes"))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        let n = self.u64()? as usize;
+        let v = self.take(n)?;
+        String::from_utf8(v.to_vec()).context("string must be utf-8")
+    }
+
+    fn value(&mut self, ty: u32) -> anyhow::Result<Value> {
+        let v = match ty {
+            0 => Value::U8(self.array::<1>()?[0]),
+            1 => Value::I8(i8::from_le_bytes(self.array()?)),
+            2 => Value::U16(u16::from_le_bytes(self.array()?)),
+            3 => Value::I16(i16::from_le_bytes(self.array()?)),
+            4 => Value::U32(self.u32()?),
+            5 => Value::I32(i32::from_le_bytes(self.array()?)),
+            6 => Value::F32(f32::from_le_bytes(self.array()?)),
+            7 => Value::Bool(self.array::<1>()?[0] != 0),
+            8 => Value::String(self.string()?),
+            9 => {
+                let ty = self.u32()?;
+                let n = self.u64()?;
+                let vs = (0..n)
+                    .map(|i| self.value(ty).with_context(|| format!("read {i}-th element")))
+                    .collect::<anyhow::Result<_>>()?;
+                Value::Array(vs)
+            }
+            10 => Value::U64(self.u64()?),
+            11 => Value::I64(i64::from_le_bytes(self.array()?)),
+            12 => Value::F64(f64::from_le_bytes(self.array()?)),
+            _ => anyhow::bail!("unknown value type {ty}"),
+        };
+        Ok(v)
+    }
+
+    fn tensor_info(&mut self) -> anyhow::Result<TensorInfo> {
+        let ndims = self.u32().context("read #(dims)")?;
+        let dims = (0..ndims)
+            .map(|_| self.u64().map(|v| v as usize))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("read dims")?;
+        let ty = self.u32().context("read type")?;
+        let ggml_type = GgmlType::from_u32(ty).with_context(|| format!("unsupported ggml type {ty}"))?;
+        let offset = self.u64().context("read offset")? as usize;
+        Ok(TensorInfo {
+            dims,
+            ggml_type,
+            offset,
+        })
+    }
+}
+
+fn dequantize(ty: GgmlType, data: &[u8]) -> Vec<f32> {
+    let f16 = |b: &[u8]| half::f16::from_le_bytes([b[0], b[1]]).to_f32();
+    match ty {
+        GgmlType::F32 => data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        GgmlType::F16 => data.chunks_exact(2).map(f16).collect(),
+        // 每块为 f16 的缩放系数 d 和 32 个 i8，x = d * q
+        GgmlType::Q8_0 => data
+            .chunks_exact(ty.block().1)
+            .flat_map(|b| {
+                let d = f16(b);
+                b[2..].iter().map(move |q| d * (*q as i8) as f32)
+            })
+            .collect(),
+        // 每块为 f16 的缩放系数 d 和 16 个字节，低 4 位为前 16 个元素，高 4 位为后 16 个元素，x = d * (q - 8)
+        GgmlType::Q4_0 => data
+            .chunks_exact(ty.block().1)
+            .flat_map(|b| {
+                let d = f16(b);
+                let qs = &b[2..];
+                let lo = qs.iter().map(move |q| d * ((q & 0x0f) as i32 - 8) as f32);
+                let hi = qs.iter().map(move |q| d * ((q >> 4) as i32 - 8) as f32);
+                lo.chain(hi)
+            })
+            .collect(),
+    }
+}
+
+/// 按 ggml 的参考实现（`quantize_row_*_ref`）编码数据。
+fn quantize(ty: GgmlType, data: &[f32]) -> Vec<u8> {
+    match ty {
+        GgmlType::F32 => data.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        GgmlType::F16 => data
+            .iter()
+            .flat_map(|v| half::f16::from_f32(*v).to_le_bytes())
+            .collect(),
+        GgmlType::Q8_0 => data
+            .chunks_exact(QK)
+            .flat_map(|x| {
+                let amax = x.iter().fold(0f32, |m, v| m.max(v.abs()));
+                let d = amax / 127.0;
+                let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+
+                let mut out = half::f16::from_f32(d).to_le_bytes().to_vec();
+                out.extend(x.iter().map(|v| (v * id).round() as i8 as u8));
+                out
+            })
+            .collect(),
+        GgmlType::Q4_0 => data
+            .chunks_exact(QK)
+            .flat_map(|x| {
+                // 绝对值最大的元素映射为 -8
+                let max = x.iter().fold(0f32, |m, v| if v.abs() > m.abs() { *v } else { m });
+                let d = max / -8.0;
+                let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+                let q = |v: f32| ((v * id + 8.5) as u8).min(15);
+
+                let mut out = half::f16::from_f32(d).to_le_bytes().to_vec();
+                out.extend((0..QK / 2).map(|j| q(x[j]) | (q(x[QK / 2 + j]) << 4)));
+                out
+            })
+            .collect(),
+    }
+}
+
+/// GPT-2 字节级 BPE 的字节到字符的映射（`bytes_to_unicode`）：可见字符映射为自身，其余字节依次映射为 U+0100 起的字符。
+fn byte_chars() -> [char; 256] {
+    let mut out = ['\0'; 256];
+    let mut n = 0;
+    for b in 0..=u8::MAX {
+        out[b as usize] = if matches!(b, b'!'..=b'~' | 0xa1..=0xac | 0xae..=0xff) {
+            char::from(b)
+        } else {
+            n += 1;
+            char::from_u32(0xff + n).expect("valid char")
+        };
+    }
+    out
+}
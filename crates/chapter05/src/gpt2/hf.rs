@@ -0,0 +1,325 @@
+//! 读写 Hugging Face 格式的 GPT-2 检查点（`config.json` 和 `model.safetensors`）。读取时不需要先用 TensorFlow
+//! 导出 JSON 格式的参数，保存的检查点可以直接用 Hugging Face transformers 等工具加载。
+//!
+//! Hugging Face 的 GPT-2 使用 `Conv1D`，权重的维度为 (in, out)，和 burn 的线性层相同，不需要转置。
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context as _;
+use burn::module::{Module, Param};
+use burn::prelude::*;
+use chapter04::{Config, GptModel};
+use safetensors::tensor::TensorView;
+use safetensors::{Dtype, SafeTensors};
+
+use super::checked_assign_param;
+
+pub const CONFIG_FILE: &str = "config.json";
+pub const WEIGHTS_FILE: &str = "model.safetensors";
+
+/// 从目录 `dir` 中的 [`CONFIG_FILE`] 和 [`WEIGHTS_FILE`] 创建模型。
+pub fn load<B: Backend>(dir: &Path, device: &B::Device) -> anyhow::Result<(Config, GptModel<B>)> {
+    let c = load_config(&dir.join(CONFIG_FILE)).context("load config")?;
+
+    let mut model = c.init::<B>(device);
+    load_safetensors(&dir.join(WEIGHTS_FILE), &mut model).context("load weights")?;
+
+    Ok((c, model))
+}
+
+/// 读取 Hugging Face 的 `config.json`，按其中的超参数构造配置。
+pub fn load_config(p: &Path) -> anyhow::Result<Config> {
+    let json = fs::read_to_string(p).context("read file")?;
+
+    #[derive(serde::Deserialize)]
+    struct HfConfig {
+        vocab_size: usize,
+        n_positions: usize,
+        n_embd: usize,
+        n_head: usize,
+        n_layer: usize,
+    }
+
+    let hf: HfConfig = serde_json::from_str(&json).context("json decode")?;
+
+    super::config_from_hparams(hf.vocab_size, hf.n_positions, hf.n_embd, hf.n_head, hf.n_layer)
+}
+
+/// 把 safetensors 文件中的参数加载到 `model`，参数的形状必须和模型一致。
+///
+/// 支持 `GPT2Model` 和 `GPT2LMHeadModel`（参数名带有 `transformer.` 前缀）保存的参数。没有 `lm_head.weight` 时
+/// 输出层和词嵌入共享参数；有 `lm_head.bias` 时（例如 [`save_safetensors`] 保存的分类模型）`model` 的输出层必须有
+/// 偏置。参数可以是 F32、F16 或 BF16，加载后均转换为 F32。
+pub fn load_safetensors<B: Backend>(p: &Path, model: &mut GptModel<B>) -> anyhow::Result<()> {
+    let bytes = fs::read(p).context("read file")?;
+    let st = SafeTensors::deserialize(&bytes).context("decode safetensors")?;
+
+    let prefix = if st.names().iter().any(|v| v.starts_with("transformer.")) {
+        "transformer."
+    } else {
+        ""
+    };
+    let w = Weights::<B> {
+        st,
+        prefix,
+        device: model.devices()[0].clone(),
+    };
+
+    w.assign(&mut model.tok_emb.weight, "wte.weight")?;
+    w.assign(&mut model.pos_emb.weight, "wpe.weight")?;
+
+    let nlayers = w.num_layers();
+    anyhow::ensure!(
+        model.trf_blocks.len() == nlayers,
+        "model has {} transformer blocks, but params has {nlayers}",
+        model.trf_blocks.len(),
+    );
+    for (i, dst) in model.trf_blocks.iter_mut().enumerate() {
+        let name = |v: &str| format!("h.{i}.{v}");
+
+        let [q, k, v] = split_qkv(w.get::<2>(&name("attn.c_attn.weight"))?, 1).context("split c_attn.weight")?;
+        checked_assign_param(&mut dst.attn.wq.weight, q).context("load attention query weights")?;
+        checked_assign_param(&mut dst.attn.wk.weight, k).context("load attention key weights")?;
+        checked_assign_param(&mut dst.attn.wv.weight, v).context("load attention value weights")?;
+
+        let [q, k, v] = split_qkv(w.get::<1>(&name("attn.c_attn.bias"))?, 0).context("split c_attn.bias")?;
+        checked_assign_param(dst.attn.wq.bias.as_mut().context("miss q-bias")?, q)
+            .context("load attention query bias")?;
+        checked_assign_param(dst.attn.wk.bias.as_mut().context("miss k-bias")?, k)
+            .context("load attention key bias")?;
+        checked_assign_param(dst.attn.wv.bias.as_mut().context("miss v-bias")?, v)
+            .context("load attention value bias")?;
+
+        w.assign(&mut dst.attn.out_proj.weight, &name("attn.c_proj.weight"))?;
+        let b = dst.attn.out_proj.bias.as_mut().context("miss out-proj bias")?;
+        w.assign(b, &name("attn.c_proj.bias"))?;
+
+        w.assign(&mut dst.ff.linear1.weight, &name("mlp.c_fc.weight"))?;
+        let b = dst.ff.linear1.bias.as_mut().context("miss ff.linear1 bias")?;
+        w.assign(b, &name("mlp.c_fc.bias"))?;
+        w.assign(&mut dst.ff.linear2.weight, &name("mlp.c_proj.weight"))?;
+        let b = dst.ff.linear2.bias.as_mut().context("miss ff.linear2 bias")?;
+        w.assign(b, &name("mlp.c_proj.bias"))?;
+
+        w.assign(&mut dst.norm1.scale, &name("ln_1.weight"))?;
+        w.assign(&mut dst.norm1.shift, &name("ln_1.bias"))?;
+        w.assign(&mut dst.norm2.scale, &name("ln_2.weight"))?;
+        w.assign(&mut dst.norm2.shift, &name("ln_2.bias"))?;
+    }
+
+    w.assign(&mut model.final_norm.scale, "ln_f.weight")?;
+    w.assign(&mut model.final_norm.shift, "ln_f.bias")?;
+
+    // lm_head 是 torch 的线性层，权重的维度为 (out, in)，和词嵌入相同
+    let head = match w.st.tensor("lm_head.weight") {
+        Ok(_) => w.get_raw::<2>("lm_head.weight")?,
+        Err(_) => w.get::<2>("wte.weight")?,
+    };
+    checked_assign_param(&mut model.out_head.weight, head.transpose()).context("load out_head weights")?;
+    if w.st.tensor("lm_head.bias").is_ok() {
+        let b = model.out_head.bias.as_mut().context("miss out_head bias")?;
+        let v = w.get_raw::<1>("lm_head.bias")?;
+        checked_assign_param(b, v).context("load out_head bias")?;
+    }
+
+    Ok(())
+}
+
+/// 把模型保存为 Hugging Face 格式的检查点，即目录 `dir` 中的 [`CONFIG_FILE`] 和 [`WEIGHTS_FILE`]，可以用
+/// `GPT2LMHeadModel.from_pretrained` 加载。`c` 为创建模型的配置。
+///
+/// 输出层和词嵌入的参数相同时不保存 `lm_head.weight`，`tie_word_embeddings` 为 true；否则保存 `lm_head.weight`。
+/// 第 6 章的分类模型（输出层带偏置或者输出维度不是词表大小）没有对应的 `GPT2LMHeadModel` 结构，返回错误。
+pub fn save<B: Backend>(model: &GptModel<B>, c: &Config, dir: &Path) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        model.out_head.bias.is_none() && model.out_head.weight.dims()[1] == c.vocab_size,
+        "classifier heads cannot be exported as GPT2LMHeadModel"
+    );
+
+    fs::create_dir_all(dir).context("create dir")?;
+
+    let config = serde_json::json!({
+        "architectures": ["GPT2LMHeadModel"],
+        "model_type": "gpt2",
+        "vocab_size": c.vocab_size,
+        "n_positions": c.context_length,
+        "n_ctx": c.context_length,
+        "n_embd": c.emb_dim,
+        "n_head": c.nheads,
+        "n_layer": c.nlayers,
+        "activation_function": "gelu_new",
+        "layer_norm_epsilon": model.final_norm.eps,
+        "embd_pdrop": c.drop_emb_rate(),
+        "attn_pdrop": c.drop_attn_rate(),
+        "resid_pdrop": c.drop_shortcut_rate(),
+        "tie_word_embeddings": is_tied(model),
+        "torch_dtype": "float32",
+    });
+    let json = serde_json::to_string_pretty(&config).context("json encode config")?;
+    fs::write(dir.join(CONFIG_FILE), json).context("write config")?;
+
+    save_safetensors(model, &dir.join(WEIGHTS_FILE)).context("save weights")
+}
+
+/// 按 Hugging Face GPT-2 的参数名把模型的参数保存为 safetensors 文件，数据类型为 F32。`wq`、`wk`、`wv` 合并为
+/// `c_attn`，没有 QKV 偏置时 `c_attn.bias` 为 0。
+pub fn save_safetensors<B: Backend>(model: &GptModel<B>, p: &Path) -> anyhow::Result<()> {
+    let mut w = Writer::default();
+
+    w.add("wte.weight", model.tok_emb.weight.val());
+    w.add("wpe.weight", model.pos_emb.weight.val());
+
+    for (i, b) in model.trf_blocks.iter().enumerate() {
+        let name = |v: &str| format!("h.{i}.{v}");
+
+        let attn = &b.attn;
+        let weight = Tensor::cat(
+            vec![attn.wq.weight.val(), attn.wk.weight.val(), attn.wv.weight.val()],
+            1,
+        );
+        w.add(name("attn.c_attn.weight"), weight);
+        let bias = [&attn.wq, &attn.wk, &attn.wv]
+            .map(|v| match &v.bias {
+                Some(b) => b.val(),
+                None => Tensor::zeros([v.weight.dims()[1]], &v.weight.device()),
+            })
+            .to_vec();
+        w.add(name("attn.c_attn.bias"), Tensor::cat(bias, 0));
+
+        w.add(name("attn.c_proj.weight"), attn.out_proj.weight.val());
+        let bias = attn.out_proj.bias.as_ref().context("miss out-proj bias")?;
+        w.add(name("attn.c_proj.bias"), bias.val());
+
+        w.add(name("mlp.c_fc.weight"), b.ff.linear1.weight.val());
+        let bias = b.ff.linear1.bias.as_ref().context("miss ff.linear1 bias")?;
+        w.add(name("mlp.c_fc.bias"), bias.val());
+        w.add(name("mlp.c_proj.weight"), b.ff.linear2.weight.val());
+        let bias = b.ff.linear2.bias.as_ref().context("miss ff.linear2 bias")?;
+        w.add(name("mlp.c_proj.bias"), bias.val());
+
+        w.add(name("ln_1.weight"), b.norm1.scale.val());
+        w.add(name("ln_1.bias"), b.norm1.shift.val());
+        w.add(name("ln_2.weight"), b.norm2.scale.val());
+        w.add(name("ln_2.bias"), b.norm2.shift.val());
+    }
+
+    w.add("ln_f.weight", model.final_norm.scale.val());
+    w.add("ln_f.bias", model.final_norm.shift.val());
+
+    if !is_tied(model) {
+        w.add("lm_head.weight", model.out_head.weight.val().transpose());
+        if let Some(b) = &model.out_head.bias {
+            w.add("lm_head.bias", b.val());
+        }
+    }
+
+    let views = w
+        .tensors
+        .iter()
+        .map(|(name, shape, data)| {
+            let v = TensorView::new(Dtype::F32, shape.clone(), data).with_context(|| format!("view '{name}'"))?;
+            Ok((name.as_str(), v))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let metadata = HashMap::from([("format".to_owned(), "pt".to_owned())]);
+    safetensors::serialize_to_file(views, &Some(metadata), p).context("write safetensors")
+}
+
+/// 输出层是否没有偏置，且参数和词嵌入的转置相同。
+pub(super) fn is_tied<B: Backend>(model: &GptModel<B>) -> bool {
+    let head = model.out_head.weight.val();
+    let wte = model.tok_emb.weight.val();
+    if model.out_head.bias.is_some() || head.dims() != [wte.dims()[1], wte.dims()[0]] {
+        return false;
+    }
+
+    head.transpose().into_data().convert::<f32>() == wte.into_data().convert::<f32>()
+}
+
+/// 待保存的参数，每个元素为参数名、形状和小端序的 F32 数据。
+#[derive(Default)]
+struct Writer {
+    tensors: Vec<(String, Vec<usize>, Vec<u8>)>,
+}
+
+impl Writer {
+    fn add<B: Backend, const D: usize>(&mut self, name: impl Into<String>, t: Tensor<B, D>) {
+        let shape = t.dims().to_vec();
+        let data: Vec<f32> = t.into_data().convert::<f32>().to_vec().expect("read tensor");
+        let bytes = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.tensors.push((name.into(), shape, bytes));
+    }
+}
+
+struct Weights<'a, B: Backend> {
+    st: SafeTensors<'a>,
+    prefix: &'static str,
+    device: B::Device,
+}
+
+impl<B: Backend> Weights<'_, B> {
+    /// 读取 `transformer.` 前缀（如果有）之后名为 `name` 的参数。
+    fn get<const D: usize>(&self, name: &str) -> anyhow::Result<Tensor<B, D>> {
+        self.get_raw(&format!("{}{name}", self.prefix))
+    }
+
+    fn get_raw<const D: usize>(&self, name: &str) -> anyhow::Result<Tensor<B, D>> {
+        let view = self.st.tensor(name).with_context(|| format!("get tensor '{name}'"))?;
+        let shape = view.shape().to_vec();
+        anyhow::ensure!(
+            shape.len() == D,
+            "tensor '{name}' has {} dimensions, expect {D}",
+            shape.len()
+        );
+
+        let data = to_f32(view.dtype(), view.data()).with_context(|| format!("decode tensor '{name}'"))?;
+        Ok(Tensor::from_data(TensorData::new(data, shape), &self.device))
+    }
+
+    fn assign<const D: usize>(&self, param: &mut Param<Tensor<B, D>>, name: &str) -> anyhow::Result<()> {
+        let v = self.get(name)?;
+        checked_assign_param(param, v).with_context(|| format!("load '{name}'"))
+    }
+
+    /// 按 `h.{i}.` 前缀统计 transformer 块的数量。
+    fn num_layers(&self) -> usize {
+        let prefix = format!("{}h.", self.prefix);
+        let layers: BTreeSet<usize> = self
+            .st
+            .names()
+            .into_iter()
+            .filter_map(|v| v.strip_prefix(&prefix)?.split('.').next()?.parse().ok())
+            .collect();
+        layers.len()
+    }
+}
+
+/// 沿维度 `dim` 把 c_attn 的参数三等分为 Q、K、V 的参数。
+pub(super) fn split_qkv<B: Backend, const D: usize>(t: Tensor<B, D>, dim: usize) -> anyhow::Result<[Tensor<B, D>; 3]> {
+    let n = t.dims()[dim];
+    anyhow::ensure!(n % 3 == 0, "bad size = {n} of dimension {dim}, not multiple of 3");
+
+    let splits: [Tensor<B, D>; 3] = t.split(n / 3, dim).try_into().expect("3 splits");
+    Ok(splits)
+}
+
+fn to_f32(dtype: Dtype, data: &[u8]) -> anyhow::Result<Vec<f32>> {
+    let out = match dtype {
+        Dtype::F32 => data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        Dtype::F16 => data
+            .chunks_exact(2)
+            .map(|b| half::f16::from_le_bytes([b[0], b[1]]).to_f32())
+            .collect(),
+        Dtype::BF16 => data
+            .chunks_exact(2)
+            .map(|b| half::bf16::from_le_bytes([b[0], b[1]]).to_f32())
+            .collect(),
+        _ => anyhow::bail!("unsupported dtype {dtype:?}"),
+    };
+
+    Ok(out)
+}
@@ -0,0 +1,260 @@
+//! 以 NumPy 的 `.npz` 格式（每个变量一个 `.npy` 文件的 zip 归档）读写 [`Params`]，代替体积庞大的 JSON。
+//!
+//! 变量名为 [`Params`] 中字段的路径，例如 `wte`、`blocks.0.attn.c_attn.w`、`blocks.0.ln_1.g`，和 `gpt2/main.py` 中
+//! `np.savez` 保存的名称一致。数据类型为小端序的 float32，读取时直接写入连续的内存。
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+use anyhow::Context as _;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use super::{Attn, AttnOutProj, AttnQkv, Block, FeedForward, FeedForwardWb, LayerNorm, Matrix, Params};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// 读取 `.npy` 格式的 float32 数组，返回其形状和按行优先顺序排列的数据。
+pub fn read_npy(r: &mut impl Read) -> anyhow::Result<(Vec<usize>, Vec<f32>)> {
+    let mut prefix = [0u8; 8];
+    r.read_exact(&mut prefix).context("read magic")?;
+    anyhow::ensure!(&prefix[..6] == MAGIC, "bad magic");
+
+    // 1.0 版本的头部长度为 2 字节，2.0 和 3.0 版本为 4 字节
+    let header_len = match prefix[6] {
+        1 => {
+            let mut b = [0u8; 2];
+            r.read_exact(&mut b).context("read header length")?;
+            u16::from_le_bytes(b) as usize
+        }
+        2 | 3 => {
+            let mut b = [0u8; 4];
+            r.read_exact(&mut b).context("read header length")?;
+            u32::from_le_bytes(b) as usize
+        }
+        v => anyhow::bail!("unsupported version {v}"),
+    };
+    let mut header = vec![0u8; header_len];
+    r.read_exact(&mut header).context("read header")?;
+    let header = String::from_utf8(header).context("header must be utf-8")?;
+
+    let descr = header_value(&header, "descr")?;
+    anyhow::ensure!(descr == "'<f4'", "unsupported dtype {descr}, expect '<f4'");
+    anyhow::ensure!(
+        header_value(&header, "fortran_order")? == "False",
+        "fortran order is not supported"
+    );
+    let shape = header_value(&header, "shape")?;
+    let shape = shape
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<usize>().with_context(|| format!("parse shape {shape}")))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // 分块读取，避免额外复制一份完整的字节数据
+    let n: usize = shape.iter().product();
+    let mut data = vec![0f32; n];
+    let mut buf = vec![0u8; 1 << 16];
+    for chunk in data.chunks_mut(buf.len() / 4) {
+        let b = &mut buf[..4 * chunk.len()];
+        r.read_exact(b).context("read data")?;
+        for (v, b) in chunk.iter_mut().zip(b.chunks_exact(4)) {
+            *v = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        }
+    }
+
+    Ok((shape, data))
+}
+
+/// 以 `.npy` 1.0 格式写入 float32 数组。
+pub fn write_npy(w: &mut impl Write, shape: &[usize], data: &[f32]) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        shape.iter().product::<usize>() == data.len(),
+        "shape {shape:?} does not match data length {}",
+        data.len()
+    );
+
+    let shape = match shape {
+        [n] => format!("({n},)"),
+        _ => format!(
+            "({})",
+            shape.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ")
+        ),
+    };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {shape}, }}");
+    // 魔数、版本、头部长度和头部的总长度按 64 字节对齐，头部以换行结尾
+    let len = MAGIC.len() + 2 + 2 + header.len() + 1;
+    header.extend(std::iter::repeat_n(' ', len.next_multiple_of(64) - len));
+    header.push('\n');
+
+    w.write_all(MAGIC)?;
+    w.write_all(&[1, 0])?;
+    w.write_all(&(header.len() as u16).to_le_bytes())?;
+    w.write_all(header.as_bytes())?;
+    for chunk in data.chunks(1 << 14) {
+        let b: Vec<u8> = chunk.iter().flat_map(|v| v.to_le_bytes()).collect();
+        w.write_all(&b)?;
+    }
+
+    Ok(())
+}
+
+/// 读取 `.npz` 格式的参数。
+pub fn load_params(p: &Path) -> anyhow::Result<Params> {
+    let f = File::open(p).context("open file")?;
+    let mut npz = Npz(ZipArchive::new(BufReader::new(f)).context("open zip archive")?);
+
+    let layers: BTreeSet<usize> = npz
+        .0
+        .file_names()
+        .filter_map(|v| v.strip_prefix("blocks.")?.split('.').next()?.parse().ok())
+        .collect();
+    anyhow::ensure!(
+        layers.iter().copied().eq(0..layers.len()),
+        "blocks {layers:?} are not contiguous"
+    );
+
+    let mut blocks = Vec::with_capacity(layers.len());
+    for i in 0..layers.len() {
+        let mut get_1d = |name: &str| npz.vec1(&format!("blocks.{i}.{name}"));
+        let c_attn_b = get_1d("attn.c_attn.b")?;
+        let c_proj_b = get_1d("attn.c_proj.b")?;
+        let c_fc_b = get_1d("mlp.c_fc.b")?;
+        let mlp_c_proj_b = get_1d("mlp.c_proj.b")?;
+        let ln_1 = LayerNorm {
+            g: get_1d("ln_1.g")?,
+            b: get_1d("ln_1.b")?,
+        };
+        let ln_2 = LayerNorm {
+            g: get_1d("ln_2.g")?,
+            b: get_1d("ln_2.b")?,
+        };
+
+        let mut get_2d = |name: &str| npz.vec2(&format!("blocks.{i}.{name}"));
+        blocks.push(Block {
+            attn: Attn {
+                c_attn: AttnQkv {
+                    w: get_2d("attn.c_attn.w")?,
+                    b: c_attn_b,
+                },
+                c_proj: AttnOutProj {
+                    w: get_2d("attn.c_proj.w")?,
+                    b: c_proj_b,
+                },
+            },
+            mlp: FeedForward {
+                c_fc: FeedForwardWb {
+                    w: get_2d("mlp.c_fc.w")?,
+                    b: c_fc_b,
+                },
+                c_proj: FeedForwardWb {
+                    w: get_2d("mlp.c_proj.w")?,
+                    b: mlp_c_proj_b,
+                },
+            },
+            ln_1,
+            ln_2,
+        });
+    }
+
+    Ok(Params {
+        blocks,
+        g: npz.vec1("g")?,
+        b: npz.vec1("b")?,
+        wte: npz.vec2("wte")?,
+        wpe: npz.vec2("wpe")?,
+    })
+}
+
+/// 把参数保存为 `.npz` 格式，各数组不压缩。
+pub fn save_params(params: &Params, p: &Path) -> anyhow::Result<()> {
+    fn vec1(name: String, v: &[f32]) -> (String, Vec<usize>, &[f32]) {
+        (name, vec![v.len()], v)
+    }
+    fn mat(name: String, v: &Matrix) -> (String, Vec<usize>, &[f32]) {
+        (name, vec![v.rows, v.cols], &v.data)
+    }
+
+    let mut vars = vec![
+        mat("wte".into(), &params.wte),
+        mat("wpe".into(), &params.wpe),
+        vec1("g".into(), &params.g),
+        vec1("b".into(), &params.b),
+    ];
+    for (i, b) in params.blocks.iter().enumerate() {
+        let name = |v: &str| format!("blocks.{i}.{v}");
+        vars.push(mat(name("attn.c_attn.w"), &b.attn.c_attn.w));
+        vars.push(vec1(name("attn.c_attn.b"), &b.attn.c_attn.b));
+        vars.push(mat(name("attn.c_proj.w"), &b.attn.c_proj.w));
+        vars.push(vec1(name("attn.c_proj.b"), &b.attn.c_proj.b));
+        vars.push(mat(name("mlp.c_fc.w"), &b.mlp.c_fc.w));
+        vars.push(vec1(name("mlp.c_fc.b"), &b.mlp.c_fc.b));
+        vars.push(mat(name("mlp.c_proj.w"), &b.mlp.c_proj.w));
+        vars.push(vec1(name("mlp.c_proj.b"), &b.mlp.c_proj.b));
+        vars.push(vec1(name("ln_1.g"), &b.ln_1.g));
+        vars.push(vec1(name("ln_1.b"), &b.ln_1.b));
+        vars.push(vec1(name("ln_2.g"), &b.ln_2.g));
+        vars.push(vec1(name("ln_2.b"), &b.ln_2.b));
+    }
+
+    let f = File::create(p).context("create file")?;
+    let mut zip = ZipWriter::new(BufWriter::new(f));
+    for (name, shape, data) in vars {
+        let opts = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(4 * data.len() as u64 >= u32::MAX as u64);
+        zip.start_file(format!("{name}.npy"), opts)
+            .with_context(|| format!("start '{name}'"))?;
+        write_npy(&mut zip, &shape, data).with_context(|| format!("write '{name}'"))?;
+    }
+    zip.finish().context("finish zip archive")?.flush().context("flush")?;
+
+    Ok(())
+}
+
+struct Npz<R>(ZipArchive<R>);
+
+impl<R: Read + Seek> Npz<R> {
+    fn read(&mut self, name: &str) -> anyhow::Result<(Vec<usize>, Vec<f32>)> {
+        let mut f = self
+            .0
+            .by_name(&format!("{name}.npy"))
+            .with_context(|| format!("variable '{name}' not found"))?;
+        read_npy(&mut f).with_context(|| format!("read '{name}'"))
+    }
+
+    fn vec1(&mut self, name: &str) -> anyhow::Result<Vec<f32>> {
+        let (shape, data) = self.read(name)?;
+        anyhow::ensure!(shape.len() == 1, "'{name}' with shape {shape:?} is not a vector");
+        Ok(data)
+    }
+
+    fn vec2(&mut self, name: &str) -> anyhow::Result<Matrix> {
+        let (shape, data) = self.read(name)?;
+        anyhow::ensure!(shape.len() == 2, "'{name}' with shape {shape:?} is not a matrix");
+        Ok(Matrix::new(shape[0], shape[1], data))
+    }
+}
+
+/// 读取 `.npy` 头部字典中 `key` 对应的值的原始文本。
+fn header_value<'a>(header: &'a str, key: &str) -> anyhow::Result<&'a str> {
+    let pat = format!("'{key}':");
+    let begin = header
+        .find(&pat)
+        .with_context(|| format!("missing '{key}' in header"))?
+        + pat.len();
+    let rest = header[begin..].trim_start();
+
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|i| i + 1)
+    } else {
+        rest.find([',', '}'])
+    };
+    let end = end.with_context(|| format!("bad value of '{key}' in header"))?;
+
+    Ok(rest[..end].trim())
+}
@@ -0,0 +1,402 @@
+//! 读取 TensorFlow v2 检查点（tensor bundle）格式的 GPT-2 参数，即 OpenAI 发布的 `model.ckpt.index` 和
+//! `model.ckpt.data-*-of-*` 文件，不需要 Python 和 TensorFlow。
+//!
+//! `.index` 文件是 LevelDB 格式的有序表（SSTable），键为变量名，值为 protobuf 编码的 `BundleEntryProto`，记录变量的
+//! 类型、形状以及在数据文件中的位置；空键对应 `BundleHeaderProto`，记录数据文件的分片数。数据文件按顺序存放各变量的
+//! 原始字节。
+//!
+//! 这里只实现读取 GPT-2 变量所需的部分：不压缩的表、小端序的 float32 变量，不支持分块（slices）保存的变量，也不校验
+//! CRC。
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{Read as _, Seek as _, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+
+use super::{Attn, AttnOutProj, AttnQkv, Block, FeedForward, FeedForwardWb, LayerNorm, Matrix, Params};
+
+/// OpenAI 发布的检查点在模型目录下的前缀。
+pub const CHECKPOINT_PREFIX: &str = "model.ckpt";
+
+/// `DataType::DT_FLOAT`
+pub const DT_FLOAT: i32 = 1;
+
+/// SSTable 文件末尾的魔数。
+const TABLE_MAGIC: u64 = 0xdb4775248b80fb57;
+/// 文件末尾固定长度的 footer：两个 BlockHandle（填充到 40 字节）和 8 字节的魔数。
+const FOOTER_LEN: usize = 48;
+/// 每个块之后的 1 字节压缩类型和 4 字节 CRC。
+const BLOCK_TRAILER_LEN: usize = 5;
+
+/// 变量在数据文件中的位置，对应 `BundleEntryProto`。
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub dtype: i32,
+    pub shape: Vec<usize>,
+    pub shard_id: usize,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// 打开的检查点，只读取了索引，变量的数据在 [`Checkpoint::read_f32`] 时才读取。
+#[derive(Debug)]
+pub struct Checkpoint {
+    prefix: PathBuf,
+    num_shards: usize,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Checkpoint {
+    /// 打开前缀为 `prefix`（例如 `gpt2/124M/model.ckpt`）的检查点。
+    pub fn open(prefix: &Path) -> anyhow::Result<Self> {
+        let p = index_path(prefix);
+        let data = fs::read(&p).with_context(|| format!("read {p:?}"))?;
+
+        let mut num_shards = None;
+        let mut entries = BTreeMap::new();
+        for (key, value) in read_table(&data).context("read index table")? {
+            if key.is_empty() {
+                num_shards = Some(decode_header(&value).context("decode bundle header")?);
+                continue;
+            }
+
+            let name = String::from_utf8(key).context("variable name must be utf-8")?;
+            let entry = decode_entry(&value).with_context(|| format!("decode entry of '{name}'"))?;
+            entries.insert(name, entry);
+        }
+
+        let num_shards = num_shards.context("missing bundle header")?;
+        if let Some((name, e)) = entries.iter().find(|(_, e)| e.shard_id >= num_shards) {
+            anyhow::bail!("shard {} of '{name}' out of range [0, {num_shards})", e.shard_id);
+        }
+
+        Ok(Self {
+            prefix: prefix.to_owned(),
+            num_shards,
+            entries,
+        })
+    }
+
+    /// 按名称排序的变量。
+    pub fn entries(&self) -> &BTreeMap<String, Entry> {
+        &self.entries
+    }
+
+    /// 读取名为 `name` 的 float32 变量，返回其形状和数据。
+    pub fn read_f32(&self, name: &str) -> anyhow::Result<(Vec<usize>, Vec<f32>)> {
+        let e = self
+            .entries
+            .get(name)
+            .with_context(|| format!("variable '{name}' not found"))?;
+        anyhow::ensure!(
+            e.dtype == DT_FLOAT,
+            "variable '{name}' has dtype {}, expect DT_FLOAT",
+            e.dtype
+        );
+        let n: usize = e.shape.iter().product();
+        anyhow::ensure!(
+            e.size == 4 * n as u64,
+            "variable '{name}' has {} bytes, expect {} for shape {:?}",
+            e.size,
+            4 * n,
+            e.shape
+        );
+
+        let p = data_path(&self.prefix, e.shard_id, self.num_shards);
+        let mut f = File::open(&p).with_context(|| format!("open {p:?}"))?;
+        f.seek(SeekFrom::Start(e.offset)).context("seek")?;
+        let mut buf = vec![0u8; e.size as usize];
+        f.read_exact(&mut buf)
+            .with_context(|| format!("read '{name}' from {p:?}"))?;
+
+        let data = buf
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        Ok((e.shape.clone(), data))
+    }
+}
+
+/// 检查点的索引文件是否存在。
+pub fn exists(prefix: &Path) -> bool {
+    index_path(prefix).exists()
+}
+
+/// 读取 OpenAI 发布的 GPT-2 检查点，结果和 `gpt2/main.py` 导出的 JSON 相同。
+pub fn load_params(prefix: &Path) -> anyhow::Result<Params> {
+    let ckpt = Checkpoint::open(prefix).context("open checkpoint")?;
+
+    let vec1 = |name: &str| -> anyhow::Result<Vec<f32>> {
+        let (shape, data) = ckpt.read_f32(&format!("model/{name}"))?;
+        anyhow::ensure!(
+            shape.iter().filter(|&&v| v != 1).count() <= 1,
+            "'{name}' with shape {shape:?} is not a vector"
+        );
+        Ok(data)
+    };
+    // Conv1D 的权重的维度为 (1, in, out)，和 np.squeeze 一样去掉长度为 1 的维度
+    let vec2 = |name: &str| -> anyhow::Result<Matrix> {
+        let (shape, data) = ckpt.read_f32(&format!("model/{name}"))?;
+        let dims: Vec<_> = shape.iter().copied().filter(|&v| v != 1).collect();
+        anyhow::ensure!(dims.len() == 2, "'{name}' with shape {shape:?} is not a matrix");
+        Ok(Matrix::new(dims[0], dims[1], data))
+    };
+
+    let layers: BTreeSet<usize> = ckpt
+        .entries()
+        .keys()
+        .filter_map(|v| v.strip_prefix("model/h")?.split('/').next()?.parse().ok())
+        .collect();
+    anyhow::ensure!(
+        layers.iter().copied().eq(0..layers.len()),
+        "layers {layers:?} are not contiguous"
+    );
+
+    let blocks = (0..layers.len())
+        .map(|i| {
+            let ln = |name: &str| -> anyhow::Result<LayerNorm> {
+                Ok(LayerNorm {
+                    g: vec1(&format!("h{i}/{name}/g"))?,
+                    b: vec1(&format!("h{i}/{name}/b"))?,
+                })
+            };
+            let wb = |name: &str| -> anyhow::Result<FeedForwardWb> {
+                Ok(FeedForwardWb {
+                    w: vec2(&format!("h{i}/{name}/w"))?,
+                    b: vec1(&format!("h{i}/{name}/b"))?,
+                })
+            };
+
+            let attn = Attn {
+                c_attn: AttnQkv {
+                    w: vec2(&format!("h{i}/attn/c_attn/w"))?,
+                    b: vec1(&format!("h{i}/attn/c_attn/b"))?,
+                },
+                c_proj: AttnOutProj {
+                    w: vec2(&format!("h{i}/attn/c_proj/w"))?,
+                    b: vec1(&format!("h{i}/attn/c_proj/b"))?,
+                },
+            };
+            let mlp = FeedForward {
+                c_fc: wb("mlp/c_fc")?,
+                c_proj: wb("mlp/c_proj")?,
+            };
+
+            Ok(Block {
+                attn,
+                mlp,
+                ln_1: ln("ln_1")?,
+                ln_2: ln("ln_2")?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("load transformer blocks")?;
+
+    Ok(Params {
+        blocks,
+        g: vec1("ln_f/g")?,
+        b: vec1("ln_f/b")?,
+        wte: vec2("wte")?,
+        wpe: vec2("wpe")?,
+    })
+}
+
+fn index_path(prefix: &Path) -> PathBuf {
+    let mut p = OsString::from(prefix);
+    p.push(".index");
+    p.into()
+}
+
+fn data_path(prefix: &Path, shard_id: usize, num_shards: usize) -> PathBuf {
+    let mut p = OsString::from(prefix);
+    p.push(format!(".data-{shard_id:05}-of-{num_shards:05}"));
+    p.into()
+}
+
+/// 按顺序返回 SSTable 中所有的键值对。
+fn read_table(data: &[u8]) -> anyhow::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    anyhow::ensure!(data.len() >= FOOTER_LEN, "file too short");
+    let footer = &data[data.len() - FOOTER_LEN..];
+    let magic = u64::from_le_bytes(footer[40..].try_into().expect("8 bytes"));
+    anyhow::ensure!(magic == TABLE_MAGIC, "bad magic {magic:#x}");
+
+    let mut r = &footer[..40];
+    let _metaindex = BlockHandle::decode(&mut r).context("decode metaindex handle")?;
+    let index = BlockHandle::decode(&mut r).context("decode index handle")?;
+
+    // 索引块的值为各数据块的位置
+    let mut out = vec![];
+    for (_, v) in read_block(data, index).context("read index block")? {
+        let h = BlockHandle::decode(&mut v.as_slice()).context("decode block handle")?;
+        out.extend(read_block(data, h).context("read data block")?);
+    }
+    Ok(out)
+}
+
+/// 解码一个块中的键值对。每个键只保存和前一个键不同的后缀。
+fn read_block(data: &[u8], h: BlockHandle) -> anyhow::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    // 块的位置来自文件，先检查溢出和越界再切片
+    let out_of_range = || format!("block {h:?} out of range");
+    let end = h.offset.checked_add(h.size).with_context(out_of_range)?;
+    let begin = usize::try_from(h.offset).with_context(out_of_range)?;
+    let end = usize::try_from(end).with_context(out_of_range)?;
+    anyhow::ensure!(
+        end.checked_add(BLOCK_TRAILER_LEN).is_some_and(|v| v <= data.len()),
+        "block {h:?} out of range"
+    );
+    anyhow::ensure!(data[end] == 0, "compressed block (type {}) is not supported", data[end]);
+
+    let block = &data[begin..end];
+    anyhow::ensure!(block.len() >= 4, "block too short");
+    let num_restarts = u32::from_le_bytes(block[block.len() - 4..].try_into().expect("4 bytes")) as usize;
+    let entries_end = num_restarts
+        .checked_mul(4)
+        .and_then(|n| (block.len() - 4).checked_sub(n))
+        .context("bad number of restart points")?;
+
+    let mut r = &block[..entries_end];
+    let mut key = Vec::new();
+    let mut out = vec![];
+    while !r.is_empty() {
+        let shared = read_varint(&mut r)? as usize;
+        let non_shared = read_varint(&mut r)? as usize;
+        let value_len = read_varint(&mut r)? as usize;
+        anyhow::ensure!(shared <= key.len(), "shared prefix longer than previous key");
+
+        key.truncate(shared);
+        key.extend_from_slice(take(&mut r, non_shared)?);
+        let value = take(&mut r, value_len)?.to_vec();
+        out.push((key.clone(), value));
+    }
+    Ok(out)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct BlockHandle {
+    offset: u64,
+    size: u64,
+}
+
+impl BlockHandle {
+    fn decode(r: &mut &[u8]) -> anyhow::Result<Self> {
+        let offset = read_varint(r)?;
+        let size = read_varint(r)?;
+        Ok(Self { offset, size })
+    }
+}
+
+/// protobuf 字段的值。
+enum Field<'a> {
+    Varint(u64),
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32,
+}
+
+/// 按顺序解码 protobuf 消息的字段，返回字段编号和值。
+fn decode_fields(mut r: &[u8]) -> anyhow::Result<Vec<(u64, Field<'_>)>> {
+    let mut out = vec![];
+    while !r.is_empty() {
+        let tag = read_varint(&mut r)?;
+        let field = match tag & 7 {
+            0 => Field::Varint(read_varint(&mut r)?),
+            1 => {
+                take(&mut r, 8)?;
+                Field::Fixed64
+            }
+            2 => {
+                let n = read_varint(&mut r)? as usize;
+                Field::Bytes(take(&mut r, n)?)
+            }
+            5 => {
+                take(&mut r, 4)?;
+                Field::Fixed32
+            }
+            v => anyhow::bail!("unsupported wire type {v}"),
+        };
+        out.push((tag >> 3, field));
+    }
+    Ok(out)
+}
+
+/// 解码 `BundleHeaderProto`，返回分片数。
+fn decode_header(data: &[u8]) -> anyhow::Result<usize> {
+    let mut num_shards = 1;
+    for (n, f) in decode_fields(data)? {
+        match (n, f) {
+            (1, Field::Varint(v)) => num_shards = v as usize,
+            // endianness：0 为小端序，1 为大端序
+            (2, Field::Varint(v)) => anyhow::ensure!(v == 0, "big-endian checkpoint is not supported"),
+            _ => {}
+        }
+    }
+    Ok(num_shards)
+}
+
+/// 解码 `BundleEntryProto`。
+fn decode_entry(data: &[u8]) -> anyhow::Result<Entry> {
+    let mut out = Entry {
+        dtype: 0,
+        shape: vec![],
+        shard_id: 0,
+        offset: 0,
+        size: 0,
+    };
+    for (n, f) in decode_fields(data)? {
+        match (n, f) {
+            (1, Field::Varint(v)) => out.dtype = v as i32,
+            (2, Field::Bytes(v)) => out.shape = decode_shape(v).context("decode shape")?,
+            (3, Field::Varint(v)) => out.shard_id = v as usize,
+            (4, Field::Varint(v)) => out.offset = v,
+            (5, Field::Varint(v)) => out.size = v,
+            (7, _) => anyhow::bail!("sliced variable is not supported"),
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+/// 解码 `TensorShapeProto`，其中每个 `dim` 为一个嵌套的消息，字段 1 为长度。
+fn decode_shape(data: &[u8]) -> anyhow::Result<Vec<usize>> {
+    let mut out = vec![];
+    for (n, f) in decode_fields(data)? {
+        match (n, f) {
+            (2, Field::Bytes(dim)) => {
+                let mut size = 0;
+                for (n, f) in decode_fields(dim)? {
+                    if let (1, Field::Varint(v)) = (n, f) {
+                        // int64 的 -1 表示未知的长度
+                        anyhow::ensure!((v as i64) >= 0, "unknown dimension");
+                        size = v as usize;
+                    }
+                }
+                out.push(size);
+            }
+            (3, Field::Varint(v)) => anyhow::ensure!(v == 0, "unknown rank"),
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+fn read_varint(r: &mut &[u8]) -> anyhow::Result<u64> {
+    let mut out = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&b, rest) = r.split_first().context("truncated varint")?;
+        *r = rest;
+        out |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Ok(out);
+        }
+    }
+    anyhow::bail!("varint too long")
+}
+
+fn take<'a>(r: &mut &'a [u8], n: usize) -> anyhow::Result<&'a [u8]> {
+    anyhow::ensure!(r.len() >= n, "unexpected end of data");
+    let (out, rest) = r.split_at(n);
+    *r = rest;
+    Ok(out)
+}
@@ -1,6 +1,16 @@
+pub mod beam;
+pub mod checkpoint;
 pub mod config;
+pub mod constrained;
+pub mod contrastive;
 pub mod gpt2;
 pub mod loss;
 pub mod rand;
+pub mod resume;
+pub mod sampling;
+pub mod score;
+pub mod speculative;
+pub mod stopping;
+pub mod stream;
 pub mod utils;
 pub mod x;
@@ -5,11 +5,11 @@ use crate::x::TensorExt;
 
 /// 多项式采样函数
 /// 参数：`probs` - 2 维概率数组。
-/// 返回值：采样到的对象下标（索引）
+/// 返回值：采样到的对象下标（索引），维度为 (probs 的行数, 1)。
 /// 假设 probs 的最后一维每个元素的和为 1。
 pub fn multinomial<B: Backend>(probas: Tensor<B, 2>) -> Tensor<B, 2, Int> {
     // let ndim = *probas.dims().last().expect("get last dim");
-    let [d0, _] = probas.dims();
+    let [d0, d1] = probas.dims();
     let device = &probas.device();
 
     // // 1. 计算累计概率。
@@ -19,6 +19,77 @@ pub fn multinomial<B: Backend>(probas: Tensor<B, 2>) -> Tensor<B, 2, Int> {
     // // 2. 生成随机数
     let r = Tensor::<B, 1>::random([d0], Distribution::Uniform(0.0, 1.0), device);
 
-    // 3. 搜索第一个满足 p>r 的下标
-    p.search_sorted(r).unsqueeze()
+    // 3. 搜索第一个满足 p>r 的下标。舍入误差可能导致 r 不小于最后的累计概率，此时取最后一个下标
+    p.search_sorted(r).clamp_max(d1 as i64 - 1).unsqueeze_dim(1)
+}
+
+/// 与后端无关的伪随机数生成器（SplitMix64）。相同的种子在任何后端和平台上都产生相同的序列，
+/// 也不受其他代码消耗后端随机数的影响。
+#[derive(Clone, Debug)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// 批量中第 `row` 行使用的生成器。各行的序列互不相关，只取决于 `seed` 和 `row`。
+    pub fn for_row(seed: u64, row: usize) -> Self {
+        Self::new(mix64(seed ^ mix64(row as u64)))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix64(self.state)
+    }
+
+    /// [0, 1) 上均匀分布的随机数，精度为 53 位。
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+fn mix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// 和 [`multinomial`] 相同，但第 i 行使用 `rngs[i]` 生成的随机数，并在主机上计算累计概率。
+/// 相同的随机数生成器状态和概率在任何后端上都得到相同的结果。
+///
+/// 各行的概率之和不必严格为 1。
+pub fn multinomial_with<B: Backend>(probas: Tensor<B, 2>, rngs: &mut [SplitMix64]) -> Tensor<B, 2, Int> {
+    let [d0, d1] = probas.dims();
+    assert_eq!(d0, rngs.len(), "bad rngs len");
+    let device = &probas.device();
+
+    let probas = probas
+        .into_data()
+        .convert::<f32>()
+        .to_vec::<f32>()
+        .expect("read probas");
+    let ids: Vec<i64> = probas
+        .chunks(d1)
+        .zip(rngs)
+        .map(|(p, rng)| sample_index(p, rng) as i64)
+        .collect();
+
+    Tensor::<B, 1, Int>::from_ints(ids.as_slice(), device).unsqueeze_dim(1)
+}
+
+/// 按概率 `p` 在主机上采样一个下标，`p` 之和不必严格为 1。
+pub fn sample_index(p: &[f32], rng: &mut SplitMix64) -> usize {
+    let total: f64 = p.iter().map(|&v| v as f64).sum();
+    let r = rng.next_f64() * total;
+
+    let mut c = 0.0;
+    let i = p.iter().position(|&v| {
+        c += v as f64;
+        c > r
+    });
+    // 舍入误差导致 r 不小于累计概率时，取最后一个概率非 0 的下标
+    i.or_else(|| p.iter().rposition(|&v| v > 0.0)).unwrap_or_default()
 }
@@ -0,0 +1,241 @@
+//! 可以中断后继续的训练。[`Checkpointer`] 每隔若干步把模型、优化器状态和训练进度 [`TrainState`] 保存到检查点目录，
+//! 中断后从最近一次保存的位置继续训练，损失曲线和没有中断时相同。
+//!
+//! 还原训练需要还原三种随机状态：
+//! - 优化器的状态（AdamW 的一阶、二阶矩），保存在 [`OPTIMIZER_FILE`]；
+//! - 训练数据的洗牌顺序。burn 的数据加载器每次调用 `iter` 都从内部的随机数生成器取一个种子打乱数据，
+//!   [`ResumableLoader`] 记录每轮开始前的调用次数，恢复时重放同样次数的调用。[`ResumableLoader::eval`] 迭代副本，
+//!   不消耗种子，所以和原来直接用训练数据加载器评估的训练循环相比，第二轮起的洗牌顺序不同；
+//! - 后端的随机数生成器（dropout 使用），无法读取。每次保存检查点后按 [`TrainState::seed`] 和步数重设种子，
+//!   恢复时也重设为同样的种子。
+//!
+//! 学习率目前是固定的，调度器需要的位置就是 [`TrainState::step`]。
+use std::cell::Cell;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use burn::data::dataloader::{DataLoader, DataLoaderIterator};
+use burn::optim::Optimizer;
+use burn::prelude::*;
+use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder, Recorder};
+use burn::tensor::backend::AutodiffBackend;
+use chapter04::GptModel;
+use serde::{Deserialize, Serialize};
+
+use crate::checkpoint::{self, CheckpointMeta};
+
+pub const STATE_FILE: &str = "train-state.json";
+pub const OPTIMIZER_FILE: &str = "optimizer.mpk";
+
+/// 训练进度以及到目前为止的评估结果。
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrainState {
+    /// 重设后端随机数生成器的基础种子。
+    pub seed: u64,
+    /// 已经完成的轮数。
+    pub epochs_done: usize,
+    /// 当前轮次中已经训练的批次数。
+    pub batches_done: usize,
+    /// 已经执行的优化步数。
+    pub step: usize,
+    pub tokens_seen: usize,
+    pub examples_seen: usize,
+    /// 当前轮次开始前训练数据加载器被迭代的次数。
+    pub epoch_start_iters: usize,
+    pub train_losses: Vec<f32>,
+    pub val_losses: Vec<f32>,
+    pub track_tokens_seen: Vec<usize>,
+    pub train_accs: Vec<f32>,
+    pub val_accs: Vec<f32>,
+}
+
+impl TrainState {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Default::default()
+        }
+    }
+
+    /// 完成当前轮次。
+    pub fn finish_epoch(&mut self) {
+        self.epochs_done += 1;
+        self.batches_done = 0;
+    }
+
+    /// 按种子和步数重设后端的随机数生成器。
+    pub fn reseed<B: Backend>(&self) {
+        B::seed(self.seed.wrapping_add(self.step as u64));
+    }
+}
+
+/// 记录 `iter` 调用次数的训练数据加载器，用于还原洗牌顺序。burn 的 `DataLoader` 要求实现 `Send`，
+/// 共享的 `Arc<dyn DataLoader>` 不满足，所以这里不实现 `DataLoader`，所有迭代都要经过 [`Self::epoch`] 或 [`Self::eval`]。
+pub struct ResumableLoader<B: Backend, O> {
+    inner: Arc<dyn DataLoader<B, O>>,
+    iters: Cell<usize>,
+}
+
+impl<B: Backend, O> ResumableLoader<B, O> {
+    pub fn new(inner: Arc<dyn DataLoader<B, O>>) -> Self {
+        Self {
+            inner,
+            iters: Cell::new(0),
+        }
+    }
+
+    /// `iter` 被调用的次数。
+    pub fn iters(&self) -> usize {
+        self.iters.get()
+    }
+
+    /// 开始 `state` 所在的轮次，跳过已经训练的批次。恢复训练时先重放本轮开始前的洗牌，使之后的迭代和没有中断时相同。
+    pub fn epoch<'a>(&'a self, state: &mut TrainState) -> impl Iterator<Item = O> + use<'a, B, O> {
+        while self.iters() < state.epoch_start_iters {
+            drop(self.iter());
+        }
+        state.epoch_start_iters = self.iters();
+
+        self.iter().skip(state.batches_done)
+    }
+
+    /// 用训练数据评估模型。`f` 得到的是数据加载器的副本，随机数生成器复制自当前的状态，
+    /// 迭代副本不影响之后各轮的洗牌顺序。
+    ///
+    /// 副本中 `iter` 的调用次数无从得知，恢复时无法重放，所以评估不推进训练数据加载器的随机数生成器。
+    /// 和直接用训练数据加载器评估相比，之后各轮的洗牌顺序会不同。
+    pub fn eval<R>(&self, f: impl FnOnce(&dyn DataLoader<B, O>) -> R) -> R {
+        let v = self.inner.slice(0, self.inner.num_items());
+        f(v.as_ref())
+    }
+
+    fn iter(&self) -> Box<dyn DataLoaderIterator<O> + '_> {
+        self.iters.set(self.iters() + 1);
+        self.inner.iter()
+    }
+}
+
+/// 定期把训练状态保存到目录 `dir`。目录的内容为 [`checkpoint::save_checkpoint`] 保存的模型，
+/// 加上 [`OPTIMIZER_FILE`] 和 [`STATE_FILE`]。
+pub struct Checkpointer {
+    dir: PathBuf,
+    meta: CheckpointMeta,
+    every: usize,
+}
+
+impl Checkpointer {
+    /// 每 `every` 步保存一次，为 0 时不保存。
+    pub fn new(dir: impl Into<PathBuf>, meta: CheckpointMeta, every: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            meta,
+            every,
+        }
+    }
+
+    /// 在每个优化步之后调用，`state.step` 是保存间隔的倍数时保存检查点并重设后端的随机数生成器。返回是否保存了检查点。
+    pub fn on_step<B, O>(&self, model: &GptModel<B>, optimizer: &O, state: &mut TrainState) -> anyhow::Result<bool>
+    where
+        B: AutodiffBackend,
+        O: Optimizer<GptModel<B>, B>,
+    {
+        if self.every == 0 || state.step % self.every != 0 {
+            return Ok(false);
+        }
+
+        self.save(model, optimizer, state)
+            .with_context(|| format!("save training checkpoint to {:?}", self.dir))?;
+        state.reseed::<B>();
+
+        Ok(true)
+    }
+
+    /// 保存检查点。先写入临时目录再替换旧的检查点，保存过程中断时旧的检查点仍然可用。
+    pub fn save<B, O>(&self, model: &GptModel<B>, optimizer: &O, state: &TrainState) -> anyhow::Result<()>
+    where
+        B: AutodiffBackend,
+        O: Optimizer<GptModel<B>, B>,
+    {
+        let tmp = with_suffix(&self.dir, "tmp");
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp).context("remove stale temporary dir")?;
+        }
+
+        checkpoint::save_checkpoint(model, &self.meta, &tmp).context("save model")?;
+
+        let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+        recorder
+            .record(optimizer.to_record(), tmp.join(OPTIMIZER_FILE))
+            .context("save optimizer")?;
+
+        let f = File::create(tmp.join(STATE_FILE)).context("create state file")?;
+        serde_json::to_writer_pretty(f, state).context("save state")?;
+
+        let old = with_suffix(&self.dir, "old");
+        if self.dir.exists() {
+            // 上次保存在删除旧检查点之前中断时会留下 old，此时 dir 已是完整的检查点，old 可以直接删除
+            if old.exists() {
+                fs::remove_dir_all(&old).context("remove stale old checkpoint")?;
+            }
+            fs::rename(&self.dir, &old).context("move old checkpoint")?;
+        }
+        fs::rename(&tmp, &self.dir).context("move new checkpoint")?;
+        if old.exists() {
+            fs::remove_dir_all(&old).context("remove old checkpoint")?;
+        }
+
+        Ok(())
+    }
+
+    /// 读取最近一次保存的检查点，返回模型、加载了状态的 `optimizer` 以及训练进度，并重设后端的随机数生成器。
+    pub fn resume<B, O>(&self, optimizer: O, device: &B::Device) -> anyhow::Result<(GptModel<B>, O, TrainState)>
+    where
+        B: AutodiffBackend,
+        O: Optimizer<GptModel<B>, B>,
+    {
+        // 替换检查点的过程中断时，旧的检查点还在
+        let old = with_suffix(&self.dir, "old");
+        let dir = if !self.dir.exists() && old.exists() {
+            &old
+        } else {
+            &self.dir
+        };
+
+        let (model, meta) = checkpoint::load_checkpoint::<B>(dir, device).context("load model")?;
+        anyhow::ensure!(
+            meta.head == self.meta.head,
+            "checkpoint has {:?}, but training expects {:?}",
+            meta.head,
+            self.meta.head
+        );
+        let (got, expected) = (
+            serde_json::to_value(meta.config)?,
+            serde_json::to_value(self.meta.config)?,
+        );
+        anyhow::ensure!(
+            got == expected,
+            "checkpoint config {got} differs from training config {expected}"
+        );
+
+        let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+        let record = recorder
+            .load(dir.join(OPTIMIZER_FILE), device)
+            .context("load optimizer")?;
+        let optimizer = optimizer.load_record(record);
+
+        let f = File::open(dir.join(STATE_FILE)).context("open state file")?;
+        let state: TrainState = serde_json::from_reader(f).context("load state")?;
+        state.reseed::<B>();
+
+        Ok((model, optimizer, state))
+    }
+}
+
+fn with_suffix(dir: &Path, suffix: &str) -> PathBuf {
+    let mut name = dir.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
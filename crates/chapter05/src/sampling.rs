@@ -0,0 +1,334 @@
+//! 采样前对 logits 的调整和过滤。
+//!
+//! 所有函数的 `logits` 维度均为 (batch-size, vocab-size)，被过滤的 token 的 logit 置为 -inf。
+//! 需要历史 token 的函数以 `histories[i]` 表示第 i 行的历史 token。
+//!
+//! 生成时每一步按顺序应用一组 [`LogitsProcessor`]，内置的处理器包装了本模块的各个函数，
+//! 也可以实现该 trait 添加自定义的处理器。
+use std::time::Duration;
+
+use burn::prelude::*;
+use burn::tensor::activation;
+
+use crate::utils::GenerateOptions;
+use crate::x::TensorExt;
+
+/// 构造加到 logits 上的偏置，维度为 (1, vocab-size)：`bias` 中的偏置加到对应 token 上，`banned` 中的 token 为 -inf。
+///
+/// token 编号超出词表，或者所有 token 都被禁止（此时 softmax 的结果为 NaN）时返回错误。
+pub fn logit_bias<B: Backend>(
+    bias: &[(u32, f32)],
+    banned: &[u32],
+    vocab_size: usize,
+    device: &B::Device,
+) -> anyhow::Result<Tensor<B, 2>> {
+    let mut v = vec![0.0f32; vocab_size];
+    for &(id, b) in bias {
+        anyhow::ensure!(
+            (id as usize) < vocab_size,
+            "logit bias token {id} out of vocab (size {vocab_size})"
+        );
+        v[id as usize] += b;
+    }
+    for &id in banned {
+        anyhow::ensure!(
+            (id as usize) < vocab_size,
+            "banned token {id} out of vocab (size {vocab_size})"
+        );
+        v[id as usize] = f32::NEG_INFINITY;
+    }
+    anyhow::ensure!(
+        v.iter().any(|&b| b != f32::NEG_INFINITY),
+        "logit bias and banned tokens exclude every token"
+    );
+
+    Ok(Tensor::<B, 1>::from_floats(v.as_slice(), device).unsqueeze::<2>())
+}
+
+/// CTRL 论文提出的重复惩罚：出现过的 token 的 logit 为正时除以 `penalty`，为负时乘以 `penalty`。
+/// `penalty` 大于 1 时降低重复的概率。和 Hugging Face 的实现一样，历史包括输入和已生成的 token。
+pub fn apply_repetition_penalty<B: Backend>(logits: Tensor<B, 2>, histories: &[&[u32]], penalty: f32) -> Tensor<B, 2> {
+    if penalty == 1.0 {
+        return logits;
+    }
+
+    let seen = token_counts::<B>(histories, &logits).greater_elem(0.0);
+
+    let penalized = (logits.clone() * penalty).mask_where(logits.clone().greater_elem(0.0), logits.clone() / penalty);
+    logits.mask_where(seen, penalized)
+}
+
+/// OpenAI 风格的频率惩罚和存在惩罚：token 的 logit 减去 `出现次数 * frequency + (出现过 ? presence : 0)`。
+/// 历史通常只包括已生成的 token。
+pub fn apply_frequency_presence_penalty<B: Backend>(
+    logits: Tensor<B, 2>,
+    histories: &[&[u32]],
+    frequency: f32,
+    presence: f32,
+) -> Tensor<B, 2> {
+    if frequency == 0.0 && presence == 0.0 {
+        return logits;
+    }
+
+    let counts = token_counts::<B>(histories, &logits);
+    let presented = counts.clone().greater_elem(0.0).float();
+
+    logits - counts * frequency - presented * presence
+}
+
+/// 只保留每行 logit 最大的 `k` 个 token。
+pub fn top_k<B: Backend>(logits: Tensor<B, 2>, k: usize) -> Tensor<B, 2> {
+    let threshold = logits.clone().topk(k, 1).min_dim(1);
+    mask_lower(logits, threshold)
+}
+
+/// 核采样（nucleus sampling）：按概率从大到小保留 token，直到累计概率达到 `p`。至少保留一个 token。
+pub fn top_p<B: Backend>(logits: Tensor<B, 2>, p: f32) -> Tensor<B, 2> {
+    if p >= 1.0 {
+        return logits;
+    }
+
+    let sorted = logits.clone().sort_descending(1);
+    let probas = activation::softmax(sorted.clone(), 1);
+
+    // 某个 token 之前的累计概率小于 p 时保留它，所以概率最大的 token 总会被保留。
+    let exclusive_cumsum = probas.clone().cumsum() - probas;
+    let nkept = exclusive_cumsum.lower_elem(p).int().sum_dim(1).clamp_min(1);
+
+    let threshold = sorted.gather(1, nkept - 1);
+    mask_lower(logits, threshold)
+}
+
+/// min-p 采样：丢弃概率小于 `p * 最大概率` 的 token。
+pub fn min_p<B: Backend>(logits: Tensor<B, 2>, p: f32) -> Tensor<B, 2> {
+    let probas = activation::softmax(logits.clone(), 1);
+    let threshold = probas.clone().max_dim(1) * p;
+    let discarded = probas.lower(threshold.expand(logits.dims()));
+
+    logits.mask_fill(discarded, f32::NEG_INFINITY)
+}
+
+/// 丢弃 logit 小于 `threshold`（维度为 (batch-size, 1)）的 token。
+fn mask_lower<B: Backend>(logits: Tensor<B, 2>, threshold: Tensor<B, 2>) -> Tensor<B, 2> {
+    let discarded = logits.clone().lower(threshold.expand(logits.dims()));
+    logits.mask_fill(discarded, f32::NEG_INFINITY)
+}
+
+/// 统计每行历史中各 token 的出现次数，维度和 `logits` 相同。
+fn token_counts<B: Backend>(histories: &[&[u32]], logits: &Tensor<B, 2>) -> Tensor<B, 2> {
+    let [batch_size, vocab_size] = logits.dims();
+    assert_eq!(batch_size, histories.len(), "histories must have one element per row");
+
+    let mut counts = vec![0.0f32; batch_size * vocab_size];
+    for (i, h) in histories.iter().enumerate() {
+        for &id in h.iter() {
+            counts[i * vocab_size + id as usize] += 1.0;
+        }
+    }
+
+    Tensor::<B, 1>::from_floats(counts.as_slice(), &logits.device()).reshape([batch_size, vocab_size])
+}
+
+/// 允许生成的 token 以外的 logit 都置为 -inf，例如通过生成做分类时，把输出限制在标签对应的 token 上。
+pub fn allow_only<B: Backend>(logits: Tensor<B, 2>, allowed: &[u32]) -> Tensor<B, 2> {
+    let [_, vocab_size] = logits.dims();
+    let mut v = vec![f32::NEG_INFINITY; vocab_size];
+    for &id in allowed {
+        v[id as usize] = 0.0;
+    }
+
+    let v = Tensor::<B, 1>::from_floats(v.as_slice(), &logits.device()).unsqueeze::<2>();
+    logits + v
+}
+
+/// 生成过程中每一步的状态，供 [`LogitsProcessor`] 和 [`StoppingCriteria`](crate::stopping::StoppingCriteria)
+/// 使用。
+#[derive(Clone, Copy, Debug)]
+pub struct GenerateState<'a> {
+    /// 第 i 个元素为第 i 行的输入和已生成的 token，不含填充。
+    pub seqs: &'a [Vec<u32>],
+    /// 第 i 个元素为第 i 行输入的长度。
+    pub prompt_lens: &'a [usize],
+    /// 从开始生成到现在经过的时间。
+    pub elapsed: Duration,
+}
+
+impl<'a> GenerateState<'a> {
+    /// 各行的输入和已生成的 token。
+    pub fn histories(&self) -> Vec<&'a [u32]> {
+        self.seqs.iter().map(Vec::as_slice).collect()
+    }
+
+    /// 各行已生成的 token。
+    pub fn generated(&self) -> Vec<&'a [u32]> {
+        (0..self.seqs.len()).map(|i| self.generated_row(i)).collect()
+    }
+
+    /// 第 `row` 行已生成的 token。
+    pub fn generated_row(&self, row: usize) -> &'a [u32] {
+        &self.seqs[row][self.prompt_lens[row]..]
+    }
+}
+
+/// 在采样前调整 logits（维度为 (batch-size, vocab-size)）。
+pub trait LogitsProcessor<B: Backend> {
+    fn process(&self, logits: Tensor<B, 2>, state: &GenerateState) -> Tensor<B, 2>;
+}
+
+impl<B: Backend, F> LogitsProcessor<B> for F
+where
+    F: Fn(Tensor<B, 2>, &GenerateState) -> Tensor<B, 2>,
+{
+    fn process(&self, logits: Tensor<B, 2>, state: &GenerateState) -> Tensor<B, 2> {
+        self(logits, state)
+    }
+}
+
+/// 按添加的顺序依次应用的一组 [`LogitsProcessor`]。
+pub struct LogitsProcessors<B: Backend> {
+    processors: Vec<Box<dyn LogitsProcessor<B>>>,
+}
+
+impl<B: Backend> Default for LogitsProcessors<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Backend> LogitsProcessors<B> {
+    pub fn new() -> Self {
+        Self { processors: vec![] }
+    }
+
+    /// 按 `opts` 构造处理链，顺序参见 [`GenerateOptions`]。温度为 0（贪心解码）时不添加温度缩放、top-k、top-p 和
+    /// min-p，它们不影响 argmax 的结果。
+    ///
+    /// logit 偏置和禁用 token 在这里检查并转换为张量，不合法时返回错误，参见 [`logit_bias`]。
+    pub fn from_options(opts: &GenerateOptions, vocab_size: usize, device: &B::Device) -> anyhow::Result<Self> {
+        let mut out = Self::new();
+        if !opts.logit_bias.is_empty() || !opts.banned_tokens.is_empty() {
+            let bias = logit_bias(&opts.logit_bias, &opts.banned_tokens, vocab_size, device)?;
+            out.push(LogitBias(bias));
+        }
+        out.push(RepetitionPenalty(opts.repetition_penalty));
+        out.push(FrequencyPresencePenalty {
+            frequency: opts.frequency_penalty,
+            presence: opts.presence_penalty,
+        });
+
+        if opts.temperature != 0.0 {
+            out.push(Temperature(opts.temperature));
+            if let Some(k) = opts.topk {
+                out.push(TopK(k));
+            }
+            if let Some(p) = opts.topp {
+                out.push(TopP(p));
+            }
+            if let Some(p) = opts.minp {
+                out.push(MinP(p));
+            }
+        }
+
+        Ok(out)
+    }
+
+    pub fn push(&mut self, p: impl LogitsProcessor<B> + 'static) {
+        self.processors.push(Box::new(p));
+    }
+
+    pub fn with(mut self, p: impl LogitsProcessor<B> + 'static) -> Self {
+        self.push(p);
+        self
+    }
+}
+
+impl<B: Backend> LogitsProcessor<B> for LogitsProcessors<B> {
+    fn process(&self, logits: Tensor<B, 2>, state: &GenerateState) -> Tensor<B, 2> {
+        self.processors
+            .iter()
+            .fold(logits, |logits, p| p.process(logits, state))
+    }
+}
+
+/// logits 除以温度。
+#[derive(Clone, Copy, Debug)]
+pub struct Temperature(pub f32);
+
+impl<B: Backend> LogitsProcessor<B> for Temperature {
+    fn process(&self, logits: Tensor<B, 2>, _: &GenerateState) -> Tensor<B, 2> {
+        logits / self.0
+    }
+}
+
+/// 参见 [`top_k`]。
+#[derive(Clone, Copy, Debug)]
+pub struct TopK(pub usize);
+
+impl<B: Backend> LogitsProcessor<B> for TopK {
+    fn process(&self, logits: Tensor<B, 2>, _: &GenerateState) -> Tensor<B, 2> {
+        top_k(logits, self.0)
+    }
+}
+
+/// 参见 [`top_p`]。
+#[derive(Clone, Copy, Debug)]
+pub struct TopP(pub f32);
+
+impl<B: Backend> LogitsProcessor<B> for TopP {
+    fn process(&self, logits: Tensor<B, 2>, _: &GenerateState) -> Tensor<B, 2> {
+        top_p(logits, self.0)
+    }
+}
+
+/// 参见 [`min_p`]。
+#[derive(Clone, Copy, Debug)]
+pub struct MinP(pub f32);
+
+impl<B: Backend> LogitsProcessor<B> for MinP {
+    fn process(&self, logits: Tensor<B, 2>, _: &GenerateState) -> Tensor<B, 2> {
+        min_p(logits, self.0)
+    }
+}
+
+/// 参见 [`apply_repetition_penalty`]，历史包括输入和已生成的 token。
+#[derive(Clone, Copy, Debug)]
+pub struct RepetitionPenalty(pub f32);
+
+impl<B: Backend> LogitsProcessor<B> for RepetitionPenalty {
+    fn process(&self, logits: Tensor<B, 2>, state: &GenerateState) -> Tensor<B, 2> {
+        apply_repetition_penalty(logits, &state.histories(), self.0)
+    }
+}
+
+/// 参见 [`apply_frequency_presence_penalty`]，历史只包括已生成的 token。
+#[derive(Clone, Copy, Debug)]
+pub struct FrequencyPresencePenalty {
+    pub frequency: f32,
+    pub presence: f32,
+}
+
+impl<B: Backend> LogitsProcessor<B> for FrequencyPresencePenalty {
+    fn process(&self, logits: Tensor<B, 2>, state: &GenerateState) -> Tensor<B, 2> {
+        apply_frequency_presence_penalty(logits, &state.generated(), self.frequency, self.presence)
+    }
+}
+
+/// 加上 [`logit_bias`] 构造的偏置（维度为 (1, vocab-size)）。
+#[derive(Clone, Debug)]
+pub struct LogitBias<B: Backend>(pub Tensor<B, 2>);
+
+impl<B: Backend> LogitsProcessor<B> for LogitBias<B> {
+    fn process(&self, logits: Tensor<B, 2>, _: &GenerateState) -> Tensor<B, 2> {
+        logits + self.0.clone()
+    }
+}
+
+/// 参见 [`allow_only`]。
+#[derive(Clone, Debug)]
+pub struct AllowedTokens(pub Vec<u32>);
+
+impl<B: Backend> LogitsProcessor<B> for AllowedTokens {
+    fn process(&self, logits: Tensor<B, 2>, _: &GenerateState) -> Tensor<B, 2> {
+        allow_only(logits, &self.0)
+    }
+}
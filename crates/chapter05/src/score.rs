@@ -0,0 +1,140 @@
+//! 计算文本的逐 token 对数概率、负对数似然（NLL）和困惑度，用于在验证数据上比较不同的检查点。
+//!
+//! 超过上下文长度的文本按 Hugging Face 的做法使用带步长的滑动窗口：每个窗口长度为 `max_length`，相邻窗口的
+//! 起点相距 `stride`，每个 token 只在第一个包含它的窗口中计分，因此除了最前面的 token，都以至少
+//! `max_length - stride` 个 token 为上下文。
+use std::collections::HashSet;
+
+use burn::prelude::*;
+use burn::tensor::activation;
+use chapter04::GptModel;
+use tiktoken::ext::Encoding;
+
+#[derive(Config, Debug)]
+pub struct ScoreOptions {
+    /// 窗口长度，不能超过模型的上下文长度。
+    pub max_length: usize,
+    /// 相邻窗口的起点之间的距离，必须在 [1, max_length) 内。
+    pub stride: usize,
+}
+
+/// 一段文本的得分。
+#[derive(Clone, Debug, PartialEq)]
+pub struct Score {
+    /// 计分的 token。
+    pub token_ids: Vec<u32>,
+    /// 每个计分 token 以其前面的 token 为条件的对数概率。
+    pub logprobs: Vec<f32>,
+}
+
+impl Score {
+    pub fn num_tokens(&self) -> usize {
+        self.token_ids.len()
+    }
+
+    /// 负对数似然之和。
+    pub fn nll(&self) -> f64 {
+        -self.logprobs.iter().map(|&v| v as f64).sum::<f64>()
+    }
+
+    /// 平均每个 token 的负对数似然，没有计分的 token 时为 NaN。
+    pub fn mean_nll(&self) -> f64 {
+        self.nll() / self.num_tokens() as f64
+    }
+
+    pub fn perplexity(&self) -> f64 {
+        self.mean_nll().exp()
+    }
+
+    /// 合并多段文本的得分，合并后的困惑度按 token 数加权。
+    pub fn merge(scores: impl IntoIterator<Item = Score>) -> Score {
+        let mut out = Score {
+            token_ids: vec![],
+            logprobs: vec![],
+        };
+        for s in scores {
+            out.token_ids.extend(s.token_ids);
+            out.logprobs.extend(s.logprobs);
+        }
+        out
+    }
+}
+
+/// 对 `ids` 中下标不小于 `skip` 的 token 计分。第一个 token 没有上下文，总是不计分。
+pub fn score_ids<B: Backend>(
+    model: &GptModel<B>,
+    ids: &[u32],
+    skip: usize,
+    opts: &ScoreOptions,
+    device: &B::Device,
+) -> Score {
+    assert!(
+        (1..opts.max_length).contains(&opts.stride),
+        "stride must be in [1, max_length)"
+    );
+
+    let mut out = Score {
+        token_ids: vec![],
+        logprobs: vec![],
+    };
+
+    // 下一个待计分的 token 的下标。跳过的 token 较多时，第一个窗口以 next 前面的 max_length-1 个 token 为上下文
+    let mut next = skip.max(1);
+    let mut begin = next.saturating_sub(opts.max_length - 1);
+    while next < ids.len() {
+        let end = (begin + opts.max_length).min(ids.len());
+        let window = &ids[begin..end];
+
+        // 第 j 个位置预测第 j+1 个 token
+        let input: Vec<i64> = window[..window.len() - 1].iter().map(|&v| v as i64).collect();
+        let targets: Vec<i64> = window[1..].iter().map(|&v| v as i64).collect();
+        let n = targets.len();
+
+        let input = Tensor::<B, 1, Int>::from_ints(input.as_slice(), device).reshape([1, n]);
+        let targets = Tensor::<B, 1, Int>::from_ints(targets.as_slice(), device).reshape([1, n, 1]);
+        let logprobs = activation::log_softmax(model.forward(input), 2)
+            .gather(2, targets)
+            .into_data()
+            .convert::<f32>()
+            .to_vec::<f32>()
+            .expect("read logprobs");
+
+        for t in next.max(begin + 1)..end {
+            out.token_ids.push(ids[t]);
+            out.logprobs.push(logprobs[t - begin - 1]);
+        }
+        next = end;
+        begin += opts.stride;
+    }
+
+    out
+}
+
+/// 对文本中除第一个 token 外的所有 token 计分。
+pub fn score_text<B: Backend>(
+    model: &GptModel<B>,
+    tokenizer: &Encoding,
+    text: &str,
+    opts: &ScoreOptions,
+    device: &B::Device,
+) -> Score {
+    let ids = tokenizer.encode(text, &HashSet::from(["<|endoftext|>"]));
+    score_ids(model, &ids, 1, opts, device)
+}
+
+/// 以 `context` 为条件对 `continuation` 的 token 计分。两者分别分词，`continuation` 的 token 和单独分词时相同。
+pub fn score_continuation<B: Backend>(
+    model: &GptModel<B>,
+    tokenizer: &Encoding,
+    context: &str,
+    continuation: &str,
+    opts: &ScoreOptions,
+    device: &B::Device,
+) -> Score {
+    let allowed_specials = HashSet::from(["<|endoftext|>"]);
+    let mut ids = tokenizer.encode(context, &allowed_specials);
+    let skip = ids.len();
+    ids.extend(tokenizer.encode(continuation, &allowed_specials));
+
+    score_ids(model, &ids, skip, opts, device)
+}
@@ -0,0 +1,199 @@
+//! 投机解码（speculative decoding）：小的草稿模型先逐个生成若干 token，目标模型再一次前向验证这些 token。
+//!
+//! 验证时按拒绝采样接受草稿 token，输出的分布和只用目标模型生成完全相同；贪心解码时输出和目标模型的贪心解码相同。
+use std::time::Instant;
+
+use burn::prelude::*;
+use chapter04::GptModel;
+
+use crate::rand::{self, SplitMix64};
+use crate::sampling::{GenerateState, LogitsProcessor, LogitsProcessors};
+use crate::stopping::{StoppingCriteria, StoppingCriteriaList};
+use crate::utils::GenerateOptions;
+
+/// 投机解码的统计。
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpeculativeStats {
+    /// 验证的轮数，即目标模型前向计算的次数。
+    pub rounds: usize,
+    /// 草稿模型生成的 token 数。
+    pub drafted: usize,
+    /// 被目标模型接受的草稿 token 数。
+    pub accepted: usize,
+}
+
+impl SpeculativeStats {
+    /// 草稿 token 的接受率。
+    pub fn acceptance_rate(&self) -> f64 {
+        if self.drafted == 0 {
+            return 0.0;
+        }
+        self.accepted as f64 / self.drafted as f64
+    }
+}
+
+/// 以 `prompt` 为输入进行投机解码，每轮由 `draft` 生成最多 `num_draft_tokens` 个 token。对 logits 的处理和停止条件
+/// 和 [`crate::utils::generate_batch`] 相同，两个模型使用相同的处理。两个模型的词表大小不同时返回错误。
+///
+/// 返回输入和生成的 token（不含 EOS）以及统计。输入和草稿的总长度超过 `opts.context_size` 后，各位置的上下文窗口
+/// 和逐个生成时不同，此时不再使用草稿，每轮只由目标模型生成一个 token。
+pub fn speculative_generate<B: Backend>(
+    target: &GptModel<B>,
+    draft: &GptModel<B>,
+    prompt: &[u32],
+    opts: &GenerateOptions,
+    num_draft_tokens: usize,
+    device: &B::Device,
+) -> anyhow::Result<(Vec<u32>, SpeculativeStats)> {
+    assert!(!prompt.is_empty(), "prompt must not be empty");
+    let vocab_size = target.out_head.weight.dims()[1];
+    let draft_vocab_size = draft.out_head.weight.dims()[1];
+    anyhow::ensure!(
+        vocab_size == draft_vocab_size,
+        "draft vocab size {draft_vocab_size} does not match target vocab size {vocab_size}"
+    );
+
+    let start = Instant::now();
+    let processors = LogitsProcessors::<B>::from_options(opts, vocab_size, device)?;
+    let stopping = StoppingCriteriaList::from_options(opts);
+    let greedy = opts.temperature == 0.0;
+    let mut rng = SplitMix64::for_row(opts.seed.unwrap_or_else(::rand::random), 0);
+
+    let prompt_lens = [prompt.len()];
+    let mut seqs = [prompt.to_vec()];
+    let mut stats = SpeculativeStats::default();
+
+    // 以 seqs 为历史处理 logits（维度为 (1, vocab-size)），返回处理后的 logits
+    let process = |logits: Tensor<B, 2>, seqs: &[Vec<u32>]| -> Vec<f32> {
+        let state = GenerateState {
+            seqs,
+            prompt_lens: &prompt_lens,
+            elapsed: start.elapsed(),
+        };
+        processors
+            .process(logits, &state)
+            .into_data()
+            .convert::<f32>()
+            .to_vec()
+            .expect("read logits")
+    };
+
+    'outer: loop {
+        let generated = seqs[0].len() - prompt.len();
+        if generated >= opts.max_new_tokens {
+            break;
+        }
+        let k = num_draft_tokens
+            .min(opts.max_new_tokens - generated - 1)
+            .min(opts.context_size.saturating_sub(seqs[0].len()));
+
+        // 1. 草稿模型逐个生成 k 个 token，记录各 token 的分布
+        let mut drafted = [seqs[0].clone()];
+        let mut draft_probas = Vec::with_capacity(k);
+        for _ in 0..k {
+            let logits = process(last_logits(draft, &drafted[0], 1, opts.context_size, device), &drafted);
+            let q = softmax(&logits);
+            let token = if greedy {
+                argmax(&logits)
+            } else {
+                rand::sample_index(&q, &mut rng)
+            };
+            drafted[0].push(token as u32);
+            draft_probas.push(q);
+        }
+        let drafted = drafted[0].split_off(seqs[0].len());
+
+        // 2. 目标模型一次前向得到 k+1 个位置的 logits，第 j 个位置预测第 j 个草稿 token
+        let logits = last_logits(
+            target,
+            &[&seqs[0][..], &drafted].concat(),
+            k + 1,
+            opts.context_size,
+            device,
+        );
+        stats.rounds += 1;
+        stats.drafted += k;
+
+        // 3. 依次验证草稿 token，第一个被拒绝的位置改为从剩余的分布中采样；全部接受时额外采样一个 token
+        for (j, row) in logits.iter_dim(0).enumerate() {
+            let logits = process(row, &seqs);
+            let token = match drafted.get(j) {
+                Some(&d) => {
+                    let d = d as usize;
+                    let token = if greedy {
+                        argmax(&logits)
+                    } else {
+                        let (p, q) = (softmax(&logits), &draft_probas[j]);
+                        if rng.next_f64() < (p[d] / q[d]) as f64 {
+                            d
+                        } else {
+                            let residual: Vec<f32> = p.iter().zip(q).map(|(p, q)| (p - q).max(0.0)).collect();
+                            if residual.iter().sum::<f32>() > 0.0 {
+                                rand::sample_index(&residual, &mut rng)
+                            } else {
+                                rand::sample_index(&p, &mut rng)
+                            }
+                        }
+                    };
+                    if token == d {
+                        stats.accepted += 1;
+                    }
+                    token
+                }
+                None if greedy => argmax(&logits),
+                None => rand::sample_index(&softmax(&logits), &mut rng),
+            };
+            seqs[0].push(token as u32);
+
+            let state = GenerateState {
+                seqs: &seqs,
+                prompt_lens: &prompt_lens,
+                elapsed: start.elapsed(),
+            };
+            if let Some(n) = stopping.should_stop(&state, 0) {
+                let len = seqs[0].len() - n.min(seqs[0].len() - prompt.len());
+                seqs[0].truncate(len);
+                break 'outer;
+            }
+            if drafted.get(j) != Some(&(token as u32)) {
+                break;
+            }
+        }
+    }
+
+    let [out] = seqs;
+    Ok((out, stats))
+}
+
+/// 以 `seq` 末尾的 `context_size` 个 token 为输入，返回最后 `n` 个位置的 logits，维度为 (n, vocab-size)。
+fn last_logits<B: Backend>(
+    model: &GptModel<B>,
+    seq: &[u32],
+    n: usize,
+    context_size: usize,
+    device: &B::Device,
+) -> Tensor<B, 2> {
+    let ids: Vec<i64> = seq[seq.len().saturating_sub(context_size)..]
+        .iter()
+        .map(|&v| v as i64)
+        .collect();
+    let seq_len = ids.len();
+    let idx = Tensor::<B, 1, Int>::from_ints(ids.as_slice(), device).unsqueeze::<2>();
+
+    model.forward(idx).slice(s![.., seq_len - n.., ..]).squeeze::<2>(0)
+}
+
+fn softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exp: Vec<f32> = logits.iter().map(|v| (v - max).exp()).collect();
+    let sum: f32 = exp.iter().sum();
+    exp.into_iter().map(|v| v / sum).collect()
+}
+
+/// 第一个最大值的下标。
+fn argmax(logits: &[f32]) -> usize {
+    logits
+        .iter()
+        .enumerate()
+        .fold(0, |best, (i, v)| if *v > logits[best] { i } else { best })
+}
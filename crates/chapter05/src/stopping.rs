@@ -0,0 +1,134 @@
+//! 生成的停止条件。每一步为各行追加新 token 后，按行检查是否应当停止。
+use std::collections::HashSet;
+use std::time::Duration;
+
+use tiktoken::ext::Encoding;
+
+use crate::sampling::GenerateState;
+use crate::utils::GenerateOptions;
+
+pub trait StoppingCriteria {
+    /// 第 `row` 行追加新 token 后调用。返回 `Some(n)` 表示该行停止生成，并丢弃末尾的 n 个 token。
+    fn should_stop(&self, state: &GenerateState, row: usize) -> Option<usize>;
+}
+
+/// 任一条件满足即停止。多个条件同时满足时，丢弃的 token 数取最大值。
+#[derive(Default)]
+pub struct StoppingCriteriaList<'a> {
+    criteria: Vec<Box<dyn StoppingCriteria + 'a>>,
+}
+
+impl<'a> StoppingCriteriaList<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `opts.eos_id` 和 `opts.eos_ids` 对应的 [`EosTokens`]。`opts.max_new_tokens` 总是作为生成 token 数的上限，
+    /// 无需额外添加。停止字符串需要分词器，参见 [`StopStrings`]。
+    pub fn from_options(opts: &GenerateOptions) -> Self {
+        let out = Self::new();
+        match opts.all_eos_ids() {
+            v if v.is_empty() => out,
+            v => out.with(EosTokens::new(v)),
+        }
+    }
+
+    pub fn push(&mut self, c: impl StoppingCriteria + 'a) {
+        self.criteria.push(Box::new(c));
+    }
+
+    pub fn with(mut self, c: impl StoppingCriteria + 'a) -> Self {
+        self.push(c);
+        self
+    }
+}
+
+impl StoppingCriteria for StoppingCriteriaList<'_> {
+    fn should_stop(&self, state: &GenerateState, row: usize) -> Option<usize> {
+        self.criteria.iter().filter_map(|c| c.should_stop(state, row)).max()
+    }
+}
+
+/// 生成的 token 数达到上限，用于设置比 [`GenerateOptions::max_new_tokens`] 更小的上限。
+#[derive(Clone, Copy, Debug)]
+pub struct MaxNewTokens(pub usize);
+
+impl StoppingCriteria for MaxNewTokens {
+    fn should_stop(&self, state: &GenerateState, row: usize) -> Option<usize> {
+        (state.generated_row(row).len() >= self.0).then_some(0)
+    }
+}
+
+/// 生成了任一 EOS token。EOS token 不会保留在输出中。
+#[derive(Clone, Debug)]
+pub struct EosTokens(pub HashSet<u32>);
+
+impl EosTokens {
+    pub fn new(ids: impl IntoIterator<Item = u32>) -> Self {
+        Self(ids.into_iter().collect())
+    }
+}
+
+impl StoppingCriteria for EosTokens {
+    fn should_stop(&self, state: &GenerateState, row: usize) -> Option<usize> {
+        let last = state.generated_row(row).last()?;
+        self.0.contains(last).then_some(1)
+    }
+}
+
+/// 每一步增量解码末尾的 token，生成的文本包含任一停止字符串即停止。
+///
+/// 停止字符串可能只占匹配的最后一个 token 的一部分，所以不会丢弃 token，需要在解码后用 [`trim_stop_sequences`]
+/// 截断文本。
+pub struct StopStrings<'a> {
+    tokenizer: &'a Encoding,
+    stops: Vec<String>,
+    /// 最长的停止字符串的字节数。每个 token 至少对应一个字节，所以只需解码这么多个末尾的 token。
+    max_len: usize,
+}
+
+impl<'a> StopStrings<'a> {
+    pub fn new(tokenizer: &'a Encoding, stops: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let stops: Vec<String> = stops.into_iter().map(Into::into).filter(|v| !v.is_empty()).collect();
+        let max_len = stops.iter().map(String::len).max().unwrap_or_default();
+
+        Self {
+            tokenizer,
+            stops,
+            max_len,
+        }
+    }
+}
+
+impl StoppingCriteria for StopStrings<'_> {
+    fn should_stop(&self, state: &GenerateState, row: usize) -> Option<usize> {
+        let generated = state.generated_row(row);
+        let tail = &generated[generated.len().saturating_sub(self.max_len)..];
+
+        let text = self.tokenizer.decode(tail).ok()?;
+        let text = String::from_utf8_lossy(&text);
+        self.stops.iter().any(|s| text.contains(s.as_str())).then_some(0)
+    }
+}
+
+/// 生成的耗时达到上限，所有行同时停止。
+#[derive(Clone, Copy, Debug)]
+pub struct MaxTime(pub Duration);
+
+impl StoppingCriteria for MaxTime {
+    fn should_stop(&self, state: &GenerateState, _: usize) -> Option<usize> {
+        (state.elapsed >= self.0).then_some(0)
+    }
+}
+
+/// 截断 `text` 中第一个停止字符串及其之后的内容。
+pub fn trim_stop_sequences<'a>(text: &'a str, stops: &[String]) -> &'a str {
+    let end = stops
+        .iter()
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| text.find(s.as_str()))
+        .min()
+        .unwrap_or(text.len());
+
+    &text[..end]
+}
@@ -0,0 +1,221 @@
+//! 流式生成：每生成一个 token 就返回，并增量解码出新的文本。
+use std::collections::HashSet;
+use std::time::Instant;
+
+use anyhow::Context as _;
+use burn::prelude::*;
+use chapter04::GptModel;
+use tiktoken::ext::Encoding;
+
+use crate::rand::SplitMix64;
+use crate::sampling::{GenerateState, LogitsProcessors};
+use crate::stopping::{self, StoppingCriteria, StoppingCriteriaList};
+use crate::utils::{self, GenerateOptions};
+
+/// 增量的 UTF-8 解码器。多字节字符可能被拆分到多个 token 中，不完整的字节会缓存到后续字节到达后再输出。
+#[derive(Clone, Debug, Default)]
+pub struct Utf8Decoder {
+    buf: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加 `bytes`，返回新解码出的完整字符。非法的字节序列替换为 U+FFFD。
+    pub fn push(&mut self, bytes: &[u8]) -> String {
+        self.buf.extend_from_slice(bytes);
+
+        let mut out = String::new();
+        let mut rest = self.buf.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(s) => {
+                    out.push_str(s);
+                    rest = &[];
+                    break;
+                }
+                Err(err) => {
+                    let (valid, after) = rest.split_at(err.valid_up_to());
+                    out.push_str(std::str::from_utf8(valid).expect("valid utf-8"));
+                    match err.error_len() {
+                        // 末尾的字节序列不完整，等待后续字节
+                        None => {
+                            rest = after;
+                            break;
+                        }
+                        Some(n) => {
+                            out.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[n..];
+                        }
+                    }
+                }
+            }
+        }
+
+        self.buf = rest.to_vec();
+        out
+    }
+
+    /// 输出缓存中剩余的字节，不完整的字节序列替换为 U+FFFD。
+    pub fn finish(&mut self) -> String {
+        let out = String::from_utf8_lossy(&self.buf).into_owned();
+        self.buf.clear();
+        out
+    }
+}
+
+/// [`Generator`] 每一步的输出。
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamToken {
+    pub id: u32,
+    /// 这一步新增的可以输出的文本。token 只包含不完整的 UTF-8 字节，或者文本可能是停止字符串的开头时为空，
+    /// 这些文本会在之后的步骤中输出。
+    pub text: String,
+}
+
+/// 逐个 token 生成的迭代器，批量大小为 1。对 logits 的处理和停止条件和 [`utils::generate_text`] 相同，
+/// 拼接各步的 `text` 即得到 [`utils::generate_text`] 的输出。
+///
+/// 生成 EOS 时，EOS 作为最后一个 token 返回，其文本为缓存中剩余的内容。输出的文本不含停止字符串。
+pub struct Generator<'a, B: Backend> {
+    model: &'a GptModel<B>,
+    tokenizer: &'a Encoding,
+    opts: GenerateOptions,
+    processors: LogitsProcessors<B>,
+    stopping: StoppingCriteriaList<'a>,
+    device: B::Device,
+
+    /// 只有一行，以便构造 [`GenerateState`]。
+    seqs: [Vec<u32>; 1],
+    prompt_lens: [usize; 1],
+    rngs: Option<Vec<SplitMix64>>,
+    decoder: Utf8Decoder,
+    /// 已解码但还未输出的文本，其末尾可能是某个停止字符串的开头。
+    pending: String,
+    start: Instant,
+    finished: bool,
+}
+
+impl<'a, B: Backend> Generator<'a, B> {
+    pub fn new(
+        model: &'a GptModel<B>,
+        tokenizer: &'a Encoding,
+        prompt: &str,
+        opts: GenerateOptions,
+        device: &B::Device,
+    ) -> anyhow::Result<Self> {
+        let allowed_specials = HashSet::from(["<|endoftext|>"]);
+        let prompt = tokenizer.encode(prompt, &allowed_specials);
+        assert!(!prompt.is_empty(), "prompt must not be empty");
+
+        let processors = LogitsProcessors::from_options(&opts, model.out_head.weight.dims()[1], device)?;
+        let stopping = StoppingCriteriaList::from_options(&opts);
+        let rngs = opts.seed.map(|seed| utils::row_rngs(seed, 1));
+
+        Ok(Self {
+            model,
+            tokenizer,
+            opts,
+            processors,
+            stopping,
+            device: device.clone(),
+            prompt_lens: [prompt.len()],
+            seqs: [prompt],
+            rngs,
+            decoder: Utf8Decoder::new(),
+            pending: String::new(),
+            start: Instant::now(),
+            finished: false,
+        })
+    }
+
+    /// 已生成的 token，不含 EOS。
+    pub fn generated(&self) -> &[u32] {
+        &self.seqs[0][self.prompt_lens[0]..]
+    }
+
+    fn step(&mut self) -> anyhow::Result<Option<StreamToken>> {
+        if self.finished || self.opts.max_new_tokens == 0 {
+            return Ok(None);
+        }
+
+        let seq = &self.seqs[0];
+        let ids: Vec<i64> = seq[seq.len().saturating_sub(self.opts.context_size)..]
+            .iter()
+            .map(|&v| v as i64)
+            .collect();
+        let idx = Tensor::<B, 1, Int>::from_ints(ids.as_slice(), &self.device).unsqueeze::<2>();
+        let logits = self.model.forward(idx).slice(s![.., -1, ..]).squeeze::<2>(1);
+
+        let state = GenerateState {
+            seqs: &self.seqs,
+            prompt_lens: &self.prompt_lens,
+            elapsed: self.start.elapsed(),
+        };
+        let greedy = self.opts.temperature == 0.0;
+        let next = utils::sample(logits, &self.processors, &state, greedy, self.rngs.as_deref_mut());
+        let id = next.into_data().iter::<i64>().next().context("read next token id")? as u32;
+        self.seqs[0].push(id);
+
+        let state = GenerateState {
+            seqs: &self.seqs,
+            prompt_lens: &self.prompt_lens,
+            elapsed: self.start.elapsed(),
+        };
+        let stop = self.stopping.should_stop(&state, 0);
+        // 只有 EOS 会被丢弃，其文本不输出
+        if stop.is_some_and(|n| n > 0) {
+            self.seqs[0].pop();
+        } else {
+            let b = self.tokenizer.decode(&[id]).context("decode token")?;
+            let text = self.decoder.push(&b);
+            self.pending.push_str(&text);
+        }
+
+        self.finished = stop.is_some() || self.generated().len() >= self.opts.max_new_tokens;
+        if self.finished {
+            let text = self.decoder.finish();
+            self.pending.push_str(&text);
+        }
+
+        let stops = &self.opts.stop_sequences;
+        let end = stopping::trim_stop_sequences(&self.pending, stops).len();
+        if end < self.pending.len() {
+            self.pending.truncate(end);
+            self.finished = true;
+        }
+
+        let text = if self.finished {
+            std::mem::take(&mut self.pending)
+        } else {
+            let keep = partial_stop_len(&self.pending, stops);
+            let rest = self.pending.split_off(self.pending.len() - keep);
+            std::mem::replace(&mut self.pending, rest)
+        };
+
+        Ok(Some(StreamToken { id, text }))
+    }
+}
+
+impl<B: Backend> Iterator for Generator<'_, B> {
+    type Item = anyhow::Result<StreamToken>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step().transpose()
+    }
+}
+
+/// `text` 末尾可能是某个停止字符串开头的最长部分的字节数。
+fn partial_stop_len(text: &str, stops: &[String]) -> usize {
+    stops
+        .iter()
+        .filter_map(|s| {
+            (1..s.len().min(text.len() + 1))
+                .rev()
+                .find(|&k| text.is_char_boundary(text.len() - k) && s.starts_with(&text[text.len() - k..]))
+        })
+        .max()
+        .unwrap_or_default()
+}
@@ -1,20 +1,66 @@
 use std::collections::HashSet;
+use std::time::{Duration, Instant};
 
 use anyhow::Context as _;
 use burn::nn::loss::CrossEntropyLossConfig;
 use burn::prelude::*;
 use burn::tensor::{DType, activation};
-use chapter04::GptModel;
+use chapter04::{ForwardOptions, GptModel};
 use tiktoken::ext::Encoding;
 
-#[derive(Config, Copy)]
+use crate::rand::{self, SplitMix64};
+use crate::sampling::{GenerateState, LogitsProcessor, LogitsProcessors};
+use crate::stopping::{self, StopStrings, StoppingCriteria, StoppingCriteriaList};
+
+/// 生成文本的选项。对 logits 的处理顺序为：logit 偏置和禁用 token、重复惩罚、频率/存在惩罚、温度缩放、
+/// top-k、top-p、min-p。温度为 0 时使用贪心解码，top-k/top-p/min-p 不影响结果。
+#[derive(Config)]
 pub struct GenerateOptions {
     pub max_new_tokens: usize,
     pub context_size: usize,
     #[config(default = 0.0)]
     pub temperature: f32,
     pub topk: Option<usize>,
+    /// 核采样的累计概率阈值，参见 [`crate::sampling::top_p`]。
+    pub topp: Option<f32>,
+    /// 参见 [`crate::sampling::min_p`]。
+    pub minp: Option<f32>,
+    /// 重复惩罚系数，大于 1 时降低重复的概率，参见 [`crate::sampling::apply_repetition_penalty`]。
+    #[config(default = 1.0)]
+    pub repetition_penalty: f32,
+    /// 频率惩罚，只统计已生成的 token。
+    #[config(default = 0.0)]
+    pub frequency_penalty: f32,
+    /// 存在惩罚，只统计已生成的 token。
+    #[config(default = 0.0)]
+    pub presence_penalty: f32,
+    /// 加到对应 token 的 logit 上的偏置。
+    #[config(default = "Vec::new()")]
+    pub logit_bias: Vec<(u32, f32)>,
+    /// 禁止生成的 token。
+    #[config(default = "Vec::new()")]
+    pub banned_tokens: Vec<u32>,
     pub eos_id: Option<usize>,
+    /// 除 `eos_id` 外的其他 EOS token，生成任一 EOS 即停止。
+    #[config(default = "Vec::new()")]
+    pub eos_ids: Vec<u32>,
+    /// 停止字符串，可以跨越多个 token。只有 [`generate_text`] 支持，返回的文本不含匹配的停止字符串。
+    #[config(default = "Vec::new()")]
+    pub stop_sequences: Vec<String>,
+    /// 采样的随机数种子。设置时使用与后端无关的 [`SplitMix64`]，批量中第 i 行的结果只取决于种子、i 和 logits；
+    /// 否则使用后端的随机数（参见 [`Backend::seed`]）。
+    pub seed: Option<u64>,
+}
+
+impl GenerateOptions {
+    /// `eos_id` 和 `eos_ids` 的并集。
+    pub fn all_eos_ids(&self) -> Vec<u32> {
+        self.eos_id
+            .map(|v| v as u32)
+            .into_iter()
+            .chain(self.eos_ids.iter().copied())
+            .collect()
+    }
 }
 
 pub trait Tokenizer<B: Backend> {
@@ -79,51 +125,247 @@ pub fn cross_entropy<B: Backend, const D: usize, const D2: usize>(
 }
 
 /// TODO: 将 eos_id 的类型调整为 Option<u32>
+///
+/// 支持批量输入（各行长度相同）。生成的 EOS 不会追加到输出中。批量输入中较早结束的行，其后续位置以第一个 EOS
+/// （未设置时为 0）填充。长度不同的输入请使用 [`generate_batch`]。
 pub fn generate<B: Backend<IntElem = i64>>(
     model: &GptModel<B>,
-    mut idx: Tensor<B, 2, Int>,
-    opts: GenerateOptions,
-) -> Tensor<B, 2, Int> {
-    let GenerateOptions {
-        max_new_tokens,
-        context_size,
-        temperature,
-        topk,
-        eos_id,
-    } = opts;
-
-    let context_size = context_size as i32;
-
-    for _ in 0..max_new_tokens {
-        let idx_cond = idx.clone().slice(s![.., -context_size..]);
-
-        let logits = model.forward(idx_cond);
-        let mut logits = logits.slice(s![.., -1, ..]).squeeze(1);
-
-        if let Some(k) = topk {
-            let top_logits = logits.clone().squeeze::<1>(0).topk(k, 0);
-            let v = top_logits.min().into_scalar();
-            let discarded = logits.clone().lower(logits.clone().full_like(v));
-            logits = logits.mask_fill(discarded, f32::NEG_INFINITY);
-        }
-        // println!("probas.shape: {:?}", logits.shape());
+    idx: Tensor<B, 2, Int>,
+    opts: &GenerateOptions,
+) -> anyhow::Result<Tensor<B, 2, Int>> {
+    let device = idx.device();
+    let [batch_size, prompt_len] = idx.dims();
+
+    let prompts: Vec<Vec<u32>> = {
+        let ids: Vec<i64> = idx.to_data().into_vec().expect("read input ids");
+        ids.chunks(prompt_len)
+            .map(|v| v.iter().map(|&v| v as u32).collect())
+            .collect()
+    };
+
+    let out = generate_batch(model, &prompts, opts, &device)?;
+
+    let seq_len = out.iter().map(Vec::len).max().unwrap_or(prompt_len);
+    let pad_id = opts.all_eos_ids().first().copied().unwrap_or_default() as i64;
+    let ids: Vec<i64> = out
+        .iter()
+        .flat_map(|v| {
+            v.iter()
+                .map(|&v| v as i64)
+                .chain(std::iter::repeat_n(pad_id, seq_len - v.len()))
+        })
+        .collect();
 
-        let dim = logits.dims().len() - 1;
-        let idx_next = if temperature != 0.0 {
-            logits = logits / temperature;
-            let probas = activation::softmax(logits.clone(), dim);
-            crate::rand::multinomial(probas)
+    Ok(Tensor::<B, 1, Int>::from_ints(ids.as_slice(), &device).reshape([batch_size, seq_len]))
+}
+
+/// 批量生成长度不同的输入，对 logits 的处理和停止条件由 `opts` 决定，
+/// 参见 [`LogitsProcessors::from_options`] 和 [`StoppingCriteriaList::from_options`]。
+///
+/// 返回每行的输入和生成的 token，不含填充和 EOS。`opts` 中的 logit 偏置或禁用 token 不合法时返回错误。
+pub fn generate_batch<B: Backend<IntElem = i64>>(
+    model: &GptModel<B>,
+    prompts: &[Vec<u32>],
+    opts: &GenerateOptions,
+    device: &B::Device,
+) -> anyhow::Result<Vec<Vec<u32>>> {
+    let processors = LogitsProcessors::from_options(opts, model.out_head.weight.dims()[1], device)?;
+    let stopping = StoppingCriteriaList::from_options(opts);
+
+    Ok(generate_batch_with(
+        model,
+        prompts,
+        opts,
+        &processors,
+        &stopping,
+        device,
+    ))
+}
+
+/// 生成文本，返回每个输入之后生成的文本。除了 EOS，生成的文本包含 `opts.stop_sequences` 中的任一字符串时也会停止，
+/// 返回的文本截止到第一个停止字符串之前。
+pub fn generate_text<B: Backend<IntElem = i64>>(
+    model: &GptModel<B>,
+    tokenizer: &Encoding,
+    prompts: &[&str],
+    opts: &GenerateOptions,
+    device: &B::Device,
+) -> anyhow::Result<Vec<String>> {
+    let allowed_specials = HashSet::from(["<|endoftext|>"]);
+    let prompts: Vec<_> = prompts.iter().map(|v| tokenizer.encode(v, &allowed_specials)).collect();
+
+    let processors = LogitsProcessors::from_options(opts, model.out_head.weight.dims()[1], device)?;
+    let mut stopping = StoppingCriteriaList::from_options(opts);
+    if !opts.stop_sequences.is_empty() {
+        stopping.push(StopStrings::new(tokenizer, opts.stop_sequences.iter().cloned()));
+    }
+
+    let out = generate_batch_with(model, &prompts, opts, &processors, &stopping, device);
+
+    prompts
+        .iter()
+        .zip(out)
+        .enumerate()
+        .map(|(i, (p, ids))| {
+            let b = tokenizer
+                .decode(&ids[p.len()..])
+                .with_context(|| format!("decode {i}-th output"))?;
+            let text = String::from_utf8_lossy(&b);
+            Ok(stopping::trim_stop_sequences(&text, &opts.stop_sequences).to_owned())
+        })
+        .collect()
+}
+
+/// 和 [`generate_batch`] 相同，但使用自定义的 logits 处理器和停止条件。
+///
+/// 输入按最长的一行左填充后一起前向计算（参见 [`GptModel::forward_left_padded`]）。每一步先用 `processors`
+/// 处理 logits，温度为 0 时取 argmax，否则按概率采样；然后逐行检查 `stopping`，所有行都停止或者生成了
+/// `opts.max_new_tokens` 个 token 后结束。
+pub fn generate_batch_with<B: Backend<IntElem = i64>>(
+    model: &GptModel<B>,
+    prompts: &[Vec<u32>],
+    opts: &GenerateOptions,
+    processors: &dyn LogitsProcessor<B>,
+    stopping: &dyn StoppingCriteria,
+    device: &B::Device,
+) -> Vec<Vec<u32>> {
+    let start = Instant::now();
+
+    let batch_size = prompts.len();
+    let max_len = prompts.iter().map(Vec::len).max().unwrap_or_default();
+    if batch_size == 0 {
+        return vec![];
+    }
+    assert!(max_len > 0, "prompts must not be all empty");
+
+    let npads: Vec<usize> = prompts.iter().map(|v| max_len - v.len()).collect();
+    let prompt_lens: Vec<usize> = prompts.iter().map(Vec::len).collect();
+    let mut out = prompts.to_vec();
+
+    // 填充 token 不会被关注，取任意合法的 token 即可。
+    let ids: Vec<i64> = prompts
+        .iter()
+        .zip(&npads)
+        .flat_map(|(p, &n)| std::iter::repeat_n(0, n).chain(p.iter().map(|&v| v as i64)))
+        .collect();
+    let mut idx = Tensor::<B, 1, Int>::from_ints(ids.as_slice(), device).reshape([batch_size, max_len]);
+
+    let mut rngs = opts.seed.map(|seed| row_rngs(seed, batch_size));
+
+    let mut finished = vec![false; batch_size];
+    for _ in 0..opts.max_new_tokens {
+        let seq_len = idx.dims()[1];
+        // 截断到上下文长度时，被截掉的部分先是填充，窗口内剩余的填充个数按原始的填充个数计算。
+        let (idx_cond, npads_cond) = if seq_len > opts.context_size {
+            let offset = seq_len - opts.context_size;
+            let npads_cond = npads.iter().map(|v| v.saturating_sub(offset)).collect();
+            (idx.clone().slice(s![.., offset..]), npads_cond)
         } else {
-            logits.argmax(dim)
+            (idx.clone(), npads.clone())
+        };
+
+        let logits = model
+            .forward_left_padded(idx_cond, &npads_cond, ForwardOptions::new())
+            .logits;
+        let logits = logits.slice(s![.., -1, ..]).squeeze(1);
+
+        let state = GenerateState {
+            seqs: &out,
+            prompt_lens: &prompt_lens,
+            elapsed: start.elapsed(),
         };
+        let idx_next = sample(logits, processors, &state, opts.temperature == 0.0, rngs.as_deref_mut());
 
-        match eos_id {
-            Some(v) if idx_next.clone().squeeze::<1>(0).into_scalar() == v as i64 => break,
-            _ => {}
+        let ids = next_token_ids(&idx_next);
+        for (i, &v) in ids.iter().enumerate() {
+            if !finished[i] {
+                out[i].push(v as u32);
+            }
+        }
+
+        let state = GenerateState {
+            seqs: &out,
+            prompt_lens: &prompt_lens,
+            elapsed: start.elapsed(),
+        };
+        let stops: Vec<_> = (0..batch_size)
+            .map(|i| {
+                if finished[i] {
+                    None
+                } else {
+                    stopping.should_stop(&state, i)
+                }
+            })
+            .collect();
+        for (i, n) in stops.into_iter().enumerate() {
+            if let Some(n) = n {
+                let len = out[i].len() - n.min(out[i].len() - prompt_lens[i]);
+                out[i].truncate(len);
+                finished[i] = true;
+            }
+        }
+        if finished.iter().all(|&v| v) {
+            break;
         }
 
         idx = Tensor::cat(vec![idx, idx_next], 1);
     }
 
-    idx
+    out
+}
+
+/// 根据 `logits`（维度为 (batch-size, vocab-size)）逐行采样下一个 token，输出的维度为 (batch-size, 1)。
+/// `seqs[i]` 为第 i 行的输入和已生成的 token，其中前 `prompt_lens[i]` 个为输入。
+///
+/// 设置了 `opts.seed` 时，每次调用都从种子重新开始，相同的输入得到相同的结果。
+pub fn sample_next<B: Backend>(
+    logits: Tensor<B, 2>,
+    opts: &GenerateOptions,
+    seqs: &[Vec<u32>],
+    prompt_lens: &[usize],
+) -> anyhow::Result<Tensor<B, 2, Int>> {
+    let processors = LogitsProcessors::from_options(opts, logits.dims()[1], &logits.device())?;
+    let state = GenerateState {
+        seqs,
+        prompt_lens,
+        elapsed: Duration::ZERO,
+    };
+
+    let mut rngs = opts.seed.map(|seed| row_rngs(seed, seqs.len()));
+
+    Ok(sample(
+        logits,
+        &processors,
+        &state,
+        opts.temperature == 0.0,
+        rngs.as_deref_mut(),
+    ))
+}
+
+pub(crate) fn sample<B: Backend>(
+    logits: Tensor<B, 2>,
+    processors: &dyn LogitsProcessor<B>,
+    state: &GenerateState,
+    greedy: bool,
+    rngs: Option<&mut [SplitMix64]>,
+) -> Tensor<B, 2, Int> {
+    let logits = processors.process(logits, state);
+
+    if greedy {
+        return logits.argmax(1);
+    }
+
+    let probas = activation::softmax(logits, 1);
+    match rngs {
+        Some(rngs) => rand::multinomial_with(probas, rngs),
+        None => rand::multinomial(probas),
+    }
+}
+
+pub(crate) fn row_rngs(seed: u64, batch_size: usize) -> Vec<SplitMix64> {
+    (0..batch_size).map(|i| SplitMix64::for_row(seed, i)).collect()
+}
+
+fn next_token_ids<B: Backend<IntElem = i64>>(idx_next: &Tensor<B, 2, Int>) -> Vec<i64> {
+    idx_next.to_data().into_vec().expect("read next token ids")
 }
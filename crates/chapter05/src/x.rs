@@ -1,5 +1,8 @@
 use burn::prelude::Backend;
-use burn::tensor::{Int, Tensor};
+use burn::tensor::{Int, Tensor, s};
+
+/// [`TensorExt::cumsum`] 分块计算时的块大小。
+const CUMSUM_BLOCK_SIZE: usize = 256;
 
 pub trait TensorExt<B: Backend> {
     fn cumsum(self) -> Self;
@@ -8,74 +11,56 @@ pub trait TensorExt<B: Backend> {
 }
 
 impl<B: Backend> TensorExt<B> for Tensor<B, 2> {
+    /// 沿最后一维计算累计和。
     fn cumsum(self) -> Self {
-        // 实现方式 1
-        // let device = &self.device();
-        // let [_, d1] = self.dims();
-        // let v = Tensor::<B, 2>::ones([d1, d1], device).triu(0);
-        // self.matmul(v)
-
-        // 实现方式 2：解决大 d1 导致 #1 OOM 的问题
-        let data: Vec<_> = self.iter_dim(0).map(|v| v.squeeze::<1>(0).cumsum()).collect();
-        Tensor::stack::<2>(data, 0)
-    }
+        // 实现方式 1：乘以上三角的全 1 矩阵，d1 较大时 OOM
+        // 实现方式 2：逐行复制到主机上计算，vocab 较大、批量较大时很慢
+        // 实现方式 3：Hillis-Steele 前缀和，需要 log2(d1) 次拼接和加法，比实现方式 2 还慢
 
-    fn search_sorted(self, values: Tensor<B, 1>) -> Tensor<B, 1, Int> {
-        // 实现方式 1
+        // 实现方式 4：分块计算，数据保留在设备上。先用上三角矩阵求块内的累计和，再加上之前各块的和
+        let [d0, d1] = self.dims();
         let device = &self.device();
 
-        // let [d0, d1] = self.dims();
-        assert_eq!(self.dims()[0], values.dims()[0], "bad values len");
-
-        // let values = values.unsqueeze::<2>().transpose().expand([d0, d1]);
-
-        // let discarded = self.lower_equal(values);
-
-        // let indices = Tensor::<B, 1, Int>::arange(0..(d1 as i64), device).unsqueeze::<2>();
-
-        // let indices = indices.expand([d0, d1]);
-
-        // let indices = indices.mask_fill(discarded, d1 as i64);
+        let bs = CUMSUM_BLOCK_SIZE.min(d1);
+        let nb = d1.div_ceil(bs);
+        let x = match nb * bs - d1 {
+            0 => self,
+            pad => Tensor::cat(vec![self, Tensor::zeros([d0, pad], device)], 1),
+        };
+
+        let within = x
+            .reshape([d0, nb, bs])
+            .matmul(Tensor::<B, 2>::ones([bs, bs], device).triu(0).unsqueeze::<3>());
+        let totals = within.clone().slice(s![.., .., bs - 1..]).reshape([d0, nb]);
+        let offsets = totals.matmul(Tensor::ones([nb, nb], device).triu(1));
+
+        (within + offsets.unsqueeze_dim(2))
+            .reshape([d0, nb * bs])
+            .slice(s![.., ..d1])
+    }
 
-        // let out = indices.min_dim(1).transpose().squeeze::<1>(0);
+    /// 每行 `self[i]` 升序排列，返回 `values[i]` 在该行的插入位置，类似 `torch.searchsorted(..,right=True,..)`。
+    fn search_sorted(self, values: Tensor<B, 1>) -> Tensor<B, 1, Int> {
+        // 实现方式 1：以 mask_fill 和 min_dim 求第一个大于 values[i] 的下标
+        // 实现方式 2：逐行复制到主机上二分查找，vocab 较大、批量较大时很慢
 
-        // 实现方式 2：解决大 [d0,d1] 导致方式 1 OOM 的问题
-        let tensors: Vec<_> = self
-            .iter_dim(0)
-            .map(|v| v.squeeze::<1>(0))
-            .zip(values.iter_dim(0))
-            .map(|(v, t)| v.search_sorted(t).into_scalar())
-            .collect();
-        let out = Tensor::from_ints(tensors.as_slice(), device);
+        // 实现方式 3：插入位置即不大于 values[i] 的元素个数，数据保留在设备上
+        let [d0, d1] = self.dims();
+        assert_eq!(d0, values.dims()[0], "bad values len");
 
-        out
+        let values = values.unsqueeze_dim::<2>(1).expand([d0, d1]);
+        self.lower_equal(values).int().sum_dim(1).squeeze::<1>(1)
     }
 }
 
 impl<B: Backend> TensorExt<B> for Tensor<B, 1> {
     fn cumsum(self) -> Self {
-        let device = &self.device();
-
-        // println!("dtype {:?}", self.dtype());
-        let mut data = self.to_data().to_vec::<f32>().expect("unwrap as Vec<f32>");
-        let mut s = 0.0f32;
-        for v in data.iter_mut() {
-            let vv = *v;
-            *v += s;
-            s += vv;
-        }
-
-        Self::from_floats(data.as_slice(), device)
+        self.unsqueeze::<2>().cumsum().squeeze::<1>(0)
     }
 
     fn search_sorted(self, values: Tensor<B, 1>) -> Tensor<B, 1, Int> {
-        let device = &self.device();
-
-        let data = self.to_data().to_vec::<f32>().expect("unwrap self as Vec<f32>");
-        let v = values.to_data().to_vec::<f32>().expect("unwrap value as f32")[0];
-        let i = search_sorted(&data, v);
-
-        Tensor::from_ints([i], device)
+        assert_eq!(1, values.dims()[0], "bad values len");
+        self.unsqueeze::<2>().search_sorted(values)
     }
 }
 
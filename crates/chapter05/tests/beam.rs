@@ -0,0 +1,118 @@
+use burn::backend::NdArray;
+use burn::prelude::*;
+use burn::tensor::activation;
+use chapter04::GptModel;
+use chapter05::beam::{self, BeamSearchOptions};
+use chapter05::utils::{self, GenerateOptions};
+
+mod common;
+
+type B = NdArray<f32>;
+
+const PROMPT: [u32; 4] = [6109, 3626, 6100, 345];
+
+/// 逐个 token 重新计算 `tokens` 的累计对数概率。
+fn logprob(model: &GptModel<B>, tokens: &[u32], device: &<B as Backend>::Device) -> f32 {
+    let ids: Vec<i64> = PROMPT.iter().chain(tokens).map(|&v| v as i64).collect();
+    let idx = Tensor::<B, 1, Int>::from_ints(ids.as_slice(), device).unsqueeze::<2>();
+
+    let logprobs = activation::log_softmax(model.forward(idx), 2);
+    let logprobs: Vec<f32> = logprobs.into_data().to_vec().expect("read logprobs");
+    let vocab_size = logprobs.len() / ids.len();
+
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| logprobs[(PROMPT.len() - 1 + i) * vocab_size + v as usize])
+        .sum()
+}
+
+#[test]
+fn beam_search_width_one_is_greedy() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    let idx = Tensor::<B, 1, Int>::from_ints(PROMPT, device).unsqueeze::<2>();
+    let expect: Vec<u32> = utils::generate(&model, idx, &GenerateOptions::new(5, 64))
+        .expect("generate")
+        .into_data()
+        .iter::<i64>()
+        .skip(PROMPT.len())
+        .map(|v| v as u32)
+        .collect();
+
+    let opts = BeamSearchOptions::new(5, 64).with_num_beams(1);
+    let got = beam::beam_search(&model, &PROMPT, &opts, device);
+
+    assert_eq!(1, got.len());
+    assert_eq!(expect, got[0].tokens);
+}
+
+#[test]
+fn beam_search_n_best() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    let opts = BeamSearchOptions::new(4, 64)
+        .with_num_beams(4)
+        .with_num_return_sequences(3)
+        .with_length_penalty(0.0);
+    let got = beam::beam_search(&model, &PROMPT, &opts, device);
+
+    assert_eq!(3, got.len());
+    for (i, h) in got.iter().enumerate() {
+        assert_eq!(4, h.tokens.len());
+        assert!(!h.finished);
+        // 长度惩罚为 0 时，得分即累计对数概率
+        assert_eq!(h.logprob, h.score);
+
+        let expect = logprob(&model, &h.tokens, device);
+        assert!(
+            (expect - h.logprob).abs() < 1e-3,
+            "#{i}: expect {expect}, got {}",
+            h.logprob
+        );
+
+        if i > 0 {
+            assert!(got[i - 1].score >= h.score, "hypotheses should be sorted by score");
+            assert_ne!(got[i - 1].tokens, h.tokens);
+        }
+    }
+}
+
+#[test]
+fn beam_search_early_stopping() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    let opts = BeamSearchOptions::new(5, 64).with_num_beams(1);
+    let first = beam::beam_search(&model, &PROMPT, &opts, device)[0].tokens[0];
+
+    // 以贪心解码的第一个 token 作为 EOS，第一步即得到一个结束的序列
+    let opts = opts.with_eos_id(Some(first)).with_early_stopping(true);
+    let got = beam::beam_search(&model, &PROMPT, &opts, device);
+
+    assert_eq!(1, got.len());
+    assert!(got[0].finished);
+    assert!(got[0].tokens.is_empty());
+    let expect = logprob(&model, &[first], device);
+    assert!((expect - got[0].logprob).abs() < 1e-3);
+}
+
+#[test]
+fn beam_search_length_penalty_bound() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    let opts = BeamSearchOptions::new(5, 64).with_num_beams(1);
+    let first = beam::beam_search(&model, &PROMPT, &opts, device)[0].tokens[0];
+
+    // 第一步即得到一个结束的序列。长度惩罚较大时更长的序列得分更高，不能因为当前长度下的上界更低就停止
+    let opts = opts.with_eos_id(Some(first)).with_length_penalty(2.0);
+    let got = beam::beam_search(&model, &PROMPT, &opts, device);
+
+    assert_eq!(1, got.len());
+    assert!(!got[0].tokens.is_empty());
+    let empty = logprob(&model, &[first], device);
+    assert!(got[0].score > empty, "expect score > {empty}, got {}", got[0].score);
+}
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use burn::backend::NdArray;
+use burn::module::Module;
+use burn::prelude::*;
+use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
+use chapter04::Preset;
+use chapter05::checkpoint::{self, CheckpointMeta, Head, TokenizerInfo, TrainingSummary};
+
+mod common;
+
+type B = NdArray<f32>;
+
+fn new_dir(name: &str) -> PathBuf {
+    common::temp_path(&format!("checkpoint-{name}"))
+}
+
+#[test]
+fn checkpoint_round_trip() {
+    let device = &<B as Backend>::Device::default();
+    let config = Preset::Gpt2Tiny
+        .config()
+        .with_vocab_size(100)
+        .with_qkv_bias(false)
+        .with_drop_rate(0.0);
+
+    let training = TrainingSummary {
+        epochs: 5,
+        steps: 42,
+        examples_seen: Some(336),
+        val_accuracy: Some(0.975),
+        ..Default::default()
+    };
+    let tokenizer = TokenizerInfo {
+        pad_id: Some(0),
+        ..TokenizerInfo::gpt2()
+    };
+
+    for (name, head) in [
+        ("lm", Head::LanguageModel),
+        ("classifier", Head::Classifier { num_classes: 3 }),
+    ] {
+        let meta = CheckpointMeta::new(config)
+            .with_head(head)
+            .with_tokenizer(tokenizer.clone())
+            .with_training(Some(training.clone()));
+
+        B::seed(5);
+        let model = meta.init::<B>(device);
+        let dir = new_dir(name);
+        checkpoint::save_checkpoint(&model, &meta, &dir).expect("save");
+        assert!(checkpoint::is_checkpoint(&dir));
+
+        let (loaded, got) = checkpoint::load_checkpoint::<B>(&dir, device).expect("load");
+        assert_eq!(head, got.head);
+        assert_eq!(tokenizer, got.tokenizer);
+        assert_eq!(Some(&training), got.training.as_ref());
+        assert_eq!(
+            (config.vocab_size, config.qkv_bias, config.drop_rate),
+            (got.config.vocab_size, got.config.qkv_bias, got.config.drop_rate)
+        );
+        assert!(
+            common::param_bits(&model) == common::param_bits(&loaded),
+            "{name}: weights differ"
+        );
+    }
+}
+
+#[test]
+fn checkpoint_head_mismatch() {
+    let device = &<B as Backend>::Device::default();
+    let config = Preset::Gpt2Tiny.config().with_vocab_size(100);
+
+    let model = CheckpointMeta::new(config).init::<B>(device);
+    let meta = CheckpointMeta::new(config).with_head(Head::Classifier { num_classes: 2 });
+
+    let err = checkpoint::save_checkpoint(&model, &meta, &new_dir("mismatch")).expect_err("should fail");
+    let msg = format!("{err:#}");
+    assert!(msg.contains("100 outputs"), "{msg}");
+}
+
+#[test]
+fn checkpoint_legacy_mpk() {
+    let device = &<B as Backend>::Device::default();
+    let config = Preset::Gpt2Tiny.config().with_vocab_size(100);
+    let meta = CheckpointMeta::new(config).with_head(Head::Classifier { num_classes: 2 });
+
+    // 旧版本只用 save_file 保存了 <dir>.mpk
+    B::seed(9);
+    let model = meta.init::<B>(device);
+    let dir = new_dir("legacy");
+    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+    model.clone().save_file(&dir, &recorder).expect("save legacy");
+    assert!(!checkpoint::is_checkpoint(&dir));
+
+    let (loaded, got) = checkpoint::load_checkpoint_or_mpk::<B>(&dir, || meta.clone(), device).expect("load legacy");
+    assert_eq!(meta.head, got.head);
+    assert!(
+        common::param_bits(&model) == common::param_bits(&loaded),
+        "weights differ"
+    );
+
+    // 检查点目录优先于旧文件
+    let dir = new_dir("legacy-preferred");
+    checkpoint::save_checkpoint(&model, &meta, &dir).expect("save");
+    model.save_file(&dir, &recorder).expect("save legacy");
+    let legacy = || panic!("should load the checkpoint directory");
+    checkpoint::load_checkpoint_or_mpk::<B>(&dir, legacy, device).expect("load");
+}
@@ -0,0 +1,79 @@
+//! 集成测试共用的合成参数、临时目录和比较参数的辅助函数，每个测试只用到其中一部分。
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use burn::module::{Module, ModuleVisitor, ParamId};
+use burn::prelude::*;
+use chapter04::{Config, GptModel, Preset};
+use chapter05::rand::SplitMix64;
+
+/// 合成参数的规模，结构和 gpt2-tiny 相同。
+pub const VOCAB_SIZE: usize = 100;
+pub const CONTEXT_LENGTH: usize = 16;
+pub const EMB_DIM: usize = 64;
+pub const NLAYERS: usize = 2;
+
+/// 参数名到形状和数据的映射。
+pub type Tensors = BTreeMap<String, (Vec<usize>, Vec<f32>)>;
+
+/// 和合成参数规模相同的 gpt2-tiny 配置。
+pub fn tiny_config() -> Config {
+    Preset::Gpt2Tiny
+        .config()
+        .with_vocab_size(VOCAB_SIZE)
+        .with_context_length(CONTEXT_LENGTH)
+}
+
+/// 从 `rng` 取 `n` 个 [-0.1, 0.1) 内的随机数。
+pub fn random_vec(rng: &mut SplitMix64, n: usize) -> Vec<f32> {
+    (0..n).map(|_| (rng.next_f64() as f32 - 0.5) * 0.2).collect()
+}
+
+/// 按 `shapes` 的顺序生成随机参数，相同的 `seed` 得到相同的结果。
+pub fn random_tensors(seed: u64, shapes: impl IntoIterator<Item = (String, Vec<usize>)>) -> Tensors {
+    let mut rng = SplitMix64::new(seed);
+    shapes
+        .into_iter()
+        .map(|(name, shape)| {
+            let data = random_vec(&mut rng, shape.iter().product());
+            (name, (shape, data))
+        })
+        .collect()
+}
+
+/// 临时目录下名为 `chapter05-{name}` 的路径，已有的同名文件或目录会被删除。
+pub fn temp_path(name: &str) -> PathBuf {
+    let p = std::env::temp_dir().join(format!("chapter05-{name}"));
+    if p.is_dir() {
+        let _ = fs::remove_dir_all(&p);
+    } else {
+        let _ = fs::remove_file(&p);
+    }
+    p
+}
+
+/// 以 `seed` 初始化的 gpt2-tiny 模型（不使用 dropout），相同的 `seed` 得到相同的参数。
+pub fn new_model<B: Backend>(seed: u64, device: &B::Device) -> GptModel<B> {
+    B::seed(seed);
+    Preset::Gpt2Tiny.config().with_drop_rate(0.0).init::<B>(device)
+}
+
+/// 按访问顺序返回模型所有参数的形状和二进制表示。
+pub fn param_bits<B: Backend>(model: &GptModel<B>) -> Vec<(Vec<usize>, Vec<u32>)> {
+    struct Visitor(Vec<(Vec<usize>, Vec<u32>)>);
+
+    impl<B: Backend> ModuleVisitor<B> for Visitor {
+        fn visit_float<const D: usize>(&mut self, _id: ParamId, tensor: &Tensor<B, D>) {
+            let data: Vec<f32> = tensor.to_data().to_vec().expect("read param");
+            self.0
+                .push((tensor.dims().to_vec(), data.iter().map(|v| v.to_bits()).collect()));
+        }
+    }
+
+    let mut v = Visitor(vec![]);
+    model.visit(&mut v);
+    v.0
+}
@@ -0,0 +1,128 @@
+use burn::backend::NdArray;
+use burn::prelude::*;
+use chapter04::GptModel;
+use chapter05::constrained::{self, Constraint};
+use chapter05::stopping::StoppingCriteriaList;
+use chapter05::utils::{self, GenerateOptions};
+use serde_json::{Value, json};
+use tiktoken::ext::Encoding;
+
+mod common;
+
+type B = NdArray<f32>;
+
+const EOS_ID: u32 = 50256;
+const PROMPTS: [&[u32]; 3] = [&[6109, 3626, 6100, 345], &[15496], &[40, 1842, 257]];
+
+/// 生成并返回各行生成的 token 以及是否以 EOS 结束。
+fn generate(model: &GptModel<B>, constraint: Constraint, opts: &GenerateOptions) -> Vec<(Vec<u32>, bool)> {
+    let device = &<B as Backend>::Device::default();
+
+    let prompts: Vec<_> = PROMPTS.iter().map(|v| v.to_vec()).collect();
+    let processors = constraint.processors(opts, device).expect("processors");
+    let stopping = StoppingCriteriaList::from_options(opts);
+    let out = utils::generate_batch_with(model, &prompts, opts, &processors, &stopping, device);
+
+    out.into_iter()
+        .zip(PROMPTS)
+        .map(|(v, p)| {
+            let generated = v[p.len()..].to_vec();
+            let finished = generated.len() < opts.max_new_tokens;
+            (generated, finished)
+        })
+        .collect()
+}
+
+fn decode(tokenizer: &Encoding, ids: &[u32]) -> String {
+    String::from_utf8(tokenizer.decode(ids).expect("decode")).expect("utf-8")
+}
+
+#[test]
+fn json_schema_to_regex() {
+    let tokenizer = Encoding::gpt2();
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "name": {"type": "string", "maxLength": 4},
+            "tags": {"type": "array", "items": {"enum": ["a", 1]}, "minItems": 1, "maxItems": 2},
+            "score": {"type": "number"},
+            "ok": {"type": "boolean"},
+            "none": {"type": "null"},
+        },
+    });
+    let c = Constraint::json_schema(&schema, &tokenizer, 256, EOS_ID).expect("compile schema");
+
+    for (text, expect) in [
+        (
+            r#"{"name":"ab\"c","none":null,"ok":true,"score":-1.5e3,"tags":["a", 1]}"#,
+            true,
+        ),
+        (
+            r#"{"name": "", "none": null, "ok": false, "score": 0, "tags": [1]}"#,
+            true,
+        ),
+        // 属性顺序、字符串长度、数组长度和数字格式不符
+        (r#"{"name":"","ok":true,"none":null,"score":0,"tags":[1]}"#, false),
+        (r#"{"name":"abcde","none":null,"ok":true,"score":0,"tags":[1]}"#, false),
+        (r#"{"name":"","none":null,"ok":true,"score":0,"tags":[]}"#, false),
+        (r#"{"name":"","none":null,"ok":true,"score":01,"tags":[1]}"#, false),
+    ] {
+        assert_eq!(expect, c.is_match(text.as_bytes()), "{text}");
+    }
+
+    assert!(constrained::json_schema_to_regex(&json!({"type": "tuple"})).is_err());
+}
+
+#[test]
+fn regex_labels() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+    let vocab_size = model.out_head.weight.dims()[1];
+    let tokenizer = Encoding::gpt2();
+
+    const LABELS: [&str; 3] = ["positive", "negative", "neutral"];
+    let pattern = LABELS.join("|");
+
+    let greedy = GenerateOptions::new(16, 64).with_eos_id(Some(EOS_ID as usize));
+    for opts in [greedy.clone(), greedy.with_temperature(1.0).with_seed(Some(7))] {
+        let c = Constraint::regex(&pattern, &tokenizer, vocab_size, EOS_ID).expect("compile regex");
+        for (ids, finished) in generate(&model, c, &opts) {
+            assert!(finished, "EOS should be generated");
+            let text = decode(&tokenizer, &ids);
+            assert!(LABELS.contains(&text.as_str()), "bad label {text:?}");
+        }
+    }
+}
+
+#[test]
+fn json_schema_outputs() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+    let vocab_size = model.out_head.weight.dims()[1];
+    let tokenizer = Encoding::gpt2();
+
+    // 取值有限的 schema，输出一定能在长度上限内结束
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "label": {"enum": ["positive", "negative"]},
+            "flags": {"type": "array", "items": {"type": "boolean"}, "maxItems": 3},
+            "note": {"type": "string", "maxLength": 5},
+        },
+    });
+
+    let opts = GenerateOptions::new(96, 64)
+        .with_eos_id(Some(EOS_ID as usize))
+        .with_temperature(1.0)
+        .with_seed(Some(42));
+    let c = Constraint::json_schema(&schema, &tokenizer, vocab_size, EOS_ID).expect("compile schema");
+    for (ids, finished) in generate(&model, c, &opts) {
+        assert!(finished, "EOS should be generated");
+
+        let text = decode(&tokenizer, &ids);
+        let v: Value = serde_json::from_str(&text).unwrap_or_else(|err| panic!("bad json {text:?}: {err}"));
+        assert!(["positive", "negative"].contains(&v["label"].as_str().expect("label")));
+        assert!(v["flags"].as_array().expect("flags").iter().all(Value::is_boolean));
+        assert!(v["note"].as_str().expect("note").chars().count() <= 5);
+    }
+}
@@ -0,0 +1,94 @@
+use burn::backend::NdArray;
+use burn::prelude::*;
+use burn::tensor::activation;
+use chapter04::{ForwardOptions, GptModel};
+use chapter05::contrastive::{self, ContrastiveSearchOptions};
+use chapter05::utils::{self, GenerateOptions};
+
+mod common;
+
+type B = NdArray<f32>;
+
+const PROMPT: [u32; 4] = [6109, 3626, 6100, 345];
+
+fn greedy(model: &GptModel<B>, max_new_tokens: usize, device: &<B as Backend>::Device) -> Vec<u32> {
+    let idx = Tensor::<B, 1, Int>::from_ints(PROMPT, device).unsqueeze::<2>();
+    utils::generate(model, idx, &GenerateOptions::new(max_new_tokens, 64))
+        .expect("generate")
+        .into_data()
+        .iter::<i64>()
+        .skip(PROMPT.len())
+        .map(|v| v as u32)
+        .collect()
+}
+
+#[test]
+fn contrastive_search_without_penalty_is_greedy() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+    let expect = greedy(&model, 6, device);
+
+    let opts = ContrastiveSearchOptions::new(6, 64).with_alpha(0.0);
+    assert_eq!(expect, contrastive::contrastive_search(&model, &PROMPT, &opts, device));
+
+    let opts = ContrastiveSearchOptions::new(6, 64).with_top_k(1);
+    assert_eq!(expect, contrastive::contrastive_search(&model, &PROMPT, &opts, device));
+}
+
+/// 逐个候选重新计算第一步的得分，和对比搜索选择的 token 比较。
+#[test]
+fn contrastive_search_first_step() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+    let (top_k, alpha) = (5, 0.6);
+
+    let idx = Tensor::<B, 1, Int>::from_ints(PROMPT, device).unsqueeze::<2>();
+    let logits = model.forward(idx).slice(s![.., -1, ..]).flatten::<1>(0, 2);
+    let probas: Vec<f32> = activation::softmax(logits, 0).into_data().to_vec().unwrap();
+    let mut ranked: Vec<usize> = (0..probas.len()).collect();
+    ranked.sort_by(|&a, &b| probas[b].total_cmp(&probas[a]));
+
+    let score = |c: usize| {
+        let ids: Vec<i64> = PROMPT.iter().map(|&v| v as i64).chain([c as i64]).collect();
+        let idx = Tensor::<B, 1, Int>::from_ints(ids.as_slice(), device).unsqueeze::<2>();
+        let hidden = model
+            .forward_with_outputs(idx, ForwardOptions::new().with_final_hidden(true))
+            .final_hidden
+            .unwrap();
+        let hidden: Vec<f32> = hidden.into_data().to_vec().unwrap();
+        let rows: Vec<&[f32]> = hidden.chunks(hidden.len() / ids.len()).collect();
+
+        let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let cand = rows[PROMPT.len()];
+        let max_sim = rows[..PROMPT.len()]
+            .iter()
+            .map(|r| r.iter().zip(cand).map(|(a, b)| a * b).sum::<f32>() / (norm(r) * norm(cand)))
+            .fold(f32::NEG_INFINITY, f32::max);
+        (1.0 - alpha) * probas[c] - alpha * max_sim
+    };
+    let expect = ranked[..top_k]
+        .iter()
+        .copied()
+        .max_by(|&a, &b| score(a).total_cmp(&score(b)))
+        .unwrap();
+
+    let opts = ContrastiveSearchOptions::new(1, 64).with_top_k(top_k).with_alpha(alpha);
+    let got = contrastive::contrastive_search(&model, &PROMPT, &opts, device);
+    assert_eq!(vec![expect as u32], got);
+}
+
+#[test]
+fn contrastive_search_eos() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    let opts = ContrastiveSearchOptions::new(6, 64);
+    let tokens = contrastive::contrastive_search(&model, &PROMPT, &opts, device);
+    assert_eq!(6, tokens.len());
+
+    // 以第三个 token 作为 EOS，在它第一次出现时停止
+    let eos = tokens[2];
+    let i = tokens.iter().position(|&v| v == eos).unwrap();
+    let got = contrastive::contrastive_search(&model, &PROMPT, &opts.with_eos_id(Some(eos)), device);
+    assert_eq!(tokens[..i], got);
+}
@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use burn::backend::NdArray;
+use burn::prelude::*;
+use chapter04::GptModel;
+use chapter05::sampling::{AllowedTokens, LogitsProcessors};
+use chapter05::stopping::{MaxNewTokens, StoppingCriteriaList};
+use chapter05::utils::{self, GenerateOptions};
+use tiktoken::ext::Encoding;
+
+mod common;
+
+type B = NdArray<f32>;
+
+const PROMPTS: [&[u32]; 3] = [&[6109, 3626, 6100, 345], &[15496], &[40, 1842, 257]];
+
+fn generate_one(model: &GptModel<B>, prompt: &[u32], opts: &GenerateOptions) -> Vec<u32> {
+    let device = &<B as Backend>::Device::default();
+
+    let ids: Vec<i64> = prompt.iter().map(|&v| v as i64).collect();
+    let idx = Tensor::<B, 1, Int>::from_ints(ids.as_slice(), device).unsqueeze::<2>();
+
+    utils::generate(model, idx, opts)
+        .expect("generate")
+        .into_data()
+        .iter::<i64>()
+        .map(|v| v as u32)
+        .collect()
+}
+
+#[test]
+fn generate_batch_matches_single() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    // 上下文长度小于输入和输出的总长度，以覆盖滑动窗口截断填充的情况。
+    let opts = GenerateOptions::new(6, 8);
+
+    let prompts: Vec<_> = PROMPTS.iter().map(|v| v.to_vec()).collect();
+    let got = utils::generate_batch(&model, &prompts, &opts, device).expect("generate");
+
+    assert_eq!(prompts.len(), got.len());
+    for (p, g) in PROMPTS.iter().zip(got) {
+        assert_eq!(generate_one(&model, p, &opts), g, "prompt {p:?}");
+    }
+}
+
+#[test]
+fn generate_batch_slides_past_context() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    // 生成的 token 远超上下文长度，窗口多次滑动；最长的输入本身就超过了上下文长度。
+    let opts = GenerateOptions::new(12, 8);
+
+    let prompts = vec![vec![15496], vec![40, 1842, 257], (0..10).map(|v| 100 + v).collect()];
+    let got = utils::generate_batch(&model, &prompts, &opts, device).expect("generate");
+
+    for (p, g) in prompts.iter().zip(got) {
+        assert_eq!(generate_one(&model, p, &opts), g, "prompt {p:?}");
+    }
+}
+
+#[test]
+fn generate_batch_sampled() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    // 只保留概率最大的 token 时，按概率采样的结果和取 argmax 相同
+    let greedy = GenerateOptions::new(6, 8);
+    let opts = greedy.clone().with_temperature(1.0).with_topk(Some(1));
+
+    let prompts: Vec<_> = PROMPTS.iter().map(|v| v.to_vec()).collect();
+    let got = utils::generate_batch(&model, &prompts, &opts, device).expect("generate");
+
+    assert_eq!(prompts.len(), got.len());
+    for (p, g) in PROMPTS.iter().zip(got) {
+        assert_eq!(generate_one(&model, p, &greedy), g, "prompt {p:?}");
+    }
+}
+
+#[test]
+fn generate_batch_stops_per_row() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    let opts = GenerateOptions::new(6, 64);
+
+    // 以第 0 行生成的第 3 个 token 作为 EOS
+    let first = generate_one(&model, PROMPTS[0], &opts);
+    let eos_id = first[PROMPTS[0].len() + 2];
+    let opts = opts.with_eos_id(Some(eos_id as usize));
+
+    let prompts: Vec<_> = PROMPTS.iter().map(|v| v.to_vec()).collect();
+    let got = utils::generate_batch(&model, &prompts, &opts, device).expect("generate");
+
+    for (p, g) in PROMPTS.iter().zip(got) {
+        let expect = generate_one(&model, p, &opts);
+        assert_eq!(expect, g, "prompt {p:?}");
+        assert!(!g[p.len()..].contains(&eos_id), "EOS should be stripped");
+    }
+}
+
+#[test]
+fn generate_batch_with_processors() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    // 通过生成做分类：输出只能是标签对应的 token
+    const LABELS: [u32; 2] = [3763, 645];
+    let opts = GenerateOptions::new(3, 64).with_temperature(1.0);
+    let processors = LogitsProcessors::from_options(&opts, model.out_head.weight.dims()[1], device)
+        .expect("processors")
+        .with(AllowedTokens(LABELS.to_vec()));
+    let stopping = StoppingCriteriaList::new().with(MaxNewTokens(2));
+
+    let prompts: Vec<_> = PROMPTS.iter().map(|v| v.to_vec()).collect();
+    let got = utils::generate_batch_with(&model, &prompts, &opts, &processors, &stopping, device);
+
+    for (p, g) in PROMPTS.iter().zip(got) {
+        assert_eq!(p.len() + 2, g.len());
+        assert!(g[p.len()..].iter().all(|v| LABELS.contains(v)), "bad output {g:?}");
+    }
+}
+
+#[test]
+fn generate_multiple_eos_ids() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    let opts = GenerateOptions::new(6, 64);
+    let greedy = generate_one(&model, PROMPTS[0], &opts);
+    let generated = &greedy[PROMPTS[0].len()..];
+
+    // 在第 2 个或第 4 个生成的 token 处停止，以先出现者为准
+    let eos_ids = vec![generated[3], generated[1]];
+    let end = generated.iter().position(|v| eos_ids.contains(v)).expect("find eos");
+    let opts = opts.with_eos_ids(eos_ids);
+
+    let got = utils::generate_batch(&model, &[PROMPTS[0].to_vec()], &opts, device).expect("generate");
+    assert_eq!(&greedy[..PROMPTS[0].len() + end], got[0].as_slice());
+}
+
+#[test]
+fn generate_text_stop_sequences() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+    let tokenizer = Encoding::gpt2();
+
+    const PROMPT: &str = "Every effort moves you";
+
+    let opts = GenerateOptions::new(8, 64);
+    let text = utils::generate_text(&model, &tokenizer, &[PROMPT], &opts, device).expect("generate text")[0].clone();
+
+    // 以生成的第 3、4 个 token 对应的文本作为停止字符串，它可能跨越多个 token
+    let ids = tokenizer.encode(PROMPT, &HashSet::new());
+    let greedy = generate_one(&model, &ids, &opts);
+    let stop =
+        String::from_utf8_lossy(&tokenizer.decode(&greedy[ids.len() + 2..ids.len() + 4]).expect("decode")).into_owned();
+    let opts = opts.with_stop_sequences(vec!["never matched".to_owned(), stop.clone()]);
+
+    let got = utils::generate_text(&model, &tokenizer, &[PROMPT], &opts, device).expect("generate text");
+    assert_eq!(&text[..text.find(&stop).expect("find stop")], got[0]);
+}
+
+#[test]
+fn generate_seeded() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+
+    let opts = GenerateOptions::new(6, 64).with_temperature(1.0).with_seed(Some(42));
+    let prompts: Vec<_> = PROMPTS.iter().map(|v| v.to_vec()).collect();
+
+    // 相同的种子得到相同的结果，不受后端随机数的影响
+    B::seed(1);
+    let expect = utils::generate_batch(&model, &prompts, &opts, device).expect("generate");
+    B::seed(2);
+    assert_eq!(
+        expect,
+        utils::generate_batch(&model, &prompts, &opts, device).expect("generate")
+    );
+
+    // 每行的结果只取决于种子和行号
+    let got = utils::generate_batch(&model, &prompts[..1], &opts, device).expect("generate");
+    assert_eq!(expect[0], got[0]);
+
+    let opts = opts.with_seed(Some(43));
+    assert_ne!(
+        expect,
+        utils::generate_batch(&model, &prompts, &opts, device).expect("generate")
+    );
+}
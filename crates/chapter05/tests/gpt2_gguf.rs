@@ -0,0 +1,223 @@
+use std::fs;
+use std::path::PathBuf;
+
+use burn::backend::NdArray;
+use burn::module::Param;
+use burn::nn::LinearConfig;
+use burn::prelude::*;
+use chapter05::gpt2::gguf::{self, GgmlType, Gguf, Value, Vocab};
+
+use common::{EMB_DIM, VOCAB_SIZE};
+
+mod common;
+
+type B = NdArray<f32>;
+
+const EOS_ID: u32 = VOCAB_SIZE as u32 - 1;
+
+/// 覆盖各种字节的词表，最后一个 token 为 EOS，其中 " the" 可以由前面的 token 逐步合并得到。
+fn vocab() -> Vocab {
+    let mut tokens: Vec<Vec<u8>> = [&[0][..], b" ", b"t", b"h", b"e", b" t", b"he", b" the"]
+        .into_iter()
+        .map(|t| t.to_vec())
+        .collect();
+    tokens.push("你好".as_bytes().to_vec());
+    tokens.extend((tokens.len()..VOCAB_SIZE - 1).map(|i| vec![(i * 37 % 256) as u8, 0xff]));
+    tokens.push(b"<|endoftext|>".to_vec());
+
+    Vocab::new(tokens, Some(EOS_ID))
+}
+
+fn path(name: &str) -> PathBuf {
+    common::temp_path(&format!("gpt2-{name}.gguf"))
+}
+
+#[test]
+fn gguf_round_trip() {
+    let device = &<B as Backend>::Device::default();
+    let c = common::tiny_config();
+
+    B::seed(7);
+    let untied = c.init::<B>(device);
+
+    let mut tied = c.init::<B>(device);
+    tied.out_head.weight = Param::from_tensor(tied.tok_emb.weight.val().transpose());
+
+    let new_classifier = || {
+        let mut m = c.init::<B>(device);
+        m.out_head = LinearConfig::new(EMB_DIM, 2).with_bias(true).init(device);
+        m
+    };
+    let classifier = new_classifier();
+
+    for (name, model, tied) in [
+        ("untied", untied, false),
+        ("tied", tied, true),
+        ("classifier", classifier, false),
+    ] {
+        let p = path(&format!("round-trip-{name}"));
+        gguf::save(&model, &c, Some(&vocab()), GgmlType::F32, &p).expect("save");
+
+        let f = Gguf::open(&p).expect("open");
+        assert_eq!(Some(&Value::U32(2)), f.metadata().get("gpt2.block_count"));
+        assert_eq!(!tied, f.tensors().contains_key("output.weight"), "{name}");
+        let qkv = &f.tensors()["blk.1.attn_qkv.weight"];
+        assert_eq!(
+            (vec![EMB_DIM, 3 * EMB_DIM], GgmlType::F32),
+            (qkv.dims.clone(), qkv.ggml_type)
+        );
+
+        let loaded = if name == "classifier" {
+            let mut m = new_classifier();
+            gguf::load_weights(&f, &mut m).expect("load classifier");
+            m
+        } else {
+            let (cc, m, v) = gguf::load::<B>(&p, device).expect("load");
+            assert_eq!(
+                (c.vocab_size, c.context_length, c.emb_dim, c.nheads, c.nlayers),
+                (cc.vocab_size, cc.context_length, cc.emb_dim, cc.nheads, cc.nlayers)
+            );
+            assert_eq!(Some(vocab()), v);
+            m
+        };
+        assert!(
+            common::param_bits(&model) == common::param_bits(&loaded),
+            "{name}: weights differ"
+        );
+    }
+}
+
+#[test]
+fn gguf_vocab_merges() {
+    let device = &<B as Backend>::Device::default();
+    let c = common::tiny_config();
+
+    let vocab = vocab();
+    let expect: Vec<(Vec<u8>, Vec<u8>)> = [(" ", "t"), ("h", "e"), (" t", "he")]
+        .into_iter()
+        .map(|(a, b)| (a.as_bytes().to_vec(), b.as_bytes().to_vec()))
+        .collect();
+    assert_eq!(expect, vocab.merges);
+
+    let p = path("vocab-merges");
+    gguf::save(&c.init::<B>(device), &c, Some(&vocab), GgmlType::F32, &p).expect("save");
+
+    let f = Gguf::open(&p).expect("open");
+    assert_eq!(
+        Some("gpt2"),
+        f.metadata().get("tokenizer.ggml.pre").and_then(Value::as_str)
+    );
+    // 空格按字节级 BPE 的映射写为 'Ġ'
+    let merges: Vec<&str> = f.metadata()["tokenizer.ggml.merges"]
+        .as_array()
+        .expect("merges array")
+        .iter()
+        .map(|v| v.as_str().expect("merge string"))
+        .collect();
+    assert_eq!(vec!["Ġ t", "h e", "Ġt he"], merges);
+    assert_eq!(Some(vocab), gguf::load_vocab(&f).expect("load vocab"));
+}
+
+#[test]
+fn gguf_quantized() {
+    let device = &<B as Backend>::Device::default();
+    let c = common::tiny_config();
+
+    B::seed(11);
+    let model = c.init::<B>(device);
+    let expect: Vec<f32> = model.trf_blocks[0]
+        .ff
+        .linear1
+        .weight
+        .val()
+        .transpose()
+        .into_data()
+        .to_vec()
+        .unwrap();
+    let amax = expect.iter().fold(0f32, |m, v| m.max(v.abs()));
+
+    // 误差上限：F16 的相对误差、Q8_0 和 Q4_0 的量化步长
+    for (ty, tol) in [
+        (GgmlType::F16, amax / 1024.0),
+        (GgmlType::Q8_0, amax / 127.0),
+        (GgmlType::Q4_0, amax / 8.0),
+    ] {
+        let p = path(&format!("quantized-{ty}"));
+        gguf::save(&model, &c, None, ty, &p).expect("save");
+
+        let f = Gguf::open(&p).expect("open");
+        assert_eq!(ty, f.tensors()["blk.0.ffn_up.weight"].ggml_type);
+        assert_eq!(GgmlType::F32, f.tensors()["blk.0.ffn_up.bias"].ggml_type);
+
+        let (shape, got) = f.read_f32("blk.0.ffn_up.weight").expect("read");
+        assert_eq!(vec![4 * EMB_DIM, EMB_DIM], shape);
+        let err = expect.iter().zip(&got).fold(0f32, |m, (a, b)| m.max((a - b).abs()));
+        assert!(err <= tol, "{ty}: max error {err} > {tol}");
+
+        let (_, _, vocab) = gguf::load::<B>(&p, device).expect("load");
+        assert_eq!(None, vocab);
+    }
+}
+
+#[test]
+fn gguf_q4_0_exact() {
+    let device = &<B as Backend>::Device::default();
+    let c = common::tiny_config();
+
+    // 每块都包含 -4，缩放系数为 0.5，各元素都能精确表示
+    let data: Vec<f32> = (0..VOCAB_SIZE * EMB_DIM)
+        .map(|i| ((i * 7 % 16) as f32 - 8.0) * 0.5)
+        .collect();
+    let mut model = c.init::<B>(device);
+    let wte = Tensor::from_data(TensorData::new(data.clone(), [VOCAB_SIZE, EMB_DIM]), device);
+    model.tok_emb.weight = Param::from_tensor(wte);
+
+    let p = path("q4_0-exact");
+    gguf::save(&model, &c, None, GgmlType::Q4_0, &p).expect("save");
+    let (_, loaded, _) = gguf::load::<B>(&p, device).expect("load");
+
+    let got: Vec<f32> = loaded.tok_emb.weight.val().into_data().to_vec().unwrap();
+    assert_eq!(data, got);
+}
+
+#[test]
+fn gguf_truncated() {
+    let device = &<B as Backend>::Device::default();
+    let c = common::tiny_config();
+    let model = c.init::<B>(device);
+
+    let p = path("truncated");
+    gguf::save(&model, &c, None, GgmlType::F32, &p).expect("save");
+    let mut bytes = fs::read(&p).unwrap();
+    bytes.truncate(bytes.len() - 4);
+
+    let Err(err) = Gguf::parse(bytes) else {
+        panic!("should fail");
+    };
+    let msg = format!("{err:#}");
+    assert!(msg.contains("beyond data"), "{msg}");
+}
+
+#[test]
+fn gguf_offset_overflow() {
+    let device = &<B as Backend>::Device::default();
+    let c = common::tiny_config();
+    let model = c.init::<B>(device);
+
+    let p = path("offset-overflow");
+    gguf::save(&model, &c, None, GgmlType::F32, &p).expect("save");
+    let mut bytes = fs::read(&p).unwrap();
+
+    // 把 token_embd.weight 的偏移量改为 u64::MAX：名称之后依次为维数、各维度、类型和偏移量
+    let name = b"token_embd.weight";
+    let mut i = bytes.windows(name.len()).position(|v| v == name).expect("tensor info") + name.len();
+    let ndims = u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap()) as usize;
+    i += 4 + 8 * ndims + 4;
+    bytes[i..i + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+
+    let Err(err) = Gguf::parse(bytes) else {
+        panic!("should fail");
+    };
+    let msg = format!("{err:#}");
+    assert!(msg.contains("beyond data"), "{msg}");
+}
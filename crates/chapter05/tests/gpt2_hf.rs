@@ -0,0 +1,225 @@
+use std::fs;
+use std::path::PathBuf;
+
+use burn::backend::NdArray;
+use burn::module::Param;
+use burn::nn::LinearConfig;
+use burn::prelude::*;
+use chapter04::GptModel;
+use chapter05::gpt2::{
+    self, Attn, AttnOutProj, AttnQkv, Block, FeedForward, FeedForwardWb, LayerNorm, Matrix, Params, hf,
+};
+use common::{CONTEXT_LENGTH, EMB_DIM, NLAYERS, Tensors, VOCAB_SIZE};
+use safetensors::Dtype;
+use safetensors::tensor::TensorView;
+
+mod common;
+
+type B = NdArray<f32>;
+
+/// 和 gpt2-tiny 结构相同的合成参数，键为 Hugging Face 的参数名（不含前缀），值为形状和数据。
+fn fixture(wpe_rows: usize) -> Tensors {
+    let mut shapes = vec![
+        ("wte.weight".into(), vec![VOCAB_SIZE, EMB_DIM]),
+        ("wpe.weight".into(), vec![wpe_rows, EMB_DIM]),
+    ];
+    for i in 0..NLAYERS {
+        for ln in ["ln_1", "ln_2"] {
+            shapes.push((format!("h.{i}.{ln}.weight"), vec![EMB_DIM]));
+            shapes.push((format!("h.{i}.{ln}.bias"), vec![EMB_DIM]));
+        }
+        shapes.push((format!("h.{i}.attn.c_attn.weight"), vec![EMB_DIM, 3 * EMB_DIM]));
+        shapes.push((format!("h.{i}.attn.c_attn.bias"), vec![3 * EMB_DIM]));
+        shapes.push((format!("h.{i}.attn.c_proj.weight"), vec![EMB_DIM, EMB_DIM]));
+        shapes.push((format!("h.{i}.attn.c_proj.bias"), vec![EMB_DIM]));
+        shapes.push((format!("h.{i}.mlp.c_fc.weight"), vec![EMB_DIM, 4 * EMB_DIM]));
+        shapes.push((format!("h.{i}.mlp.c_fc.bias"), vec![4 * EMB_DIM]));
+        shapes.push((format!("h.{i}.mlp.c_proj.weight"), vec![4 * EMB_DIM, EMB_DIM]));
+        shapes.push((format!("h.{i}.mlp.c_proj.bias"), vec![EMB_DIM]));
+        // 因果掩码的缓冲区，加载时应当忽略
+        shapes.push((format!("h.{i}.attn.bias"), vec![1, 1, CONTEXT_LENGTH, CONTEXT_LENGTH]));
+    }
+    shapes.push(("ln_f.weight".into(), vec![EMB_DIM]));
+    shapes.push(("ln_f.bias".into(), vec![EMB_DIM]));
+
+    common::random_tensors(42, shapes)
+}
+
+/// 把 `tensors` 以 Hugging Face 的格式写入临时目录，参数名加上 `prefix`。
+fn write_checkpoint(name: &str, prefix: &str, tensors: &Tensors) -> PathBuf {
+    let dir = common::temp_path(&format!("gpt2-hf-{name}"));
+    fs::create_dir_all(&dir).expect("create dir");
+
+    let config = serde_json::json!({
+        "vocab_size": VOCAB_SIZE,
+        "n_positions": CONTEXT_LENGTH,
+        "n_embd": EMB_DIM,
+        "n_head": 4,
+        "n_layer": NLAYERS,
+    });
+    fs::write(dir.join(hf::CONFIG_FILE), config.to_string()).expect("write config");
+
+    let bytes: Vec<(String, Vec<usize>, Vec<u8>)> = tensors
+        .iter()
+        .map(|(k, (shape, data))| {
+            let b = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+            (format!("{prefix}{k}"), shape.clone(), b)
+        })
+        .collect();
+    let views = bytes.iter().map(|(k, shape, b)| {
+        let v = TensorView::new(Dtype::F32, shape.clone(), b).expect("new tensor view");
+        (k.clone(), v)
+    });
+    let data = safetensors::serialize(views, &None).expect("serialize");
+    fs::write(dir.join(hf::WEIGHTS_FILE), data).expect("write weights");
+
+    dir
+}
+
+/// 按 `gpt2::load_params` 的格式组织同样的参数。
+fn to_params(tensors: &Tensors) -> Params {
+    let get = |name: &str| tensors[name].1.clone();
+    let get_2d = |name: &str| {
+        let (shape, data) = &tensors[name];
+        Matrix::new(shape[0], shape[1], data.clone())
+    };
+    let ln = |name: &str| LayerNorm {
+        g: get(&format!("{name}.weight")),
+        b: get(&format!("{name}.bias")),
+    };
+    let wb = |name: &str| FeedForwardWb {
+        w: get_2d(&format!("{name}.weight")),
+        b: get(&format!("{name}.bias")),
+    };
+
+    let blocks = (0..NLAYERS)
+        .map(|i| Block {
+            attn: Attn {
+                c_attn: AttnQkv {
+                    w: get_2d(&format!("h.{i}.attn.c_attn.weight")),
+                    b: get(&format!("h.{i}.attn.c_attn.bias")),
+                },
+                c_proj: AttnOutProj {
+                    w: get_2d(&format!("h.{i}.attn.c_proj.weight")),
+                    b: get(&format!("h.{i}.attn.c_proj.bias")),
+                },
+            },
+            mlp: FeedForward {
+                c_fc: wb(&format!("h.{i}.mlp.c_fc")),
+                c_proj: wb(&format!("h.{i}.mlp.c_proj")),
+            },
+            ln_1: ln(&format!("h.{i}.ln_1")),
+            ln_2: ln(&format!("h.{i}.ln_2")),
+        })
+        .collect();
+
+    Params {
+        blocks,
+        g: get("ln_f.weight"),
+        b: get("ln_f.bias"),
+        wte: get_2d("wte.weight"),
+        wpe: get_2d("wpe.weight"),
+    }
+}
+
+fn logits(model: &GptModel<B>, device: &<B as Backend>::Device) -> Vec<f32> {
+    let idx = Tensor::<B, 1, Int>::from_ints([3, 14, 15, 92, 65, 35], device).unsqueeze::<2>();
+    model.forward(idx).into_data().to_vec().expect("read logits")
+}
+
+#[test]
+fn safetensors_matches_json_params() {
+    let device = &<B as Backend>::Device::default();
+    let tensors = fixture(CONTEXT_LENGTH);
+
+    let (c, _) = hf::load::<B>(&write_checkpoint("plain", "", &tensors), device).expect("load checkpoint");
+    let mut expect = c.init::<B>(device);
+    gpt2::load_weights_into_gpt2(to_params(&tensors), &mut expect).expect("load json params");
+    let expect = logits(&expect, device);
+
+    // GPT2LMHeadModel 保存的参数名带有 transformer. 前缀
+    for prefix in ["", "transformer."] {
+        let dir = write_checkpoint(&format!("prefix-{}", prefix.len()), prefix, &tensors);
+        let (c, model) = hf::load::<B>(&dir, device).expect("load checkpoint");
+        assert_eq!(
+            (VOCAB_SIZE, CONTEXT_LENGTH, EMB_DIM, NLAYERS),
+            (c.vocab_size, c.context_length, c.emb_dim, c.nlayers)
+        );
+        assert_eq!(expect, logits(&model, device), "prefix '{prefix}'");
+    }
+}
+
+#[test]
+fn safetensors_shape_mismatch() {
+    let device = &<B as Backend>::Device::default();
+
+    let dir = write_checkpoint("bad-wpe", "", &fixture(CONTEXT_LENGTH * 2));
+    let err = hf::load::<B>(&dir, device).expect_err("should fail");
+
+    let msg = format!("{err:#}");
+    assert!(msg.contains("wpe.weight") && msg.contains("shape mismatch"), "{msg}");
+}
+
+#[test]
+fn export_round_trip() {
+    let device = &<B as Backend>::Device::default();
+    let c = common::tiny_config();
+
+    B::seed(123);
+    let untied = c.init::<B>(device);
+
+    let mut tied = c.init::<B>(device);
+    tied.out_head.weight = Param::from_tensor(tied.tok_emb.weight.val().transpose());
+
+    for (name, model, tie_word_embeddings) in [("untied", untied, false), ("tied", tied, true)] {
+        let dir = common::temp_path(&format!("gpt2-hf-export-{name}"));
+        hf::save(&model, &c, &dir).expect("save");
+
+        let config: serde_json::Value = serde_json::from_slice(&fs::read(dir.join(hf::CONFIG_FILE)).unwrap()).unwrap();
+        assert_eq!(tie_word_embeddings, config["tie_word_embeddings"], "{name}");
+        let weights = fs::read(dir.join(hf::WEIGHTS_FILE)).unwrap();
+        let st = safetensors::SafeTensors::deserialize(&weights).unwrap();
+        assert_eq!(
+            [EMB_DIM, 3 * EMB_DIM],
+            st.tensor("h.1.attn.c_attn.weight").unwrap().shape()
+        );
+        assert_eq!(!tie_word_embeddings, st.tensor("lm_head.weight").is_ok(), "{name}");
+
+        let (cc, loaded) = hf::load::<B>(&dir, device).expect("load");
+        assert_eq!((c.vocab_size, c.context_length), (cc.vocab_size, cc.context_length));
+        assert!(
+            common::param_bits(&model) == common::param_bits(&loaded),
+            "{name}: weights differ"
+        );
+    }
+}
+
+#[test]
+fn export_classifier() {
+    let device = &<B as Backend>::Device::default();
+    let c = common::tiny_config();
+
+    // 第 6 章的分类模型把输出层替换为带偏置的二分类线性层
+    B::seed(123);
+    let new_classifier = || {
+        let mut m = c.init::<B>(device);
+        m.out_head = LinearConfig::new(EMB_DIM, 2).with_bias(true).init(device);
+        m
+    };
+    let classifier = new_classifier();
+
+    // 不能保存为 GPT2LMHeadModel
+    let dir = common::temp_path("gpt2-hf-export-classifier");
+    let err = hf::save(&classifier, &c, &dir).expect_err("save classifier");
+    assert!(format!("{err:#}").contains("classifier"), "{err:#}");
+
+    // safetensors 文件仍然可以保存输出层的偏置
+    let p = common::temp_path("gpt2-hf-export-classifier.safetensors");
+    hf::save_safetensors(&classifier, &p).expect("save safetensors");
+    let mut loaded = new_classifier();
+    hf::load_safetensors(&p, &mut loaded).expect("load classifier");
+    assert!(
+        common::param_bits(&classifier) == common::param_bits(&loaded),
+        "weights differ"
+    );
+}
@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::PathBuf;
+
+use burn::backend::NdArray;
+use chapter04::Preset;
+use chapter05::gpt2::{
+    self, Attn, AttnOutProj, AttnQkv, Block, FeedForward, FeedForwardWb, LayerNorm, Matrix, Params, npy,
+};
+use chapter05::rand::SplitMix64;
+use common::{CONTEXT_LENGTH, EMB_DIM, NLAYERS, VOCAB_SIZE};
+
+mod common;
+
+type B = NdArray;
+
+/// 和 gpt2-tiny 结构相同的合成参数。
+fn fixture() -> Params {
+    let mut rng = SplitMix64::new(11);
+    let mut mat = |rows: usize, cols: usize| Matrix::new(rows, cols, common::random_vec(&mut rng, rows * cols));
+
+    let mut blocks = vec![];
+    for _ in 0..NLAYERS {
+        blocks.push(Block {
+            attn: Attn {
+                c_attn: AttnQkv {
+                    w: mat(EMB_DIM, 3 * EMB_DIM),
+                    b: vec![0.1; 3 * EMB_DIM],
+                },
+                c_proj: AttnOutProj {
+                    w: mat(EMB_DIM, EMB_DIM),
+                    b: vec![0.2; EMB_DIM],
+                },
+            },
+            mlp: FeedForward {
+                c_fc: FeedForwardWb {
+                    w: mat(EMB_DIM, 4 * EMB_DIM),
+                    b: vec![0.3; 4 * EMB_DIM],
+                },
+                c_proj: FeedForwardWb {
+                    w: mat(4 * EMB_DIM, EMB_DIM),
+                    b: vec![0.4; EMB_DIM],
+                },
+            },
+            ln_1: LayerNorm {
+                g: vec![1.0; EMB_DIM],
+                b: vec![0.0; EMB_DIM],
+            },
+            ln_2: LayerNorm {
+                g: vec![0.9; EMB_DIM],
+                b: vec![0.1; EMB_DIM],
+            },
+        });
+    }
+
+    Params {
+        blocks,
+        g: vec![1.1; EMB_DIM],
+        b: vec![-0.1; EMB_DIM],
+        wte: mat(VOCAB_SIZE, EMB_DIM),
+        wpe: mat(CONTEXT_LENGTH, EMB_DIM),
+    }
+}
+
+fn tiny_hparams() -> serde_json::Value {
+    serde_json::json!({
+        "n_vocab": VOCAB_SIZE,
+        "n_ctx": CONTEXT_LENGTH,
+        "n_embd": EMB_DIM,
+        "n_head": 4,
+        "n_layer": NLAYERS,
+    })
+}
+
+/// 创建名为 `size` 的参数目录并写入 hparams.json。
+fn new_dir(name: &str, size: &str, hparams: serde_json::Value) -> PathBuf {
+    let dir = common::temp_path(&format!("gpt2-npy-{name}")).join(size);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("hparams.json"), hparams.to_string()).unwrap();
+    dir
+}
+
+#[test]
+fn npz_round_trip() {
+    let params = fixture();
+    let dir = new_dir("ok", "124M", tiny_hparams());
+    npy::save_params(&params, &dir.join("params-124m.npz")).expect("save params");
+
+    let (c, loaded) = gpt2::load_settings_and_params(&dir).expect("load settings and params");
+    assert_eq!((VOCAB_SIZE, NLAYERS), (c.vocab_size, c.nlayers));
+    assert!(params == loaded, "params mismatch");
+
+    let mut model = c.init::<B>(&Default::default());
+    gpt2::load_weights_into_gpt2(loaded, &mut model).expect("load weights");
+}
+
+#[test]
+fn npy_dtype() {
+    let mut buf = vec![];
+    npy::write_npy(&mut buf, &[2, 3], &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0]).expect("write npy");
+    let (shape, data) = npy::read_npy(&mut buf.as_slice()).expect("read npy");
+    assert_eq!((vec![2, 3], vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]), (shape, data));
+
+    // 只支持小端序的 float32
+    let i = buf.windows(4).position(|v| v == b"'<f4").expect("descr");
+    buf[i + 1] = b'>';
+    let err = npy::read_npy(&mut buf.as_slice()).expect_err("should fail");
+    assert!(err.to_string().contains("unsupported dtype"), "{err}");
+}
+
+#[test]
+fn npz_shape_mismatch() {
+    let mut params = fixture();
+    params.wte = Matrix::new(VOCAB_SIZE, EMB_DIM + 1, vec![0.0; VOCAB_SIZE * (EMB_DIM + 1)]);
+    let dir = new_dir("mismatch", "124M", tiny_hparams());
+    let p = dir.join("params-124m.npz");
+    npy::save_params(&params, &p).expect("save params");
+
+    // 加载时和 hparams.json 比较形状
+    let err = gpt2::load_settings_and_params(&dir).expect_err("should fail");
+    let msg = format!("{err:#}");
+    assert!(msg.contains("n_vocab, n_embd"), "{msg}");
+
+    let loaded = npy::load_params(&p).expect("load params");
+    let mut model = Preset::Gpt2Tiny
+        .config()
+        .with_vocab_size(VOCAB_SIZE)
+        .with_context_length(CONTEXT_LENGTH)
+        .init::<B>(&Default::default());
+    let err = gpt2::load_weights_into_gpt2(loaded, &mut model).expect_err("should fail");
+    let msg = format!("{err:#}");
+    assert!(msg.contains("load token embeddings"), "{msg}");
+}
+
+#[test]
+fn size_from_hparams() {
+    // 规模由 hparams.json 推断，和目录名无关
+    let dir = new_dir("size-tiny", "355M", tiny_hparams());
+    npy::save_params(&fixture(), &dir.join("params-tiny.npz")).expect("save params");
+    let (c, _) = gpt2::load_settings_and_params(&dir).expect("load settings and params");
+    assert_eq!(EMB_DIM, c.emb_dim);
+
+    // 无法确定加载哪个参数文件
+    fs::write(dir.join("params-other.json"), "{}").unwrap();
+    let err = gpt2::load_settings_and_params(&dir).expect_err("should fail");
+    let msg = format!("{err:#}");
+    assert!(msg.contains("params-other.json"), "{msg}");
+
+    // 层数不一致
+    let dir = new_dir("size-layers", "tiny", tiny_hparams());
+    let mut params = fixture();
+    params.blocks.truncate(1);
+    npy::save_params(&params, &dir.join("params-tiny.npz")).expect("save params");
+    let err = gpt2::load_settings_and_params(&dir).expect_err("should fail");
+    let msg = format!("{err:#}");
+    assert!(msg.contains("n_layer"), "{msg}");
+
+    // 774M 的超参数只匹配 params-774m.*
+    let hparams = serde_json::json!({"n_vocab": 50257, "n_ctx": 1024, "n_embd": 1280, "n_head": 20, "n_layer": 36});
+    let dir = new_dir("size-774m", "774M", hparams);
+    npy::save_params(&fixture(), &dir.join("params-124m.npz")).expect("save params");
+    let err = gpt2::load_settings_and_params(&dir).expect_err("should fail");
+    let msg = format!("{err:#}");
+    assert!(msg.contains("774m") && msg.contains("params-124m.npz"), "{msg}");
+}
+
+#[test]
+fn custom_size() {
+    // 不属于任何预设规模的超参数
+    let mut hparams = tiny_hparams();
+    hparams["n_layer"] = 1.into();
+    let dir = new_dir("custom", "custom", hparams);
+    let mut params = fixture();
+    params.blocks.truncate(1);
+    npy::save_params(&params, &dir.join("params-custom.npz")).expect("save params");
+
+    let (c, loaded) = gpt2::load_settings_and_params(&dir).expect("load settings and params");
+    assert_eq!((EMB_DIM, 4, 1), (c.emb_dim, c.nheads, c.nlayers));
+    assert_eq!(c.emb_dim, gpt2::load_hparams(&dir).expect("load hparams").emb_dim);
+
+    let mut model = c.init::<B>(&Default::default());
+    gpt2::load_weights_into_gpt2(loaded, &mut model).expect("load weights");
+}
@@ -0,0 +1,273 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chapter05::gpt2::{
+    self, Attn, AttnOutProj, AttnQkv, Block, FeedForward, FeedForwardWb, LayerNorm, Matrix, Params, tf,
+};
+use common::{CONTEXT_LENGTH, EMB_DIM, NLAYERS, Tensors as Variables, VOCAB_SIZE};
+
+mod common;
+
+const NUM_SHARDS: usize = 2;
+
+/// 和 gpt2-tiny 结构相同的合成变量，名称和形状和 OpenAI 发布的检查点相同。
+fn fixture() -> Variables {
+    let mut shapes = vec![
+        ("wte".into(), vec![VOCAB_SIZE, EMB_DIM]),
+        ("wpe".into(), vec![CONTEXT_LENGTH, EMB_DIM]),
+    ];
+    for i in 0..NLAYERS {
+        for ln in ["ln_1", "ln_2"] {
+            shapes.push((format!("h{i}/{ln}/g"), vec![EMB_DIM]));
+            shapes.push((format!("h{i}/{ln}/b"), vec![EMB_DIM]));
+        }
+        for (name, d_in, d_out) in [
+            ("attn/c_attn", EMB_DIM, 3 * EMB_DIM),
+            ("attn/c_proj", EMB_DIM, EMB_DIM),
+            ("mlp/c_fc", EMB_DIM, 4 * EMB_DIM),
+            ("mlp/c_proj", 4 * EMB_DIM, EMB_DIM),
+        ] {
+            shapes.push((format!("h{i}/{name}/w"), vec![1, d_in, d_out]));
+            shapes.push((format!("h{i}/{name}/b"), vec![d_out]));
+        }
+    }
+    shapes.push(("ln_f/g".into(), vec![EMB_DIM]));
+    shapes.push(("ln_f/b".into(), vec![EMB_DIM]));
+
+    common::random_tensors(
+        7,
+        shapes.into_iter().map(|(name, shape)| (format!("model/{name}"), shape)),
+    )
+}
+
+fn to_params(vars: &Variables) -> Params {
+    let get = |name: &str| vars[&format!("model/{name}")].1.clone();
+    let get_2d = |name: &str| {
+        let (shape, data) = &vars[&format!("model/{name}")];
+        let (rows, cols) = (shape[shape.len() - 2], shape[shape.len() - 1]);
+        Matrix::new(rows, cols, data.clone())
+    };
+    let ln = |name: &str| LayerNorm {
+        g: get(&format!("{name}/g")),
+        b: get(&format!("{name}/b")),
+    };
+    let wb = |name: &str| FeedForwardWb {
+        w: get_2d(&format!("{name}/w")),
+        b: get(&format!("{name}/b")),
+    };
+
+    let blocks = (0..NLAYERS)
+        .map(|i| Block {
+            attn: Attn {
+                c_attn: AttnQkv {
+                    w: get_2d(&format!("h{i}/attn/c_attn/w")),
+                    b: get(&format!("h{i}/attn/c_attn/b")),
+                },
+                c_proj: AttnOutProj {
+                    w: get_2d(&format!("h{i}/attn/c_proj/w")),
+                    b: get(&format!("h{i}/attn/c_proj/b")),
+                },
+            },
+            mlp: FeedForward {
+                c_fc: wb(&format!("h{i}/mlp/c_fc")),
+                c_proj: wb(&format!("h{i}/mlp/c_proj")),
+            },
+            ln_1: ln(&format!("h{i}/ln_1")),
+            ln_2: ln(&format!("h{i}/ln_2")),
+        })
+        .collect();
+
+    Params {
+        blocks,
+        g: get("ln_f/g"),
+        b: get("ln_f/b"),
+        wte: get_2d("wte"),
+        wpe: get_2d("wpe"),
+    }
+}
+
+fn put_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn put_varint_field(out: &mut Vec<u8>, n: u64, v: u64) {
+    put_varint(out, n << 3);
+    put_varint(out, v);
+}
+
+fn put_bytes_field(out: &mut Vec<u8>, n: u64, v: &[u8]) {
+    put_varint(out, (n << 3) | 2);
+    put_varint(out, v.len() as u64);
+    out.extend_from_slice(v);
+}
+
+/// 按 LevelDB 的格式编码一个块，每两个键设置一个重启点。
+fn encode_block(entries: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut restarts = vec![];
+    let mut prev: &[u8] = &[];
+    for (i, (k, v)) in entries.iter().enumerate() {
+        let shared = if i % 2 == 0 {
+            restarts.push(out.len() as u32);
+            0
+        } else {
+            prev.iter().zip(k).take_while(|(a, b)| a == b).count()
+        };
+        put_varint(&mut out, shared as u64);
+        put_varint(&mut out, (k.len() - shared) as u64);
+        put_varint(&mut out, v.len() as u64);
+        out.extend_from_slice(&k[shared..]);
+        out.extend_from_slice(v);
+        prev = k;
+    }
+    if restarts.is_empty() {
+        restarts.push(0);
+    }
+    for r in &restarts {
+        out.extend(r.to_le_bytes());
+    }
+    out.extend((restarts.len() as u32).to_le_bytes());
+    out
+}
+
+/// 把块追加到文件中，返回块的位置（BlockHandle 的编码）。
+fn append_block(file: &mut Vec<u8>, block: &[u8], compression: u8) -> Vec<u8> {
+    let mut handle = vec![];
+    put_varint(&mut handle, file.len() as u64);
+    put_varint(&mut handle, block.len() as u64);
+    file.extend_from_slice(block);
+    file.push(compression);
+    file.extend([0; 4]);
+    handle
+}
+
+/// 以 tensor bundle 的格式写入 `vars`，变量轮流写入 [`NUM_SHARDS`] 个数据文件，每个数据块最多 5 个键。
+fn write_checkpoint(prefix: &Path, vars: &Variables, compression: u8) {
+    let mut shards = vec![Vec::<u8>::new(); NUM_SHARDS];
+
+    let mut header = vec![];
+    put_varint_field(&mut header, 1, NUM_SHARDS as u64);
+    put_varint_field(&mut header, 2, 0);
+    // VersionDef { producer: 1 }
+    put_bytes_field(&mut header, 3, &[0x08, 0x01]);
+    let mut entries = vec![(vec![], header)];
+
+    for (i, (name, (shape, data))) in vars.iter().enumerate() {
+        let shard_id = i % NUM_SHARDS;
+        let offset = shards[shard_id].len();
+        shards[shard_id].extend(data.iter().flat_map(|v| v.to_le_bytes()));
+
+        let mut shape_proto = vec![];
+        for &d in shape {
+            let mut dim = vec![];
+            put_varint_field(&mut dim, 1, d as u64);
+            put_bytes_field(&mut shape_proto, 2, &dim);
+        }
+
+        let mut entry = vec![];
+        put_varint_field(&mut entry, 1, tf::DT_FLOAT as u64);
+        put_bytes_field(&mut entry, 2, &shape_proto);
+        put_varint_field(&mut entry, 3, shard_id as u64);
+        put_varint_field(&mut entry, 4, offset as u64);
+        put_varint_field(&mut entry, 5, (data.len() * 4) as u64);
+        // crc32c，读取时不校验
+        put_varint(&mut entry, (6 << 3) | 5);
+        entry.extend([0; 4]);
+
+        entries.push((name.as_bytes().to_vec(), entry));
+    }
+
+    let mut file = vec![];
+    let mut index = vec![];
+    for chunk in entries.chunks(5) {
+        let handle = append_block(&mut file, &encode_block(chunk), compression);
+        index.push((chunk.last().unwrap().0.clone(), handle));
+    }
+    let metaindex = append_block(&mut file, &encode_block(&[]), 0);
+    let index = append_block(&mut file, &encode_block(&index), 0);
+
+    let mut footer = [metaindex, index].concat();
+    footer.resize(40, 0);
+    footer.extend(0xdb4775248b80fb57u64.to_le_bytes());
+    file.extend(footer);
+
+    let name = prefix.file_name().unwrap().to_str().unwrap();
+    fs::write(prefix.with_file_name(format!("{name}.index")), file).unwrap();
+    for (i, data) in shards.into_iter().enumerate() {
+        let p = prefix.with_file_name(format!("{name}.data-{i:05}-of-{NUM_SHARDS:05}"));
+        fs::write(p, data).unwrap();
+    }
+}
+
+fn new_dir(name: &str) -> PathBuf {
+    let dir = common::temp_path(&format!("gpt2-tf-{name}"));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn load_tf_checkpoint() {
+    let vars = fixture();
+    let dir = new_dir("ok");
+    let prefix = dir.join(tf::CHECKPOINT_PREFIX);
+    write_checkpoint(&prefix, &vars, 0);
+
+    let ckpt = tf::Checkpoint::open(&prefix).expect("open checkpoint");
+    assert_eq!(vars.len(), ckpt.entries().len());
+    let e = &ckpt.entries()["model/h1/mlp/c_fc/w"];
+    assert_eq!(vec![1, EMB_DIM, 4 * EMB_DIM], e.shape);
+    assert_eq!(vars["model/wpe"], ckpt.read_f32("model/wpe").unwrap());
+
+    // 没有 JSON 参数时 load_settings_and_params 直接读取检查点
+    let hparams = serde_json::json!({
+        "n_vocab": VOCAB_SIZE,
+        "n_ctx": CONTEXT_LENGTH,
+        "n_embd": EMB_DIM,
+        "n_head": 4,
+        "n_layer": NLAYERS,
+    });
+    fs::write(dir.join("hparams.json"), hparams.to_string()).unwrap();
+
+    let (c, params) = gpt2::load_settings_and_params(&dir).expect("load settings and params");
+    assert_eq!((VOCAB_SIZE, CONTEXT_LENGTH), (c.vocab_size, c.context_length));
+    assert!(to_params(&vars) == params, "params mismatch");
+}
+
+#[test]
+fn tf_checkpoint_compressed_block() {
+    let dir = new_dir("compressed");
+    let prefix = dir.join(tf::CHECKPOINT_PREFIX);
+    write_checkpoint(&prefix, &fixture(), 1);
+
+    let err = tf::Checkpoint::open(&prefix).expect_err("should fail");
+    let msg = format!("{err:#}");
+    assert!(msg.contains("compressed"), "{msg}");
+}
+
+#[test]
+fn tf_checkpoint_block_out_of_range() {
+    let dir = new_dir("out-of-range");
+    let prefix = dir.join(tf::CHECKPOINT_PREFIX);
+    write_checkpoint(&prefix, &fixture(), 0);
+
+    // 把索引块的位置改为 offset + size 溢出 u64 的值
+    let index = prefix.with_file_name(format!("{}.index", tf::CHECKPOINT_PREFIX));
+    let mut file = fs::read(&index).unwrap();
+    let mut footer = vec![];
+    for v in [0, 0, u64::MAX, 2] {
+        put_varint(&mut footer, v);
+    }
+    footer.resize(40, 0);
+    footer.extend(0xdb4775248b80fb57u64.to_le_bytes());
+    let n = file.len();
+    file[n - footer.len()..].copy_from_slice(&footer);
+    fs::write(&index, file).unwrap();
+
+    let err = tf::Checkpoint::open(&prefix).expect_err("should fail");
+    let msg = format!("{err:#}");
+    assert!(msg.contains("out of range"), "{msg}");
+}
@@ -0,0 +1,48 @@
+use burn::backend::NdArray;
+use burn::prelude::*;
+use chapter05::rand::{self, SplitMix64};
+
+type B = NdArray<f32>;
+
+#[test]
+fn split_mix64() {
+    // 参考实现 https://prng.di.unimi.it/splitmix64.c 以 0 为种子的输出
+    let mut rng = SplitMix64::new(0);
+    let got: Vec<_> = (0..3).map(|_| rng.next_u64()).collect();
+    assert_eq!(
+        vec![0xE220_A839_7B1D_CDAF, 0x6E78_9E6A_A1B9_65F4, 0x06C4_5D18_8009_454F],
+        got
+    );
+
+    let mut rng = SplitMix64::for_row(123, 1);
+    let got: Vec<_> = (0..4).map(|_| rng.next_f64()).collect();
+    assert_eq!(
+        vec![
+            0.010107104395478683,
+            0.6460718640057348,
+            0.5652682773742468,
+            0.5121719717653246
+        ],
+        got
+    );
+}
+
+#[test]
+fn multinomial_with() {
+    let device = &<B as Backend>::Device::default();
+    let probas = Tensor::<B, 2>::from_floats([[0.1, 0.2, 0.3, 0.4], [0.0, 0.5, 0.0, 0.5]], device);
+
+    let mut rngs: Vec<_> = (0..2).map(|i| SplitMix64::for_row(123, i)).collect();
+    let mut got = vec![];
+    for i in 0..8 {
+        // 结果不受后端随机数的影响
+        B::seed(i);
+        let ids = rand::multinomial_with(probas.clone(), &mut rngs);
+        assert_eq!([2, 1], ids.dims());
+        got.push(ids.into_data().to_vec::<i64>().expect("read ids"));
+    }
+
+    // 第 2 行不会采样到概率为 0 的 token
+    let expect = [[1, 1], [3, 3], [3, 3], [2, 3], [2, 1], [0, 3], [3, 3], [2, 1]];
+    assert_eq!(expect.map(Vec::from).to_vec(), got);
+}
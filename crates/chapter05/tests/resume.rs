@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use burn::backend::{Autodiff, NdArray};
+use burn::data::dataloader::DataLoader;
+use burn::module::Module;
+use burn::optim::{AdamWConfig, GradientsParams, Optimizer};
+use burn::prelude::*;
+use chapter02::dataset::{self, Batch, LoaderV1Options};
+use chapter02::tokenizer::Tokenizer;
+use chapter04::{Config, GptModel, Preset};
+use chapter05::checkpoint::CheckpointMeta;
+use chapter05::loss;
+use chapter05::resume::{Checkpointer, ResumableLoader, TrainState};
+
+mod common;
+
+type B = Autodiff<NdArray<f32>>;
+
+const EPOCHES: usize = 2;
+const SAVE_EVERY: usize = 5;
+const EVAL_FREQ: usize = 4;
+
+/// 按字节分词。
+struct Bytes;
+
+impl Tokenizer for Bytes {
+    fn decode(&self, ids: &[u32]) -> anyhow::Result<String> {
+        let v: Vec<u8> = ids.iter().map(|&v| v as u8).collect();
+        Ok(String::from_utf8(v)?)
+    }
+
+    fn encode(&self, text: &str) -> anyhow::Result<Vec<u32>> {
+        Ok(text.bytes().map(u32::from).collect())
+    }
+}
+
+fn config() -> Config {
+    Preset::Gpt2Tiny
+        .config()
+        .with_vocab_size(256)
+        .with_context_length(16)
+        .with_drop_rate(0.1)
+}
+
+fn new_dir(name: &str) -> PathBuf {
+    common::temp_path(&format!("resume-{name}"))
+}
+
+fn new_loader(shuffle_seed: Option<u64>) -> Arc<dyn DataLoader<B, Batch<B>>> {
+    let text = "Every effort moves you forward, and every step counts. ".repeat(8);
+    let opts = LoaderV1Options {
+        batch_size: 2,
+        max_length: 16,
+        stride: 16,
+        shuffle_seed,
+        drop_last: true,
+        num_workers: 0,
+    };
+    dataset::create_dataloader_v1::<B, _>(&text, &Bytes, opts).expect("new loader")
+}
+
+fn train<O: Optimizer<GptModel<B>, B>>(
+    mut model: GptModel<B>,
+    mut optimizer: O,
+    mut state: TrainState,
+    checkpointer: &Checkpointer,
+    stop_at: Option<usize>,
+) -> (GptModel<B>, TrainState) {
+    let device = &<B as Backend>::Device::default();
+    let train_loader = ResumableLoader::new(new_loader(Some(123)));
+    let val_loader = new_loader(None);
+
+    for _ in state.epochs_done..EPOCHES {
+        for (input_batch, target_batch) in train_loader.epoch(&mut state) {
+            let loss = loss::calc_loss_batch(input_batch.clone(), target_batch, &model, device);
+            let grads = GradientsParams::from_grads(loss.backward(), &model);
+            model = optimizer.step(4e-4, model, grads);
+
+            state.tokens_seen += input_batch.shape().num_elements();
+            state.batches_done += 1;
+            state.step += 1;
+
+            // 和各章的训练循环一样，第一步之后就评估
+            if (state.step - 1) % EVAL_FREQ == 0 {
+                let m = model.clone().no_grad();
+                let train_loss = train_loader.eval(|v| loss::calc_loss_loader(v, &m, 2.into(), device));
+                state.train_losses.push(train_loss);
+                state
+                    .val_losses
+                    .push(loss::calc_loss_loader(val_loader.as_ref(), &m, 2.into(), device));
+                state.track_tokens_seen.push(state.tokens_seen);
+            }
+
+            checkpointer
+                .on_step(&model, &optimizer, &mut state)
+                .expect("save checkpoint");
+            if stop_at == Some(state.step) {
+                return (model, state);
+            }
+        }
+        state.finish_epoch();
+    }
+
+    (model, state)
+}
+
+#[test]
+fn resume_matches_uninterrupted() {
+    let device = &<B as Backend>::Device::default();
+    let meta = CheckpointMeta::new(config());
+    let new_optimizer = || AdamWConfig::new().with_weight_decay(0.1).init::<B, GptModel<B>>();
+
+    B::seed(123);
+    let model = meta.init::<B>(device);
+    let c = Checkpointer::new(new_dir("full"), meta.clone(), SAVE_EVERY);
+    let (full_model, full) = train(model, new_optimizer(), TrainState::new(123), &c, None);
+    assert!(full.step > 3 * SAVE_EVERY + 3, "too few steps: {}", full.step);
+
+    // 第 2 轮中，第 3 个检查点之后、第 4 个检查点之前中断
+    B::seed(123);
+    let model = meta.init::<B>(device);
+    let c = Checkpointer::new(new_dir("interrupted"), meta.clone(), SAVE_EVERY);
+    let stop_at = 3 * SAVE_EVERY + 3;
+    let (_, partial) = train(model, new_optimizer(), TrainState::new(123), &c, Some(stop_at));
+    assert_eq!(stop_at, partial.step);
+
+    // 随机种子不同，确保恢复时重设了种子
+    B::seed(7);
+    let (model, optimizer, state) = c.resume::<B, _>(new_optimizer(), device).expect("resume");
+    assert_eq!((3 * SAVE_EVERY, 1), (state.step, state.epochs_done));
+    let (resumed_model, resumed) = train(model, optimizer, state, &c, None);
+
+    assert_eq!(full.train_losses, resumed.train_losses);
+    assert_eq!(full.val_losses, resumed.val_losses);
+    assert_eq!(full.track_tokens_seen, resumed.track_tokens_seen);
+    assert!(
+        common::param_bits(&full_model) == common::param_bits(&resumed_model),
+        "weights differ"
+    );
+}
+
+#[test]
+fn save_with_stale_old() {
+    let device = &<B as Backend>::Device::default();
+    let meta = CheckpointMeta::new(config());
+    let new_optimizer = || AdamWConfig::new().init::<B, GptModel<B>>();
+    let model = meta.init::<B>(device);
+
+    let dir = new_dir("stale-old");
+    let c = Checkpointer::new(&dir, meta.clone(), SAVE_EVERY);
+    let mut state = TrainState::new(123);
+    c.save(&model, &new_optimizer(), &state).expect("save");
+
+    // 模拟上次保存在删除旧检查点之前中断
+    let old = PathBuf::from(format!("{}.old", dir.display()));
+    fs::create_dir_all(&old).unwrap();
+    fs::write(old.join("junk"), "junk").unwrap();
+
+    state.step = 1;
+    c.save(&model, &new_optimizer(), &state).expect("save with stale old");
+    assert!(!old.exists());
+    let (_, _, resumed) = c.resume::<B, _>(new_optimizer(), device).expect("resume");
+    assert_eq!(1, resumed.step);
+}
@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use burn::backend::NdArray;
+use burn::prelude::*;
+use chapter05::sampling::{self, AllowedTokens, GenerateState, LogitsProcessor, LogitsProcessors, TopK};
+use chapter05::utils::{self, GenerateOptions};
+
+type B = NdArray<f32>;
+
+const NEG_INF: f32 = f32::NEG_INFINITY;
+
+fn logits<const N: usize, const M: usize>(v: [[f32; M]; N]) -> Tensor<B, 2> {
+    Tensor::from_floats(v, &Default::default())
+}
+
+/// 概率为 [0.5, 0.3, 0.15, 0.05] 的 logits，第 2 行为其逆序。
+fn probas_logits() -> Tensor<B, 2> {
+    let p = [0.5f32, 0.3, 0.15, 0.05].map(f32::ln);
+    let mut q = p;
+    q.reverse();
+    logits([p, q])
+}
+
+fn assert_logits<const N: usize, const M: usize>(expect: [[f32; M]; N], got: Tensor<B, 2>) {
+    let got = got.into_data().to_vec::<f32>().expect("read logits");
+    let expect = expect.as_flattened();
+    assert_eq!(expect.len(), got.len());
+    for (i, (e, g)) in expect.iter().zip(&got).enumerate() {
+        let ok = if e.is_infinite() { e == g } else { (e - g).abs() < 1e-5 };
+        assert!(ok, "#{i}: expect {expect:?}, got {got:?}");
+    }
+}
+
+fn assert_kept<const N: usize, const M: usize>(expect: [[bool; M]; N], got: Tensor<B, 2>) {
+    let got = got.into_data().to_vec::<f32>().expect("read logits");
+    let got: Vec<_> = got.iter().map(|v| v.is_finite()).collect();
+    assert_eq!(expect.as_flattened(), got.as_slice());
+}
+
+#[test]
+fn logit_bias() {
+    let bias = sampling::logit_bias::<B>(&[(0, 10.0), (2, -1.0)], &[3], 4, &Default::default()).expect("logit bias");
+    assert_logits([[11.0, 2.0, 2.0, NEG_INF]], logits([[1.0, 2.0, 3.0, 4.0]]) + bias);
+}
+
+#[test]
+fn logit_bias_out_of_vocab() {
+    let err = sampling::logit_bias::<B>(&[], &[4], 4, &Default::default()).expect_err("out of vocab");
+    assert!(err.to_string().contains("banned token 4 out of vocab"), "{err}");
+
+    let opts = GenerateOptions::new(1, 8).with_logit_bias(vec![(4, 1.0)]);
+    let err = utils::sample_next(probas_logits(), &opts, &[vec![0], vec![0]], &[1, 1]).expect_err("out of vocab");
+    assert!(err.to_string().contains("logit bias token 4 out of vocab"), "{err}");
+}
+
+#[test]
+fn logit_bias_ban_all() {
+    let err = sampling::logit_bias::<B>(&[], &[0, 1], 2, &Default::default()).expect_err("ban all");
+    assert!(err.to_string().contains("exclude every token"), "{err}");
+}
+
+#[test]
+fn repetition_penalty() {
+    let histories: [&[u32]; 2] = [&[0, 1, 1], &[3]];
+    let got =
+        sampling::apply_repetition_penalty(logits([[2.0, -2.0, 1.0, 0.5], [2.0, -2.0, 1.0, 0.5]]), &histories, 2.0);
+    assert_logits([[1.0, -4.0, 1.0, 0.5], [2.0, -2.0, 1.0, 0.25]], got);
+}
+
+#[test]
+fn frequency_presence_penalty() {
+    let histories: [&[u32]; 1] = [&[1, 1, 2]];
+    let got = sampling::apply_frequency_presence_penalty(logits([[0.0; 4]]), &histories, 0.5, 1.0);
+    assert_logits([[0.0, -2.0, -1.5, 0.0]], got);
+}
+
+#[test]
+fn top_k() {
+    assert_kept(
+        [[true, true, false, false], [false, false, true, true]],
+        sampling::top_k(probas_logits(), 2),
+    );
+}
+
+#[test]
+fn top_p() {
+    // 累计概率为 [0.5, 0.8, 0.95, 1.0]
+    assert_kept(
+        [[true, true, false, false], [false, false, true, true]],
+        sampling::top_p(probas_logits(), 0.7),
+    );
+    assert_kept(
+        [[true, true, true, false], [false, true, true, true]],
+        sampling::top_p(probas_logits(), 0.9),
+    );
+    // 至少保留概率最大的 token
+    assert_kept(
+        [[true, false, false, false], [false, false, false, true]],
+        sampling::top_p(probas_logits(), 0.0),
+    );
+    assert_kept([[true; 4]; 2], sampling::top_p(probas_logits(), 1.0));
+}
+
+#[test]
+fn min_p() {
+    // 阈值为 0.5 * 0.5 = 0.25
+    assert_kept(
+        [[true, true, false, false], [false, false, true, true]],
+        sampling::min_p(probas_logits(), 0.5),
+    );
+    assert_kept(
+        [[true, true, true, false], [false, true, true, true]],
+        sampling::min_p(probas_logits(), 0.2),
+    );
+}
+
+#[test]
+fn sample_next() {
+    let seqs = [vec![7, 0], vec![7, 0]];
+    let prompt_lens = [1, 1];
+
+    let sample = |opts: &GenerateOptions| -> Vec<i64> {
+        utils::sample_next(probas_logits(), opts, &seqs, &prompt_lens)
+            .expect("sample next")
+            .into_data()
+            .to_vec()
+            .expect("read ids")
+    };
+
+    let greedy = GenerateOptions::new(1, 8);
+    assert_eq!(vec![0, 3], sample(&greedy));
+
+    // 已生成的 token 0 被惩罚后，第 1 行改为选择 token 1
+    let opts = greedy.clone().with_presence_penalty(1.0);
+    assert_eq!(vec![1, 3], sample(&opts));
+
+    let opts = greedy
+        .clone()
+        .with_banned_tokens(vec![3])
+        .with_logit_bias(vec![(2, 5.0)]);
+    assert_eq!(vec![2, 2], sample(&opts));
+
+    // 过滤后只剩一个 token，采样结果是确定的
+    B::seed(123);
+    for opts in [
+        greedy.clone().with_temperature(1.0).with_topk(Some(1)),
+        greedy.clone().with_temperature(1.0).with_topp(Some(0.3)),
+        greedy.clone().with_temperature(1.0).with_minp(Some(0.9)),
+    ] {
+        for _ in 0..10 {
+            assert_eq!(vec![0, 3], sample(&opts));
+        }
+    }
+}
+
+#[test]
+fn processor_chain() {
+    let seqs = [vec![0], vec![0]];
+    let prompt_lens = [1, 1];
+    let state = GenerateState {
+        seqs: &seqs,
+        prompt_lens: &prompt_lens,
+        elapsed: Duration::ZERO,
+    };
+
+    // 处理器按添加的顺序应用：先把 token 0 限制在标签之外，再只保留最大的 1 个
+    let processors = LogitsProcessors::<B>::new()
+        .with(AllowedTokens(vec![1, 2]))
+        .with(|logits: Tensor<B, 2>, _: &GenerateState| logits * 2.0)
+        .with(TopK(1));
+
+    assert_logits(
+        [
+            [NEG_INF, 2.0 * 0.3f32.ln(), NEG_INF, NEG_INF],
+            [NEG_INF, NEG_INF, 2.0 * 0.3f32.ln(), NEG_INF],
+        ],
+        processors.process(probas_logits(), &state),
+    );
+}
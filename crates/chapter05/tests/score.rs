@@ -0,0 +1,101 @@
+use burn::backend::NdArray;
+use burn::prelude::*;
+use chapter05::score::{self, ScoreOptions};
+use chapter05::utils;
+use tiktoken::ext::Encoding;
+
+mod common;
+
+type B = NdArray<f32>;
+
+const TEXT: &str = "Every effort moves you forward.";
+
+fn assert_close(expect: f64, got: f64) {
+    assert!(
+        (expect - got).abs() < 1e-4 * expect.abs().max(1.0),
+        "expect {expect}, got {got}"
+    );
+}
+
+#[test]
+fn score_matches_cross_entropy() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+    let tokenizer = Encoding::gpt2();
+
+    let ids = tokenizer.encode(TEXT, &Default::default());
+    let n = ids.len();
+
+    // 窗口足以容纳整段文本时，平均 NLL 即交叉熵
+    let got = score::score_text(&model, &tokenizer, TEXT, &ScoreOptions::new(64, 32), device);
+    assert_eq!(&ids[1..], got.token_ids.as_slice());
+
+    let ids: Vec<i64> = ids.iter().map(|&v| v as i64).collect();
+    let ids = Tensor::<B, 1, Int>::from_ints(ids.as_slice(), device).reshape([1, n]);
+    let logits = model.forward(ids.clone().slice(s![.., ..n - 1]));
+    let loss = utils::cross_entropy(logits, ids.slice(s![.., 1..])).into_scalar() as f64;
+
+    assert_close(loss, got.mean_nll());
+    assert_close(loss.exp(), got.perplexity());
+}
+
+#[test]
+fn sliding_window() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+    let tokenizer = Encoding::gpt2();
+
+    // 窗口长度为 MAX_LENGTH 时，第一个窗口对前 k 个 token 计分
+    const MAX_LENGTH: usize = 4;
+    let k = MAX_LENGTH - 1;
+
+    let text = TEXT.repeat(2);
+    let full = score::score_text(&model, &tokenizer, &text, &ScoreOptions::new(64, 32), device);
+    let n = full.num_tokens();
+    assert!((MAX_LENGTH..64).contains(&n), "{n} tokens");
+
+    for stride in 1..MAX_LENGTH {
+        let got = score::score_text(
+            &model,
+            &tokenizer,
+            &text,
+            &ScoreOptions::new(MAX_LENGTH, stride),
+            device,
+        );
+        assert_eq!(full.token_ids, got.token_ids, "stride {stride}");
+
+        // 第一个窗口内的 token 的上下文和完整文本相同
+        for i in 0..k {
+            assert_close(full.logprobs[i] as f64, got.logprobs[i] as f64);
+        }
+        // 之后的 token 的上下文被截断
+        assert!(full.logprobs[k..n] != got.logprobs[k..n], "stride {stride}");
+    }
+}
+
+#[test]
+fn continuation() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+    let tokenizer = Encoding::gpt2();
+
+    const MAX_LENGTH: usize = 4;
+    const CONTINUATION: &str = " you forward.";
+    let context = format!("{}Every effort moves", TEXT.repeat(2));
+
+    let encode = |v: &str| tokenizer.encode(v, &Default::default());
+    let mut ids = encode(&context);
+    let skip = ids.len();
+    ids.extend(encode(CONTINUATION));
+    assert!(skip > MAX_LENGTH, "context must be longer than the window");
+
+    // 上下文长于窗口时，第一个计分的 token 以前面 max_length-1 个 token 为上下文
+    let opts = ScoreOptions::new(MAX_LENGTH, 2);
+    let got = score::score_continuation(&model, &tokenizer, &context, CONTINUATION, &opts, device);
+    assert_eq!(encode(CONTINUATION), got.token_ids);
+
+    let k = MAX_LENGTH - 1;
+    let expect = score::score_ids(&model, &ids[skip - k..], k, &opts, device);
+    assert_eq!(expect.token_ids, got.token_ids);
+    assert_eq!(expect.logprobs[0], got.logprobs[0]);
+}
@@ -0,0 +1,76 @@
+use burn::backend::NdArray;
+use burn::prelude::*;
+use chapter04::Preset;
+use chapter05::speculative;
+use chapter05::utils::{self, GenerateOptions};
+
+mod common;
+
+type B = NdArray<f32>;
+
+const PROMPTS: [&[u32]; 3] = [&[6109, 3626, 6100, 345], &[15496], &[40, 1842, 257]];
+
+#[test]
+fn greedy_matches_target() {
+    let device = &<B as Backend>::Device::default();
+    let target = common::new_model::<B>(123, device);
+    let draft = common::new_model::<B>(456, device);
+
+    // 上下文长度小于输入和输出的总长度，以覆盖超出上下文后不再使用草稿的情况
+    let opts = GenerateOptions::new(10, 8);
+
+    for p in PROMPTS {
+        let expect = utils::generate_batch(&target, &[p.to_vec()], &opts, device).expect("generate");
+        let (got, stats) =
+            speculative::speculative_generate(&target, &draft, p, &opts, 3, device).expect("speculative generate");
+        assert_eq!(expect[0], got, "prompt {p:?}");
+        assert!(stats.accepted <= stats.drafted, "bad stats {stats:?}");
+    }
+
+    // 以生成的第 3 个 token 作为 EOS
+    let expect = utils::generate_batch(&target, &[PROMPTS[0].to_vec()], &opts, device).expect("generate");
+    let opts = opts.with_eos_id(Some(expect[0][PROMPTS[0].len() + 2] as usize));
+    let expect = utils::generate_batch(&target, &[PROMPTS[0].to_vec()], &opts, device).expect("generate");
+    let (got, _) =
+        speculative::speculative_generate(&target, &draft, PROMPTS[0], &opts, 3, device).expect("speculative generate");
+    assert_eq!(expect[0], got);
+}
+
+#[test]
+fn same_draft_accepts_all() {
+    let device = &<B as Backend>::Device::default();
+    let target = common::new_model::<B>(123, device);
+
+    let opts = GenerateOptions::new(10, 64);
+    let (got, stats) = speculative::speculative_generate(&target, &target, PROMPTS[0], &opts, 4, device)
+        .expect("speculative generate");
+
+    // 每轮接受 4 个草稿 token 并额外生成 1 个
+    assert_eq!(PROMPTS[0].len() + 10, got.len());
+    assert_eq!(2, stats.rounds);
+    assert_eq!(8, stats.drafted);
+    assert_eq!(1.0, stats.acceptance_rate());
+
+    // 采样时草稿和目标的分布相同，草稿 token 总是被接受
+    let opts = opts.with_temperature(1.0).with_topk(Some(5)).with_seed(Some(42));
+    let (got, stats) = speculative::speculative_generate(&target, &target, PROMPTS[0], &opts, 4, device)
+        .expect("speculative generate");
+    assert_eq!(PROMPTS[0].len() + 10, got.len());
+    assert_eq!(stats.drafted, stats.accepted);
+
+    let (again, _) = speculative::speculative_generate(&target, &target, PROMPTS[0], &opts, 4, device)
+        .expect("speculative generate");
+    assert_eq!(got, again);
+}
+
+#[test]
+fn vocab_size_mismatch() {
+    let device = &<B as Backend>::Device::default();
+    let target = common::new_model::<B>(123, device);
+    let draft = Preset::Gpt2Tiny.config().with_vocab_size(100).init::<B>(device);
+
+    let opts = GenerateOptions::new(10, 64);
+    let err = speculative::speculative_generate(&target, &draft, PROMPTS[0], &opts, 4, device)
+        .expect_err("vocab size mismatch");
+    assert!(err.to_string().contains("does not match"), "{err}");
+}
@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use chapter05::sampling::GenerateState;
+use chapter05::stopping::{
+    self, EosTokens, MaxNewTokens, MaxTime, StopStrings, StoppingCriteria, StoppingCriteriaList,
+};
+use tiktoken::ext::Encoding;
+
+fn check(c: &dyn StoppingCriteria, seqs: &[Vec<u32>], prompt_lens: &[usize], elapsed: Duration) -> Vec<Option<usize>> {
+    let state = GenerateState {
+        seqs,
+        prompt_lens,
+        elapsed,
+    };
+    (0..seqs.len()).map(|i| c.should_stop(&state, i)).collect()
+}
+
+#[test]
+fn eos_tokens() {
+    let c = EosTokens::new([1, 2]);
+    let seqs = [vec![1, 5], vec![5, 2], vec![5, 1], vec![2]];
+
+    // 输入中的 EOS 不会触发停止
+    let got = check(&c, &seqs, &[1, 1, 1, 1], Duration::ZERO);
+    assert_eq!(vec![None, Some(1), Some(1), None], got);
+}
+
+#[test]
+fn criteria_list() {
+    let c = StoppingCriteriaList::new()
+        .with(MaxNewTokens(2))
+        .with(EosTokens::new([9]))
+        .with(MaxTime(Duration::from_secs(1)));
+    let seqs = [vec![0, 1], vec![0, 1, 2], vec![0, 1, 9], vec![0, 9]];
+    let prompt_lens = [1; 4];
+
+    let got = check(&c, &seqs, &prompt_lens, Duration::ZERO);
+    assert_eq!(vec![None, Some(0), Some(1), Some(1)], got);
+
+    let got = check(&c, &seqs, &prompt_lens, Duration::from_secs(1));
+    assert_eq!(vec![Some(0), Some(0), Some(1), Some(1)], got);
+}
+
+#[test]
+fn stop_strings() {
+    let tokenizer = Encoding::gpt2();
+    let allowed_specials = HashSet::new();
+
+    let prompt = tokenizer.encode("### Input:", &allowed_specials);
+    let c = StopStrings::new(&tokenizer, ["###", "\n\n"]);
+
+    let cases = [
+        ("Hello, world", None),
+        ("Hello\n\n", Some(0)),
+        ("The answer is 42.###", Some(0)),
+    ];
+    for (text, expect) in cases {
+        let mut seq = prompt.clone();
+        seq.extend(tokenizer.encode(text, &allowed_specials));
+
+        // 每一步都会检查，所以只需查找以最后一个 token 结尾的停止字符串；输入中的停止字符串不会触发停止
+        let got = check(&c, &[seq], &[prompt.len()], Duration::ZERO);
+        assert_eq!(vec![expect], got, "text {text:?}");
+    }
+}
+
+#[test]
+fn trim_stop_sequences() {
+    let stops = ["\n\n### Instruction:".to_owned(), "###".to_owned(), String::new()];
+
+    assert_eq!(
+        "Answer",
+        stopping::trim_stop_sequences("Answer\n\n### Instruction: more", &stops)
+    );
+    assert_eq!(
+        "A ",
+        stopping::trim_stop_sequences("A ### B\n\n### Instruction:", &stops)
+    );
+    assert_eq!("no stop", stopping::trim_stop_sequences("no stop", &stops));
+}
@@ -0,0 +1,85 @@
+use burn::backend::NdArray;
+use burn::prelude::*;
+use chapter04::GptModel;
+use chapter05::stream::{Generator, StreamToken, Utf8Decoder};
+use chapter05::utils::{self, GenerateOptions};
+use tiktoken::ext::Encoding;
+
+mod common;
+
+type B = NdArray<f32>;
+
+const PROMPT: &str = "Every effort moves you";
+
+fn stream(model: &GptModel<B>, tokenizer: &Encoding, opts: &GenerateOptions) -> Vec<StreamToken> {
+    let device = &<B as Backend>::Device::default();
+    Generator::new(model, tokenizer, PROMPT, opts.clone(), device)
+        .expect("generator")
+        .collect::<anyhow::Result<_>>()
+        .expect("stream tokens")
+}
+
+fn concat(tokens: &[StreamToken]) -> String {
+    tokens.iter().map(|v| v.text.as_str()).collect()
+}
+
+#[test]
+fn utf8_decoder() {
+    let mut decoder = Utf8Decoder::new();
+
+    // “你好”的每个字符占 3 个字节，逐字节输入时只在字符完整后输出
+    let got: Vec<_> = "你好".bytes().map(|b| decoder.push(&[b])).collect();
+    assert_eq!(vec!["", "", "你", "", "", "好"], got);
+
+    assert_eq!("a\u{FFFD}b", decoder.push(b"a\xFFb"));
+    assert_eq!("c", decoder.push(b"c\xE4\xBD"));
+    assert_eq!("\u{FFFD}", decoder.finish());
+    assert_eq!("", decoder.finish());
+}
+
+#[test]
+fn stream_matches_generate_text() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+    let tokenizer = Encoding::gpt2();
+
+    let opts = GenerateOptions::new(8, 64);
+    let tokens = stream(&model, &tokenizer, &opts);
+
+    let prompt = tokenizer.encode(PROMPT, &Default::default());
+    let expect = utils::generate_batch(&model, std::slice::from_ref(&prompt), &opts, device).expect("generate");
+    let ids: Vec<_> = tokens.iter().map(|v| v.id).collect();
+    assert_eq!(&expect[0][prompt.len()..], ids.as_slice());
+
+    let text = utils::generate_text(&model, &tokenizer, &[PROMPT], &opts, device).expect("generate text");
+    assert_eq!(text[0], concat(&tokens));
+
+    // EOS 作为最后一个 token 返回
+    let eos_id = ids[3];
+    let end = ids.iter().position(|&v| v == eos_id).expect("find eos");
+    let opts = opts.with_eos_id(Some(eos_id as usize));
+    let tokens = stream(&model, &tokenizer, &opts);
+    assert_eq!(&ids[..=end], tokens.iter().map(|v| v.id).collect::<Vec<_>>());
+    let text = utils::generate_text(&model, &tokenizer, &[PROMPT], &opts, device).expect("generate text");
+    assert_eq!(text[0], concat(&tokens));
+}
+
+#[test]
+fn stream_stop_sequences() {
+    let device = &<B as Backend>::Device::default();
+    let model = common::new_model::<B>(123, device);
+    let tokenizer = Encoding::gpt2();
+
+    let opts = GenerateOptions::new(8, 64);
+    let text = concat(&stream(&model, &tokenizer, &opts));
+
+    // 停止字符串从第 2 个字符开始，它的开头在匹配完成前不会被输出
+    let stop: String = text.chars().skip(1).take(3).collect();
+    let opts = opts.with_stop_sequences(vec![stop.clone()]);
+    let tokens = stream(&model, &tokenizer, &opts);
+
+    let expect = &text[..text.find(&stop).expect("find stop")];
+    assert_eq!(expect, concat(&tokens));
+    let got = utils::generate_text(&model, &tokenizer, &[PROMPT], &opts, device).expect("generate text");
+    assert_eq!(got[0], concat(&tokens));
+}
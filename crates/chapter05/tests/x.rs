@@ -165,3 +165,36 @@ fn search_sorted_2d() {
         assert!(matched, "given\n{t}\nand\n{values}\nexpect\n{expect}\ngot\n{got}");
     }
 }
+
+#[test]
+fn cumsum_search_sorted_large() {
+    type T = Tensor<B, 2>;
+
+    let device = &<B as Backend>::Device::default();
+
+    // 长度不是 2 的幂，且远大于上面的用例
+    const N: usize = 1000;
+    let data: Vec<f32> = (0..2 * N).map(|i| (i % 7) as f32).collect();
+    let t = T::from_data(burn::tensor::TensorData::new(data.clone(), [2, N]), device);
+
+    let got = t.cumsum().into_data().to_vec::<f32>().expect("read cumsum");
+    for (row, got) in data.chunks(N).zip(got.chunks(N)) {
+        let expect: Vec<f32> = row
+            .iter()
+            .scan(0.0, |s, v| {
+                *s += v;
+                Some(*s)
+            })
+            .collect();
+        assert_eq!(expect, got);
+    }
+
+    let t = (Tensor::arange(1..(N as i64 + 1), device).float()).expand([2, N]);
+    let values = Tensor::<B, 1>::from_floats([500.5, 1e9], device);
+    let got = t
+        .search_sorted(values)
+        .into_data()
+        .to_vec::<i64>()
+        .expect("read indices");
+    assert_eq!(vec![500, N as i64], got);
+}
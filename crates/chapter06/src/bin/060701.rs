@@ -1,4 +1,5 @@
 use std::fs::OpenOptions;
+use std::path::Path;
 use std::time::Instant;
 
 use anyhow::Context as _;
@@ -8,20 +9,27 @@ use burn::data::dataloader::DataLoader;
 use burn::module::Module;
 use burn::optim::{AdamWConfig, GradientsParams, Optimizer};
 use burn::prelude::Backend;
-use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
 use burn::serde::Serialize;
 use burn::tensor::backend::AutodiffBackend;
 use chapter04::GptModel;
+use chapter05::checkpoint::{self, CheckpointMeta, Head, TrainingSummary};
+use chapter05::resume::{Checkpointer, ResumableLoader, TrainState};
 use chapter06::dataset::{self, Batch, DataLoaderOptions, LoadCsvOptions, SpamDataset};
-use chapter06::{loss, utils};
+use chapter06::{CLASSIFIER_CHECKPOINT, loss, utils};
+use clap::Parser;
 use tiktoken::ext::Encoding;
 
 type B = Autodiff<LibTorch>;
 
 type Device = <LibTorch as Backend>::Device;
 
+/// 训练检查点目录，中断后使用 `--resume` 继续训练。
+const TRAIN_CHECKPOINT: &str = "spam-classifier-train-ckpt";
+
 /// 需要先进去 gpt2 运行 uv run main.py 准备好数据。
 fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
     let device = if !minikit::cuda::is_available() {
         &Device::Cpu
     } else {
@@ -29,7 +37,8 @@ fn main() -> anyhow::Result<()> {
         &Device::Cuda(0)
     };
 
-    let model = utils::load_gpt2_for_fine_tuning("gpt2/124M", device).context("load model")?;
+    const PARAM_DIR: &str = "gpt2/124M";
+    let config = utils::load_gpt2_config(PARAM_DIR).context("load model config")?;
 
     let tokenizer = Encoding::gpt2();
 
@@ -46,38 +55,69 @@ fn main() -> anyhow::Result<()> {
     let opts = DataLoaderOptions::new()
         .with_shuffle_seed(Some(456))
         .with_drop_last(true);
-    let train_loader = dataset::load(train_dataset, opts);
+    let train_loader = ResumableLoader::new(dataset::load(train_dataset, opts));
 
     let validation_loader = dataset::load(validation_dataset, DataLoaderOptions::new());
     // let test_loader = dataset::load(test_dataset, DataLoaderOptions::new());
 
-    let start = Instant::now();
-    B::seed(123);
-
+    const EPOCHES: usize = 5;
+    const LR: LearningRate = 5e-5;
     let optimizer = AdamWConfig::new().with_weight_decay(0.1).init::<B, GptModel<B>>();
 
+    let meta = CheckpointMeta::new(config).with_head(Head::Classifier {
+        num_classes: utils::NUM_CLASSES,
+    });
+    let checkpointer = Checkpointer::new(TRAIN_CHECKPOINT, meta.clone(), cli.save_every);
+    // 恢复训练时模型完全取自检查点，不需要加载预训练参数
+    let (model, optimizer, state) = if cli.resume {
+        let (model, optimizer, state) = checkpointer
+            .resume::<B, _>(optimizer, device)
+            .with_context(|| format!("resume from {TRAIN_CHECKPOINT}"))?;
+        let model = utils::freeze_for_fine_tuning(model).context("freeze model")?;
+        (model, optimizer, state)
+    } else {
+        let model = utils::load_gpt2_for_fine_tuning(PARAM_DIR, device).context("load model")?;
+        B::seed(123);
+        (model, optimizer, TrainState::new(123))
+    };
+
+    let start = Instant::now();
+
     let opts = TrainOpts {
-        model: model.clone(),
-        train_loader: train_loader.as_ref(),
+        model,
+        train_loader: &train_loader,
         val_loader: validation_loader.as_ref(),
         optimizer,
         device: &device,
-        epoches: 5,
+        epoches: EPOCHES,
         eval_freq: 50,
         eval_iter: 5,
-        lr: 5e-5,
+        lr: LR,
+        checkpointer: &checkpointer,
+        state,
     };
 
-    let (model, _, overview) = train_classifer_simple(opts);
+    let (model, _, overview) = train_classifer_simple(opts)?;
 
-    let execution_time_minutes = start.elapsed().as_secs_f64() / 60.0;
+    let elapsed_secs = start.elapsed().as_secs_f64();
+    let execution_time_minutes = elapsed_secs / 60.0;
     println!("Training completed in {execution_time_minutes:.2} minutes");
 
-    // 保存模型用于后续教程
-    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
-
-    const MODEL_PATH: &str = "spam-classifier-model";
-    model.save_file(MODEL_PATH, &recorder).expect("save model");
+    // 保存模型及其配置用于后续教程
+    let training = TrainingSummary {
+        epochs: EPOCHES,
+        steps: overview.steps,
+        examples_seen: Some(overview.examples_seen),
+        learning_rate: Some(LR),
+        train_loss: overview.train_losses.last().copied(),
+        val_loss: overview.val_losses.last().copied(),
+        train_accuracy: overview.train_accs.last().copied(),
+        val_accuracy: overview.val_accs.last().copied(),
+        elapsed_secs: Some(elapsed_secs),
+        ..Default::default()
+    };
+    let meta = meta.with_training(Some(training));
+    checkpoint::save_checkpoint(&model, &meta, Path::new(CLASSIFIER_CHECKPOINT)).context("save model")?;
 
     // 保存训练数据，用于后续绘图。
     overview.save("train-overview.json").context("save train-overview")?;
@@ -85,9 +125,19 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[derive(Parser)]
+struct Cli {
+    /// 每训练多少步保存一次训练检查点，为 0 时不保存。
+    #[clap(long, default_value_t = 50)]
+    save_every: usize,
+    /// 从上次保存的训练检查点继续训练。
+    #[clap(long)]
+    resume: bool,
+}
+
 struct EvaluateOpts<'a, B: AutodiffBackend> {
     model: GptModel<B>,
-    train_loader: &'a dyn DataLoader<B, Batch<B>>,
+    train_loader: &'a ResumableLoader<B, Batch<B>>,
     val_loader: &'a dyn DataLoader<B, Batch<B>>,
     device: &'a B::Device,
     eval_iter: usize,
@@ -99,7 +149,7 @@ where
     O: Optimizer<GptModel<B>, B>,
 {
     model: GptModel<B>,
-    train_loader: &'a dyn DataLoader<B, Batch<B>>,
+    train_loader: &'a ResumableLoader<B, Batch<B>>,
     val_loader: &'a dyn DataLoader<B, Batch<B>>,
     optimizer: O,
     device: &'a B::Device,
@@ -107,6 +157,8 @@ where
     eval_freq: usize,
     eval_iter: usize,
     lr: LearningRate,
+    checkpointer: &'a Checkpointer,
+    state: TrainState,
 }
 
 #[derive(Serialize)]
@@ -116,6 +168,7 @@ struct TrainOverview {
     train_accs: Vec<f32>,
     val_accs: Vec<f32>,
     examples_seen: usize,
+    steps: usize,
 }
 
 impl TrainOverview {
@@ -137,13 +190,15 @@ where
     let model = opts.model.no_grad();
 
     let num_batches = opts.eval_iter.into();
-    let train_loss = loss::calc_loss_loader(opts.train_loader, model.clone(), opts.device, num_batches);
+    let train_loss = opts
+        .train_loader
+        .eval(|v| loss::calc_loss_loader(v, model.clone(), opts.device, num_batches));
     let val_loss = loss::calc_loss_loader(opts.val_loader, model, opts.device, num_batches);
 
     (train_loss, val_loss)
 }
 
-fn train_classifer_simple<B, O>(opts: TrainOpts<'_, B, O>) -> (GptModel<B>, O, TrainOverview)
+fn train_classifer_simple<B, O>(opts: TrainOpts<'_, B, O>) -> anyhow::Result<(GptModel<B>, O, TrainOverview)>
 where
     B: AutodiffBackend<FloatElem = f32>,
     O: Optimizer<GptModel<B>, B>,
@@ -158,65 +213,64 @@ where
         eval_freq,
         eval_iter,
         lr,
+        checkpointer,
+        mut state,
     } = opts;
 
-    let mut train_losses = vec![];
-    let mut val_losses = vec![];
-    let mut train_accs = vec![];
-    let mut val_accs = vec![];
-
-    let mut examples_seen = 0;
-    let eval_freq = eval_freq as i32;
-    let mut global_step = -1i32;
-
-    for epoch in 1..=epoches {
-        for (input_batch, target_batch) in train_loader.iter() {
+    for epoch in state.epochs_done + 1..=epoches {
+        for (input_batch, target_batch) in train_loader.epoch(&mut state) {
             let loss = loss::calc_loss_batch(input_batch.clone(), target_batch, &model, device);
 
             let grads = GradientsParams::from_grads(loss.backward(), &model);
             // TODO: 更新学习率
             model = optimizer.step(lr, model, grads);
 
-            examples_seen += input_batch.dims()[0];
-            global_step += 1;
-
-            if global_step % eval_freq != 0 {
-                continue;
+            state.examples_seen += input_batch.dims()[0];
+            state.batches_done += 1;
+            state.step += 1;
+
+            let global_step = state.step - 1;
+            if global_step % eval_freq == 0 {
+                // 评估模型
+                let opts = EvaluateOpts {
+                    model: model.clone(),
+                    train_loader,
+                    val_loader,
+                    device,
+                    eval_iter,
+                };
+                let (train_loss, val_loss) = evaluate_model(opts);
+                state.train_losses.push(train_loss);
+                state.val_losses.push(val_loss);
+
+                println!("Ep {epoch} (Step: {global_step:06}): Train Loss: {train_loss:.3}, Val Loss: {val_loss:.3}");
             }
 
-            // 评估模型
-            let opts = EvaluateOpts {
-                model: model.clone(),
-                train_loader,
-                val_loader,
-                device,
-                eval_iter,
-            };
-            let (train_loss, val_loss) = evaluate_model(opts);
-            train_losses.push(train_loss);
-            val_losses.push(val_loss);
-
-            println!("Ep {epoch} (Step: {global_step:06}): Train Loss: {train_loss:.3}, Val Loss: {val_loss:.3}");
+            checkpointer
+                .on_step(&model, &optimizer, &mut state)
+                .context("save training checkpoint")?;
         }
 
-        let train_accuracy = loss::calc_accuracy_loader(train_loader, &model, device, Some(eval_iter));
+        let train_accuracy = train_loader.eval(|v| loss::calc_accuracy_loader(v, &model, device, Some(eval_iter)));
         let val_accuracy = loss::calc_accuracy_loader(val_loader, &model, device, Some(eval_iter));
         println!(
             "Training accuracy: {:.2}% | Validation accuracy: {:.2}%",
             train_accuracy * 100.0,
             val_accuracy * 100.0
         );
-        train_accs.push(train_accuracy);
-        val_accs.push(val_accuracy);
+        state.train_accs.push(train_accuracy);
+        state.val_accs.push(val_accuracy);
+        state.finish_epoch();
     }
 
     let overview = TrainOverview {
-        train_losses,
-        val_losses,
-        train_accs,
-        val_accs,
-        examples_seen,
+        train_losses: state.train_losses,
+        val_losses: state.val_losses,
+        train_accs: state.train_accs,
+        val_accs: state.val_accs,
+        examples_seen: state.examples_seen,
+        steps: state.step,
     };
 
-    (model, optimizer, overview)
+    Ok((model, optimizer, overview))
 }
@@ -1,14 +1,9 @@
-use std::path::Path;
-
 use anyhow::Context as _;
 use burn::backend::{Autodiff, LibTorch};
 use burn::module::Module;
 use burn::prelude::Backend;
-use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
-use chapter04::GPT_124M;
 use chapter06::dataset::{self, DataLoaderOptions, LoadCsvOptions, SpamDataset};
-use chapter06::loss;
-use tiktoken::ext::Encoding;
+use chapter06::{loss, utils};
 
 type B = Autodiff<LibTorch>;
 
@@ -16,24 +11,12 @@ type Device = <LibTorch as Backend>::Device;
 
 /// 依赖 060701 训练出的模型。
 fn main() -> anyhow::Result<()> {
-    let model_path = Path::new("spam-classifier-model.mpk");
-    if !model_path.exists() {
-        anyhow::bail!("model not exists");
-    }
-
     let device = &Device::Cpu;
 
-    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
-
-    // TODO：查明 with_qkv_bias 的具体作用。实验显示加了这个配置才能还原出和 060701 训练出的模型效果。
-    let model = GPT_124M
-        .with_qkv_bias(true)
-        .init::<B>(device)
-        .load_file("spam-classifier-model.mpk", &recorder, device)
-        .context("load model")?;
+    let (model, meta) = utils::load_classifier::<B>(device).context("load model")?;
     let model = model.no_grad();
 
-    let tokenizer = Encoding::gpt2();
+    let tokenizer = meta.tokenizer.load().context("load tokenizer")?;
 
     let opts = LoadCsvOptions::new("train.csv", &tokenizer, device);
     let train_dataset = SpamDataset::<B>::load_csv(opts).context("load train dataset")?;
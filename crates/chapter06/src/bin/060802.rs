@@ -1,14 +1,12 @@
-use std::path::Path;
-
 use anyhow::Context as _;
 use burn::backend::{Autodiff, LibTorch};
 use burn::module::{AutodiffModule, Module};
 use burn::prelude::Backend;
-use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
 use burn::tensor::backend::AutodiffBackend;
 use burn::tensor::{Int, Tensor, s};
-use chapter04::{GPT_124M, GptModel};
+use chapter04::GptModel;
 use chapter06::dataset::{LoadCsvOptions, SpamDataset};
+use chapter06::utils;
 use tiktoken::ext::Encoding;
 
 type B = Autodiff<LibTorch>;
@@ -17,23 +15,12 @@ type Device = <LibTorch as Backend>::Device;
 
 /// 依赖 060701 训练出的模型。
 fn main() -> anyhow::Result<()> {
-    let model_path = Path::new("spam-classifier-model.mpk");
-    if !model_path.exists() {
-        anyhow::bail!("model not exists");
-    }
-
     let device = &Device::Cpu;
 
-    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
-
-    let model = GPT_124M
-        .with_qkv_bias(true)
-        .init::<B>(device)
-        .load_file("spam-classifier-model.mpk", &recorder, device)
-        .context("load model")?;
+    let (model, meta) = utils::load_classifier::<B>(device).context("load model")?;
     let model = model.no_grad();
 
-    let tokenizer = Encoding::gpt2();
+    let tokenizer = meta.tokenizer.load().context("load tokenizer")?;
 
     let opts = LoadCsvOptions::new("train.csv", &tokenizer, device);
     let train_dataset = SpamDataset::<B>::load_csv(opts).context("load train dataset")?;
@@ -43,7 +30,14 @@ fn main() -> anyhow::Result<()> {
         "Hey, just wanted to check if we're still on",
         " for dinner tonight? Let me know!"
     );
-    let out = classify_review(TEXT_2, model.clone(), &tokenizer, device, max_length.into(), None);
+    let out = classify_review(
+        TEXT_2,
+        model.clone(),
+        &tokenizer,
+        device,
+        max_length.into(),
+        meta.tokenizer.pad_id,
+    );
     println!("{out}");
 
     Ok(())
@@ -1,3 +1,6 @@
 pub mod dataset;
 pub mod loss;
 pub mod utils;
+
+/// 060701 保存的垃圾短信分类模型的检查点目录，参见 [`chapter05::checkpoint`]。
+pub const CLASSIFIER_CHECKPOINT: &str = "spam-classifier-model";
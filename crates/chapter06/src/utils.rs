@@ -6,12 +6,16 @@ use burn::module::{Module as _, ModuleMapper, ParamId, Parameter as _};
 use burn::nn::LinearConfig;
 use burn::prelude::Backend;
 use burn::tensor::{Bool, Int, Tensor};
-use chapter04::{GPT_124M, GptModel};
+use chapter04::{Config, GPT_124M, GptModel};
+use chapter05::checkpoint::{self, CheckpointMeta, Head};
 use chapter05::gpt2;
 use polars::frame::DataFrame;
 use polars::io::SerReader as _;
 use polars::prelude::{CsvReadOptions, DataType, Schema};
 
+/// 分类模型的类别数：垃圾短信和正常短信。
+pub const NUM_CLASSES: usize = 2;
+
 pub struct RequireGradMapper;
 
 impl<B: Backend> ModuleMapper<B> for RequireGradMapper {
@@ -44,23 +48,28 @@ pub fn load_csv(path: &str) -> anyhow::Result<DataFrame> {
     Ok(df)
 }
 
-pub fn load_gpt2<B: Backend, P: AsRef<Path>>(param_dir: P, device: &B::Device) -> anyhow::Result<GptModel<B>> {
-    let (settings, params) = {
-        let (mut s, p) = gpt2::load_settings_and_params(param_dir.as_ref()).expect("load config");
-        s.drop_rate = 0.0;
-        (s, p)
+/// 加载 060701 保存的分类模型 [`crate::CLASSIFIER_CHECKPOINT`]，也兼容旧版本保存的 `spam-classifier-model.mpk`。
+pub fn load_classifier<B: Backend>(device: &B::Device) -> anyhow::Result<(GptModel<B>, CheckpointMeta)> {
+    // 旧版本没有描述文件，按 060701 微调时的结构重建：GPT-2 124M，QKV 带偏置，输出层为带偏置的分类层
+    let legacy = || {
+        let config = GPT_124M.clone().with_qkv_bias(true).with_drop_rate(0.0);
+        CheckpointMeta::new(config).with_head(Head::Classifier {
+            num_classes: NUM_CLASSES,
+        })
     };
+    checkpoint::load_checkpoint_or_mpk(Path::new(crate::CLASSIFIER_CHECKPOINT), legacy, device)
+}
 
-    let mut model = GPT_124M
-        .with_context_length(settings.context_length)
-        .with_qkv_bias(true)
-        .init::<B>(device);
+/// 读取 `param_dir` 中 GPT-2 的超参数，和 [`load_gpt2`] 一样关闭 dropout。
+pub fn load_gpt2_config<P: AsRef<Path>>(param_dir: P) -> anyhow::Result<Config> {
+    let settings = gpt2::load_hparams(param_dir.as_ref()).context("load config")?;
+    Ok(settings.with_drop_rate(0.0))
+}
 
-    // println!("e1: {}", settings.emb_dim);
-    // println!("e2: {:?}", model.tok_emb.weight.dims());
+pub fn load_gpt2<B: Backend, P: AsRef<Path>>(param_dir: P, device: &B::Device) -> anyhow::Result<GptModel<B>> {
+    let (settings, params) = gpt2::load_settings_and_params(param_dir.as_ref()).context("load config")?;
 
-    // println!("e1: {}", settings.context_length);
-    // println!("e2: {:?}", model.pos_emb.weight.dims());
+    let mut model = settings.with_drop_rate(0.0).init::<B>(device);
 
     gpt2::load_weights_into_gpt2(params, &mut model).context("load weights into model")?;
 
@@ -76,12 +85,19 @@ pub fn load_gpt2_for_fine_tuning<B: Backend, P: AsRef<Path>>(
     // 指定随机种子，确保 model.out_head 一样。
     B::seed(123);
 
-    let mut model = model.no_grad();
-
-    const NUM_CLASSES: usize = 2;
+    let mut model = model;
     let emb_dim = model.tok_emb.weight.dims()[1];
     model.out_head = LinearConfig::new(emb_dim, NUM_CLASSES).with_bias(true).init(device);
 
+    freeze_for_fine_tuning(model)
+}
+
+/// 只训练输出层、最后一个 transformer 块和最后的层归一化，其余参数不计算梯度。从检查点恢复微调时也要调用。
+pub fn freeze_for_fine_tuning<B: Backend>(model: GptModel<B>) -> anyhow::Result<GptModel<B>> {
+    let mut model = model.no_grad();
+
+    model.out_head = model.out_head.clone().map(&mut RequireGradMapper);
+
     let trf_block = model.trf_blocks.last_mut().context("miss last transfomer block")?;
     *trf_block = trf_block.clone().map(&mut RequireGradMapper);
 
@@ -1,12 +1,14 @@
-use std::path::Path;
+use std::io::{self, Write as _};
 
 use anyhow::Context as _;
 use burn::backend::{Autodiff, LibTorch};
 use burn::prelude::Backend;
-use chapter04::GptModel;
+use chapter04::Preset;
 use chapter05::gpt2;
-use chapter05::utils::Tokenizer;
+use chapter05::stream::Generator;
+use chapter05::utils::GenerateOptions;
 use chapter07::utils;
+use clap::Parser;
 use tiktoken::ext::Encoding;
 
 type B = Autodiff<LibTorch>;
@@ -15,16 +17,15 @@ type Device = <LibTorch as Backend>::Device;
 
 /// 需要先进去 gpt2 运行 uv run main.py 准备好数据。
 fn main() -> anyhow::Result<()> {
+    let Cli { preset } = Cli::parse();
+
     let device = &Device::Cuda(0);
 
-    let data_dir = &Path::new("gpt2/gpt2/355M");
-    let (settings, params) = {
-        let (mut s, p) = gpt2::load_settings_and_params(&data_dir).expect("load gpt2 config");
-        s.drop_rate = 0.0;
-        (s, p)
-    };
+    let data_dir = utils::gpt2_param_dir(preset)?;
+    let (settings, params) = gpt2::load_settings_and_params(&data_dir).context("load gpt2 config")?;
 
-    let mut model = GptModel::<B>::new(&settings, device);
+    let settings = settings.with_drop_rate(0.0);
+    let mut model = settings.init::<B>(device);
 
     gpt2::load_weights_into_gpt2(params, &mut model).context("load weights into model")?;
 
@@ -36,20 +37,31 @@ fn main() -> anyhow::Result<()> {
     let input_text = utils::format_input(&val_data[0]);
     println!("Input text: {input_text}");
 
-    let idx = tokenizer.tokenize(&input_text).to_device(device);
-    let token_ids = chapter05::utils::generate(
-        &model,
-        idx,
-        35,
-        settings.context_length,
-        None,
-        None,
-        Some(chapter07::PAD_TOKEN_ID as usize),
-    );
-
-    let generated_text = tokenizer.detokenize(token_ids).context("decode output")?;
-    let response_text = generated_text.split_at(input_text.len()).1.trim();
-    println!("\n\nResponse text:\n{response_text}");
+    let opts = GenerateOptions::new(35, settings.context_length).with_eos_id(Some(chapter07::PAD_TOKEN_ID as usize));
+
+    // 边生成边输出回复，略去开头的空白
+    print!("\n\nResponse text:\n");
+    let mut started = false;
+    for token in Generator::new(&model, &tokenizer, &input_text, opts, device).context("create generator")? {
+        let token = token?;
+        let text = if started {
+            token.text.as_str()
+        } else {
+            token.text.trim_start()
+        };
+        started |= !text.is_empty();
+
+        print!("{text}");
+        io::stdout().flush().context("flush stdout")?;
+    }
+    println!();
 
     Ok(())
 }
+
+#[derive(Parser)]
+struct Cli {
+    /// 预设的模型规模。
+    #[clap(long, default_value_t = Preset::Gpt2Medium)]
+    preset: Preset,
+}
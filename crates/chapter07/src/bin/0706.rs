@@ -3,36 +3,64 @@ use std::time::Instant;
 
 use anyhow::Context;
 use burn::LearningRate;
+use burn::backend::libtorch::LibTorchDevice;
 use burn::backend::{Autodiff, LibTorch};
 use burn::data::dataloader::DataLoader;
 use burn::module::{AutodiffModule, Module};
 use burn::optim::{AdamWConfig, GradientsParams, Optimizer};
-use burn::prelude::Backend;
-use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
 use burn::tensor::backend::AutodiffBackend;
-use chapter04::GptModel;
+use chapter04::checkpoint::BalancedCheckpointing;
+use chapter04::{GptModel, Preset};
+use chapter05::checkpoint::{self, CheckpointMeta, TrainingSummary};
 use chapter05::gpt2;
+use chapter05::resume::{Checkpointer, ResumableLoader, TrainState};
 use chapter05::utils::Tokenizer;
 use chapter07::dataset::Batch;
 use chapter07::{loss, utils};
+use clap::Parser;
 use tiktoken::ext::Encoding;
 
-type B = Autodiff<LibTorch>;
-
-type Device = <LibTorch as Backend>::Device;
+/// 训练检查点目录，中断后使用 `--resume` 继续训练。
+const TRAIN_CHECKPOINT: &str = "gpt-model-sft-train-ckpt";
 
 /// 需要先进去 gpt2 运行 uv run main.py 准备好数据。
 fn main() -> anyhow::Result<()> {
-    let device = &Device::Cpu;
+    let cli = Cli::parse();
 
-    let data_dir = &Path::new("gpt2/gpt2/355M");
-    let (settings, params) = {
-        let (mut s, p) = gpt2::load_settings_and_params(&data_dir).expect("load gpt2 config");
-        s.drop_rate = 0.0;
-        (s, p)
-    };
+    if cli.checkpointing {
+        run::<Autodiff<LibTorch, BalancedCheckpointing>>(&cli)
+    } else {
+        run::<Autodiff<LibTorch>>(&cli)
+    }
+}
+
+#[derive(Parser)]
+struct Cli {
+    /// 预设的模型规模。
+    #[clap(long, default_value_t = Preset::Gpt2Medium)]
+    preset: Preset,
+    /// 反向传播时重新计算 transformer 块的激活值，以训练时间换取内存。
+    #[clap(long)]
+    checkpointing: bool,
+    /// 每训练多少步保存一次训练检查点，为 0 时不保存。
+    #[clap(long, default_value_t = 20)]
+    save_every: usize,
+    /// 从上次保存的训练检查点继续训练。
+    #[clap(long)]
+    resume: bool,
+}
+
+fn run<B>(cli: &Cli) -> anyhow::Result<()>
+where
+    B: AutodiffBackend<FloatElem = f32, Device = LibTorchDevice>,
+{
+    let device = &LibTorchDevice::Cpu;
+
+    let data_dir = utils::gpt2_param_dir(cli.preset)?;
+    let (settings, params) = gpt2::load_settings_and_params(&data_dir).context("load gpt2 config")?;
 
-    let mut model = GptModel::<B>::new(&settings, device);
+    let settings = settings.with_drop_rate(0.0);
+    let mut model = settings.init::<B>(device);
 
     gpt2::load_weights_into_gpt2(params, &mut model).context("load weights into model")?;
 
@@ -49,9 +77,10 @@ fn main() -> anyhow::Result<()> {
     let (train_loader, _test_loader, val_loader) =
         chapter07::dataset::load_and_split("instruction-data.json", &tokenizer)
             .context("load and split data loader")?;
+    let train_loader = ResumableLoader::new(train_loader);
 
     let train_loss =
-        chapter07::loss::calc_loss_loader(train_loader.as_ref(), &model.clone().no_grad(), 5.into(), device);
+        train_loader.eval(|v| chapter07::loss::calc_loss_loader(v, &model.clone().no_grad(), 5.into(), device));
     let val_loss = chapter07::loss::calc_loss_loader(val_loader.as_ref(), &model.clone().no_grad(), 5.into(), device);
 
     println!("Training loss: {}", train_loss);
@@ -63,30 +92,53 @@ fn main() -> anyhow::Result<()> {
     // TODO：查明在哪里关闭了梯度传递
     let model = model.map(&mut chapter06::utils::RequireGradMapper);
 
+    const EPOCHES: usize = 2;
+    const LR: LearningRate = 0.00005;
     let optimizer = AdamWConfig::new().with_weight_decay(0.1).init::<B, GptModel<B>>();
+
+    let checkpointer = Checkpointer::new(TRAIN_CHECKPOINT, CheckpointMeta::new(settings), cli.save_every);
+    let (model, optimizer, state) = if cli.resume {
+        checkpointer
+            .resume::<B, _>(optimizer, device)
+            .with_context(|| format!("resume from {TRAIN_CHECKPOINT}"))?
+    } else {
+        (model, optimizer, TrainState::new(123))
+    };
+
     let opts = TrainOpts {
         model,
-        train_loader: train_loader.as_ref(),
+        train_loader: &train_loader,
         val_loader: val_loader.as_ref(),
         optimizer,
         device: &device,
-        epoches: 2,
+        epoches: EPOCHES,
         eval_freq: 5,
         eval_iter: 5,
         start_context: &start_ctx,
         tokenizer: &tokenizer,
-        lr: 0.00005,
+        lr: LR,
+        checkpointer: &checkpointer,
+        state,
     };
 
-    let (model, ..) = train_model_simple(opts);
-
-    println!("Training completed in {:?}", start.elapsed());
-
-    // 保存模型用于后续教程
-    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
-
-    const MODEL_PATH: &str = "gpt-355m-model-sft";
-    model.save_file(MODEL_PATH, &recorder).expect("save model");
+    let (model, _, state) = train_model_simple(opts)?;
+
+    let elapsed = start.elapsed();
+    println!("Training completed in {elapsed:?}");
+
+    // 保存模型及其配置用于后续教程
+    let training = TrainingSummary {
+        epochs: EPOCHES,
+        steps: state.step,
+        tokens_seen: Some(state.tokens_seen),
+        learning_rate: Some(LR),
+        train_loss: state.train_losses.last().copied(),
+        val_loss: state.val_losses.last().copied(),
+        elapsed_secs: Some(elapsed.as_secs_f64()),
+        ..Default::default()
+    };
+    let meta = CheckpointMeta::new(settings).with_training(Some(training));
+    checkpoint::save_checkpoint(&model, &meta, Path::new(chapter07::SFT_CHECKPOINT)).context("save model")?;
 
     Ok(())
 }
@@ -98,7 +150,7 @@ where
     T: Tokenizer<B::InnerBackend>,
 {
     model: GptModel<B>,
-    train_loader: &'a dyn DataLoader<B, Batch<B>>,
+    train_loader: &'a ResumableLoader<B, Batch<B>>,
     val_loader: &'a dyn DataLoader<B, Batch<B>>,
     optimizer: O,
     device: &'a B::Device,
@@ -108,11 +160,13 @@ where
     start_context: &'a str,
     tokenizer: &'a T,
     lr: LearningRate,
+    checkpointer: &'a Checkpointer,
+    state: TrainState,
 }
 
 struct EvaluateOpts<'a, B: AutodiffBackend> {
     model: GptModel<B>,
-    train_loader: &'a dyn DataLoader<B, Batch<B>>,
+    train_loader: &'a ResumableLoader<B, Batch<B>>,
     val_loader: &'a dyn DataLoader<B, Batch<B>>,
     device: &'a B::Device,
     eval_iter: usize,
@@ -123,7 +177,9 @@ where
     B: AutodiffBackend<FloatElem = f32>,
 {
     let model = opts.model.no_grad();
-    let train_loss = loss::calc_loss_loader(opts.train_loader, &model, opts.eval_iter.into(), opts.device);
+    let train_loss = opts
+        .train_loader
+        .eval(|v| loss::calc_loss_loader(v, &model, opts.eval_iter.into(), opts.device));
     let val_loss = loss::calc_loss_loader(opts.val_loader, &model, opts.eval_iter.into(), opts.device);
 
     (train_loss, val_loss)
@@ -143,7 +199,7 @@ where
     println!("{}", decoded_text.replace('\n', " "));
 }
 
-fn train_model_simple<B, O, T>(opts: TrainOpts<'_, B, O, T>) -> (GptModel<B>, O, Vec<f32>, Vec<f32>, Vec<usize>)
+fn train_model_simple<B, O, T>(opts: TrainOpts<'_, B, O, T>) -> anyhow::Result<(GptModel<B>, O, TrainState)>
 where
     B: AutodiffBackend<FloatElem = f32>,
     O: Optimizer<GptModel<B>, B>,
@@ -161,48 +217,47 @@ where
         start_context,
         tokenizer,
         lr,
+        checkpointer,
+        mut state,
     } = opts;
 
-    let mut train_losses = vec![];
-    let mut val_losses = vec![];
-    let mut track_tokens_seen = vec![];
-
-    let mut token_seen = 0;
-    let eval_freq = eval_freq as i32;
-    let mut global_step = -1i32;
-
-    for epoch in 1..=epoches {
-        for (input_batch, target_batch) in train_loader.iter() {
+    for epoch in state.epochs_done + 1..=epoches {
+        for (input_batch, target_batch) in train_loader.epoch(&mut state) {
             let loss = loss::calc_loss_batch(input_batch.clone(), target_batch, &model, device);
 
             let grads = GradientsParams::from_grads(loss.backward(), &model);
             // TODO: 更新学习率
             model = optimizer.step(lr, model, grads);
 
-            token_seen += input_batch.shape().num_elements();
-            global_step += 1;
-
-            if global_step % eval_freq != 0 {
-                continue;
+            state.tokens_seen += input_batch.shape().num_elements();
+            state.batches_done += 1;
+            state.step += 1;
+
+            let global_step = state.step - 1;
+            if global_step % eval_freq == 0 {
+                // 评估模型
+                let opts = EvaluateOpts {
+                    model: model.clone(),
+                    train_loader,
+                    val_loader,
+                    device,
+                    eval_iter,
+                };
+                let (train_loss, val_loss) = evaluate_model(opts);
+                state.train_losses.push(train_loss);
+                state.val_losses.push(val_loss);
+                state.track_tokens_seen.push(state.tokens_seen);
+                println!("Ep {epoch} (Step: {global_step:06}): Train Loss: {train_loss:.3}, Val Loss: {val_loss:.3}");
             }
 
-            // 评估模型
-            let opts = EvaluateOpts {
-                model: model.clone(),
-                train_loader,
-                val_loader,
-                device,
-                eval_iter,
-            };
-            let (train_loss, val_loss) = evaluate_model(opts);
-            train_losses.push(train_loss);
-            val_losses.push(val_loss);
-            track_tokens_seen.push(token_seen);
-            println!("Ep {epoch} (Step: {global_step:06}): Train Loss: {train_loss:.3}, Val Loss: {val_loss:.3}");
+            checkpointer
+                .on_step(&model, &optimizer, &mut state)
+                .context("save training checkpoint")?;
         }
 
         generate_and_print_sample(model.clone(), tokenizer, device, start_context);
+        state.finish_epoch();
     }
 
-    (model, optimizer, train_losses, val_losses, track_tokens_seen)
+    Ok((model, optimizer, state))
 }
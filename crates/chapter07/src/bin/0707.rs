@@ -1,101 +1,107 @@
+use std::collections::HashSet;
 use std::fs::File;
 use std::path::Path;
 
 use anyhow::Context as _;
 use burn::backend::LibTorch;
-use burn::module::Module;
 use burn::prelude::Backend;
-use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
-use chapter04::GptModel;
-use chapter05::gpt2;
-use chapter05::utils::Tokenizer;
+use chapter04::Preset;
+use chapter05::beam::{self, BeamSearchOptions};
+use chapter05::checkpoint::{self, CheckpointMeta};
+use chapter05::stopping;
+use chapter05::utils::GenerateOptions;
 use chapter07::utils::{self, DataWithModelResponse};
+use clap::Parser;
 use indicatif::ProgressBar;
-use tiktoken::ext::Encoding;
 
 type B = LibTorch;
 
+const BATCH_SIZE: usize = 8;
+
 type Device = <B as Backend>::Device;
 
-/// 需要先进去 gpt2 运行 uv run main.py 准备好数据。
+/// 依赖 0706 微调出的模型。
 fn main() -> anyhow::Result<()> {
-    let model_path = Path::new("gpt-355m-model-sft.mpk");
-    if !model_path.exists() {
-        anyhow::bail!("model not exists");
-    }
+    let Cli {
+        preset,
+        repetition_penalty,
+        num_beams,
+    } = Cli::parse();
 
     let device = &Device::Cpu;
 
-    let data_dir = &Path::new("gpt2/gpt2/355M");
-    let settings = {
-        let (mut s, _) = gpt2::load_settings_and_params(&data_dir).expect("load gpt2 config");
-        s.drop_rate = 0.0;
-        s
-    };
-
-    let model = GptModel::<B>::new(&settings, device);
-
-    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
-    let model = &model.load_file(model_path, &recorder, device).context("load model")?;
+    // 旧版本只保存了 gpt-355m-model-sft.mpk，按 --preset 重建微调时的配置
+    let legacy = || CheckpointMeta::new(preset.config().with_drop_rate(0.0));
+    let (model, meta) = checkpoint::load_checkpoint_or_mpk::<B>(Path::new(chapter07::SFT_CHECKPOINT), legacy, device)
+        .context("load model")?;
+    let model = &model;
+    let eos_id = meta.tokenizer.eos_id.unwrap_or(chapter07::PAD_TOKEN_ID);
 
     B::seed(123);
-    let tokenizer = Encoding::gpt2();
+    let tokenizer = meta.tokenizer.load().context("load tokenizer")?;
 
     let (_, test_data, _) = utils::load_and_split_data("instruction-data.json").context("load and split data")?;
 
-    let pad_token_id = Some(chapter07::PAD_TOKEN_ID as usize);
-    for (i, entry) in test_data.iter().enumerate().take(3) {
-        let input_text = utils::format_input(entry);
-
-        let idx = tokenizer.tokenize(&input_text).to_device(device);
-
-        let token_ids = chapter05::utils::generate(model, idx, 256, settings.context_length, None, None, pad_token_id);
-
-        let generated_text = tokenizer
-            .detokenize(token_ids)
-            .with_context(|| format!("detokenize {i}-th output"))?;
-
-        let response_text = generated_text
-            .split_at(input_text.len())
-            .1
-            .trim()
-            .replace("### Response:", "");
-
-        println!("{input_text}");
+    let opts = GenerateOptions::new(256, meta.config.context_length)
+        .with_eos_id(Some(eos_id as usize))
+        .with_stop_sequences(utils::STOP_SEQUENCES.map(String::from).to_vec())
+        .with_repetition_penalty(repetition_penalty);
+
+    let samples = &test_data[..test_data.len().min(3)];
+    let prompts: Vec<_> = samples.iter().map(utils::format_prompt).collect();
+    let prompts: Vec<_> = prompts.iter().map(String::as_str).collect();
+    let responses = chapter05::utils::generate_text(model, &tokenizer, &prompts, &opts, device)
+        .context("generate sample responses")?;
+    for (entry, response_text) in samples.iter().zip(responses) {
+        println!("{}", utils::format_input(entry));
         println!("\nCorrect Response:\n>> {}", entry.output);
-        // 再次 trim 是因为 "### Response:" 后紧跟了一个换行符
         println!("\nModel Response:\n>> {}", response_text.trim());
         println!("--------------------------------------");
     }
 
     // Listing 7.9 Generating test set responses
+    // 按批次生成，以减少前向计算的次数
+    let allowed_specials = HashSet::from(["<|endoftext|>"]);
     let p = ProgressBar::new(test_data.len() as u64);
     let mut out = Vec::with_capacity(test_data.len());
-    for (i, entry) in test_data.into_iter().enumerate() {
-        let input_text = utils::format_input(&entry);
-
-        let idx = tokenizer.tokenize(&input_text).to_device(device);
-
-        let token_ids = chapter05::utils::generate(model, idx, 256, settings.context_length, None, None, pad_token_id);
-
-        let generated_text = tokenizer
-            .detokenize(token_ids)
-            .with_context(|| format!("detokenize {i}-th output"))?;
-
-        let response_text = generated_text
-            .split_at(input_text.len())
-            .1
-            .replace("### Response:", "")
-            .trim()
-            .to_owned();
-
-        let o = DataWithModelResponse {
-            data: entry,
-            model_response: response_text,
+    for (i, batch) in test_data.chunks(BATCH_SIZE).enumerate() {
+        let prompts: Vec<_> = batch.iter().map(utils::format_prompt).collect();
+
+        let responses = match num_beams {
+            Some(n) => {
+                let beam_opts = BeamSearchOptions::new(opts.max_new_tokens, opts.context_size)
+                    .with_num_beams(n)
+                    .with_eos_id(Some(eos_id));
+                prompts
+                    .iter()
+                    .enumerate()
+                    .map(|(j, p)| {
+                        let p = tokenizer.encode(p, &allowed_specials);
+                        let best = beam::beam_search(model, &p, &beam_opts, device).swap_remove(0);
+                        let text = tokenizer
+                            .decode(&best.tokens)
+                            .with_context(|| format!("detokenize {}-th output", i * BATCH_SIZE + j))?;
+                        let text = String::from_utf8_lossy(&text);
+                        Ok(stopping::trim_stop_sequences(&text, &opts.stop_sequences).to_owned())
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?
+            }
+            None => {
+                let prompts: Vec<_> = prompts.iter().map(String::as_str).collect();
+                chapter05::utils::generate_text(model, &tokenizer, &prompts, &opts, device)
+                    .with_context(|| format!("generate {i}-th batch"))?
+            }
         };
-        out.push(o);
 
-        p.inc(1);
+        for (entry, response_text) in batch.iter().zip(responses) {
+            let o = DataWithModelResponse {
+                data: entry.clone(),
+                model_response: response_text.trim().to_owned(),
+            };
+            out.push(o);
+        }
+
+        p.inc(batch.len() as u64);
     }
     p.finish();
 
@@ -107,3 +113,16 @@ fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[derive(Parser)]
+struct Cli {
+    /// 加载旧版本保存的 .mpk 时使用的预设模型规模，检查点目录自带配置，不需要设置。
+    #[clap(long, default_value_t = Preset::Gpt2Medium)]
+    preset: Preset,
+    /// 重复惩罚系数，大于 1 时可以缓解模型反复输出相同内容的问题。
+    #[clap(long, default_value_t = 1.0)]
+    repetition_penalty: f32,
+    /// 设置时使用该束宽的束搜索生成测试集的回复，结果是确定的。
+    #[clap(long)]
+    num_beams: Option<usize>,
+}
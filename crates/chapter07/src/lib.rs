@@ -5,3 +5,6 @@ pub mod ollama;
 pub mod utils;
 
 pub const PAD_TOKEN_ID: u32 = 50256;
+
+/// 0706 保存的指令微调模型的检查点目录，参见 [`chapter05::checkpoint`]。
+pub const SFT_CHECKPOINT: &str = "gpt-355m-model-sft";
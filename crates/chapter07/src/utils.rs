@@ -1,11 +1,12 @@
 use std::fmt::Display;
 use std::fs::File;
 use std::ops::Deref;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context as _;
 use burn::prelude::Backend;
 use burn::tensor::{Int, Tensor};
+use chapter04::Preset;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
@@ -102,6 +103,26 @@ pub fn format_input(entry: &Data) -> String {
     instruction_text + &input_text
 }
 
+/// 回复的标题，训练数据中位于 [`format_input`] 的输出和回复之间。
+pub const RESPONSE_HEADER: &str = "\n\n### Response:\n";
+
+/// 生成回复时的停止字符串，模型开始生成下一个段落时停止。
+pub const STOP_SEQUENCES: [&str; 3] = ["\n\n### Instruction:", "\n\n### Input:", "\n\n### Response:"];
+
+/// 生成回复时的输入：在 [`format_input`] 的输出之后加上回复的标题，模型直接生成回复的内容。
+pub fn format_prompt(entry: &Data) -> String {
+    format_input(entry) + RESPONSE_HEADER
+}
+
+/// 预设模型的预训练参数目录，需要先进去 gpt2 运行 uv run main.py 准备好数据。
+pub fn gpt2_param_dir(preset: Preset) -> anyhow::Result<PathBuf> {
+    let size = preset
+        .size()
+        .with_context(|| format!("preset '{preset}' has no pretrained params"))?;
+
+    Ok(Path::new("gpt2/gpt2").join(size))
+}
+
 pub fn load_json<P, T>(file_path: P) -> anyhow::Result<Vec<T>>
 where
     P: AsRef<Path>,